@@ -0,0 +1,242 @@
+/// Delta Modulation Channel ($4010-$4013)
+/// ======================================
+/// Plays 1 bit delta encoded samples straight out of CPU memory, each bit
+/// moving a 7 bit output level up or down by 2. The memory reader fetches
+/// a byte whenever the sample buffer is empty, halting the CPU while it
+/// does, and can raise an IRQ when the sample ends.
+///
+/// Register | Bits      | Contents
+/// $4010    | IL-- RRRR | IRQ enable, loop, rate index
+/// $4011    | -DDD DDDD | Output level, loaded directly
+/// $4012    | AAAA AAAA | Sample address, $C000 + A * 64
+/// $4013    | LLLL LLLL | Sample length, L * 16 + 1 bytes
+///
+/// Rates are in CPU cycles (NTSC).
+const RATES: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+pub(super) struct Dmc {
+    irq_enabled: bool,
+    looping: bool,
+    pub(super) irq: bool,
+    timer_period: u16,
+    timer: u16,
+    output_level: u8,
+
+    // Memory reader
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+
+    // Output unit
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Dmc {
+            irq_enabled: false,
+            looping: false,
+            irq: false,
+            timer_period: RATES[0],
+            timer: 0,
+            output_level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+        }
+    }
+}
+
+impl Dmc {
+    pub(super) fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.irq_enabled = data & 0b1000_0000 != 0;
+                self.looping = data & 0b0100_0000 != 0;
+                self.timer_period = RATES[(data & 0b1111) as usize];
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            }
+            1 => self.output_level = data & 0b0111_1111,
+            2 => self.sample_address = 0xC000 | ((data as u16) << 6),
+            3 => self.sample_length = ((data as u16) << 4) | 1,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Enabling restarts the sample only if the last one has finished,
+    /// disabling drops whatever is left of it
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    pub(super) fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// The address the memory reader wants to fetch, if the buffer is empty
+    /// and there are bytes left in the sample
+    pub(super) fn sample_request(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    /// Completes a fetch started by `sample_request`. The address wraps
+    /// from $FFFF round to $8000.
+    pub(super) fn fill_sample_buffer(&mut self, data: u8) {
+        self.sample_buffer = Some(data);
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period - 1;
+
+        if !self.silence {
+            if self.shift_register & 1 == 1 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(data) => {
+                    self.silence = false;
+                    self.shift_register = data;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        self.output_level
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Runs the memory reader and output unit against a sample, with every
+    /// fetch served from `memory` starting at $C000
+    fn play(dmc: &mut Dmc, memory: &[u8], cycles: usize) -> usize {
+        let mut fetches = 0;
+        for _ in 0..cycles {
+            if let Some(address) = dmc.sample_request() {
+                dmc.fill_sample_buffer(memory[(address - 0xC000) as usize]);
+                fetches += 1;
+            }
+            dmc.clock_timer();
+        }
+        fetches
+    }
+
+    #[test]
+    fn test_sample_address_and_length() {
+        let mut dmc = Dmc::default();
+        dmc.write(2, 0x01);
+        dmc.write(3, 0x01);
+        dmc.set_enabled(true);
+
+        assert_eq!(dmc.sample_request(), Some(0xC040));
+        assert_eq!(dmc.bytes_remaining, 17);
+    }
+
+    #[test]
+    fn test_delta_output() {
+        let mut dmc = Dmc::default();
+        // Fastest rate, one byte
+        dmc.write(0, 0x0f);
+        dmc.write(1, 0x40);
+        dmc.set_enabled(true);
+        play(&mut dmc, &[0b0000_0111], 54 * 8);
+
+        // The first byte only starts once the empty output cycle ends
+        assert_eq!(dmc.output(), 0x40);
+        play(&mut dmc, &[], 54 * 8);
+        assert_eq!(dmc.output(), 0x40 + 3 * 2 - 5 * 2);
+    }
+
+    #[test]
+    fn test_output_level_clamps() {
+        let mut dmc = Dmc::default();
+        dmc.write(0, 0x0f);
+        dmc.write(1, 0x7f);
+        dmc.set_enabled(true);
+        play(&mut dmc, &[0xff], 54 * 16);
+
+        assert_eq!(dmc.output(), 0x7f);
+    }
+
+    #[test]
+    fn test_irq_at_end_of_sample() {
+        let mut dmc = Dmc::default();
+        dmc.write(0, 0x8f);
+        dmc.write(3, 0x01);
+        dmc.set_enabled(true);
+        let fetches = play(&mut dmc, &[0; 17], 54 * 8 * 17);
+
+        assert_eq!(fetches, 17);
+        assert!(dmc.irq);
+        assert_eq!(dmc.bytes_remaining, 0);
+
+        dmc.write(0, 0x0f);
+        assert!(!dmc.irq);
+    }
+
+    #[test]
+    fn test_looping_sample_restarts() {
+        let mut dmc = Dmc::default();
+        dmc.write(0, 0xcf);
+        dmc.set_enabled(true);
+        let fetches = play(&mut dmc, &[0], 54 * 8 * 4);
+
+        assert!(fetches >= 4);
+        assert!(dmc.bytes_remaining > 0);
+        assert!(!dmc.irq);
+    }
+}
@@ -0,0 +1,216 @@
+/// Frame Counter ($4017)
+/// =====================
+/// A divider that clocks the envelopes and triangle linear counter every
+/// quarter frame and the length counters and sweeps every half frame. It
+/// runs in one of two sequences, timed in CPU cycles (NTSC):
+///
+/// Cycle | 4-step          | 5-step
+/// 7457  | Quarter         | Quarter
+/// 14913 | Quarter, half   | Quarter, half
+/// 22371 | Quarter         | Quarter
+/// 29828 | IRQ             |
+/// 29829 | Quarter, half,  |
+///       | IRQ             |
+/// 29830 | IRQ, restart    |
+/// 37281 |                 | Quarter, half
+/// 37282 |                 | Restart
+///
+/// 7  bit  0
+/// ---- ----
+/// MI-- ----
+/// ||
+/// |+------- IRQ inhibit, which also clears the frame interrupt flag
+/// +-------- Sequence (0: 4-step; 1: 5-step)
+///
+/// The sequence restarts 3 or 4 CPU cycles after a write, depending on
+/// whether it lands on an APU cycle. Restarting in 5-step mode clocks both
+/// the quarter and half frame units straight away.
+const QUARTER: u32 = 7457;
+const HALF: u32 = 14913;
+const THREE_QUARTERS: u32 = 22371;
+const FOUR_STEP_IRQ: u32 = 29828;
+const FOUR_STEP_END: u32 = 29829;
+const FOUR_STEP_RESTART: u32 = 29830;
+const FIVE_STEP_END: u32 = 37281;
+const FIVE_STEP_RESTART: u32 = 37282;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) struct FrameClock {
+    pub(super) quarter: bool,
+    pub(super) half: bool,
+}
+
+const NONE: FrameClock = FrameClock {
+    quarter: false,
+    half: false,
+};
+const QUARTER_FRAME: FrameClock = FrameClock {
+    quarter: true,
+    half: false,
+};
+const HALF_FRAME: FrameClock = FrameClock {
+    quarter: true,
+    half: true,
+};
+
+#[derive(Default)]
+pub(super) struct FrameCounter {
+    five_step: bool,
+    irq_inhibit: bool,
+    pub(super) irq: bool,
+    cycle: u32,
+    /// Byte written to $4017 and the cycles left until it takes effect
+    pending_write: Option<(u8, u8)>,
+}
+
+impl FrameCounter {
+    pub(super) fn write(&mut self, data: u8, odd_cycle: bool) {
+        self.irq_inhibit = data & 0b0100_0000 != 0;
+        if self.irq_inhibit {
+            self.irq = false;
+        }
+        let delay = if odd_cycle { 4 } else { 3 };
+        self.pending_write = Some((data, delay));
+    }
+
+    /// Reset restarts the sequence as if the last value were written again,
+    /// and clears the frame interrupt
+    pub(super) fn reset(&mut self, odd_cycle: bool) {
+        let data = (self.five_step as u8) << 7 | (self.irq_inhibit as u8) << 6;
+        self.irq = false;
+        self.write(data, odd_cycle);
+    }
+
+    /// Advances one CPU cycle and says which units to clock
+    pub(super) fn tick(&mut self) -> FrameClock {
+        if let Some((data, delay)) = self.pending_write {
+            if delay > 1 {
+                self.pending_write = Some((data, delay - 1));
+            } else {
+                self.pending_write = None;
+                self.five_step = data & 0b1000_0000 != 0;
+                self.cycle = 0;
+                return if self.five_step { HALF_FRAME } else { NONE };
+            }
+        }
+
+        self.cycle += 1;
+        match (self.five_step, self.cycle) {
+            (_, QUARTER) | (_, THREE_QUARTERS) => QUARTER_FRAME,
+            (_, HALF) => HALF_FRAME,
+            (false, FOUR_STEP_IRQ) => {
+                self.raise_irq();
+                NONE
+            }
+            (false, FOUR_STEP_END) => {
+                self.raise_irq();
+                HALF_FRAME
+            }
+            (false, FOUR_STEP_RESTART) => {
+                self.raise_irq();
+                self.cycle = 0;
+                NONE
+            }
+            (true, FIVE_STEP_END) => HALF_FRAME,
+            (true, FIVE_STEP_RESTART) => {
+                self.cycle = 0;
+                NONE
+            }
+            _ => NONE,
+        }
+    }
+
+    fn raise_irq(&mut self) {
+        if !self.irq_inhibit {
+            self.irq = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Cycles, counted from 1, on which each kind of clock happened
+    fn run(counter: &mut FrameCounter, cycles: u32) -> (Vec<u32>, Vec<u32>) {
+        let mut quarters = vec![];
+        let mut halves = vec![];
+        for cycle in 1..=cycles {
+            let clock = counter.tick();
+            if clock.quarter {
+                quarters.push(cycle);
+            }
+            if clock.half {
+                halves.push(cycle);
+            }
+        }
+        (quarters, halves)
+    }
+
+    #[test]
+    fn test_four_step_sequence() {
+        let mut counter = FrameCounter::default();
+        let (quarters, halves) = run(&mut counter, 2 * 29830);
+
+        assert_eq!(
+            quarters,
+            vec![7457, 14913, 22371, 29829, 37287, 44743, 52201, 59659]
+        );
+        assert_eq!(halves, vec![14913, 29829, 44743, 59659]);
+    }
+
+    #[test]
+    fn test_four_step_irq() {
+        let mut counter = FrameCounter::default();
+        run(&mut counter, 29827);
+        assert!(!counter.irq);
+        counter.tick();
+        assert!(counter.irq);
+
+        // Keeps being set until the end of the sequence
+        counter.irq = false;
+        counter.tick();
+        assert!(counter.irq);
+        counter.irq = false;
+        counter.tick();
+        assert!(counter.irq);
+        counter.irq = false;
+        counter.tick();
+        assert!(!counter.irq);
+    }
+
+    #[test]
+    fn test_irq_inhibit() {
+        let mut counter = FrameCounter::default();
+        run(&mut counter, 29830);
+        assert!(counter.irq);
+
+        counter.write(0b0100_0000, false);
+        assert!(!counter.irq);
+        run(&mut counter, 29830);
+        assert!(!counter.irq);
+    }
+
+    #[test]
+    fn test_five_step_sequence() {
+        let mut counter = FrameCounter::default();
+        counter.write(0b1000_0000, false);
+        let (quarters, halves) = run(&mut counter, 3 + 37282);
+
+        // Clocked straight away when the write takes effect
+        assert_eq!(quarters, vec![3, 3 + 7457, 3 + 14913, 3 + 22371, 3 + 37281]);
+        assert_eq!(halves, vec![3, 3 + 14913, 3 + 37281]);
+        assert!(!counter.irq);
+    }
+
+    #[test]
+    fn test_write_delay_depends_on_cycle() {
+        for (odd_cycle, delay) in [(false, 3), (true, 4)] {
+            let mut counter = FrameCounter::default();
+            counter.write(0b1000_0000, odd_cycle);
+            let (quarters, _) = run(&mut counter, 4);
+
+            assert_eq!(quarters, vec![delay]);
+        }
+    }
+}
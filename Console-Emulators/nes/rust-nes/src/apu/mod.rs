@@ -0,0 +1,403 @@
+mod dmc;
+mod frame_counter;
+mod noise;
+mod pulse;
+mod triangle;
+mod units;
+
+use crate::interrupt::IrqSource;
+use dmc::Dmc;
+use frame_counter::FrameCounter;
+use noise::Noise;
+use pulse::{Pulse, PulseChannel};
+use triangle::Triangle;
+
+/// APU Registers
+/// =============
+/// $4000-$4003 | Pulse 1
+/// $4004-$4007 | Pulse 2
+/// $4008-$400B | Triangle
+/// $400C-$400F | Noise
+/// $4010-$4013 | DMC
+/// $4015       | Channel enables (write), status (read)
+/// $4017       | Frame counter
+///
+/// Status ($4015 write)
+/// 7  bit  0
+/// ---- ----
+/// ---D NT21
+///    | ||||
+///    | |||+- Pulse 1 enable
+///    | ||+-- Pulse 2 enable
+///    | |+--- Triangle enable
+///    | +---- Noise enable
+///    +------ DMC enable
+///
+/// Status ($4015 read)
+/// 7  bit  0
+/// ---- ----
+/// IF-D NT21
+/// || | ||||
+/// || | |||+- Pulse 1 length counter above 0
+/// || | ||+-- Pulse 2 length counter above 0
+/// || | |+--- Triangle length counter above 0
+/// || | +---- Noise length counter above 0
+/// || +------ DMC bytes remaining
+/// |+-------- Frame interrupt, cleared by the read
+/// +--------- DMC interrupt
+const PULSE_1: u16 = 0x4000;
+const PULSE_1_END: u16 = 0x4003;
+const PULSE_2: u16 = 0x4004;
+const PULSE_2_END: u16 = 0x4007;
+const TRIANGLE: u16 = 0x4008;
+const TRIANGLE_END: u16 = 0x400B;
+const NOISE: u16 = 0x400C;
+const NOISE_END: u16 = 0x400F;
+const DMC: u16 = 0x4010;
+const DMC_END: u16 = 0x4013;
+const STATUS: u16 = 0x4015;
+const FRAME_COUNTER: u16 = 0x4017;
+
+/// NTSC CPU clock, which the APU runs from
+pub const CPU_CLOCK_RATE: f64 = 1_789_773.0;
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+/// APU
+/// ===
+/// The five channels, mixed and averaged down to the host sample rate.
+/// Samples collect in a buffer for the host to drain, so nothing here
+/// needs an audio device.
+pub struct Apu {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    frame_counter: FrameCounter,
+    /// Total CPU cycles since power on
+    pub cycles: u64,
+
+    // Resampling
+    sample_rate: u32,
+    cycles_per_sample: f64,
+    sample_clock: f64,
+    sample_sum: f32,
+    sample_count: u32,
+    samples: Vec<f32>,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new(DEFAULT_SAMPLE_RATE)
+    }
+}
+
+impl Apu {
+    pub fn new(sample_rate: u32) -> Self {
+        Apu {
+            pulse1: Pulse::new(PulseChannel::One),
+            pulse2: Pulse::new(PulseChannel::Two),
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            dmc: Dmc::default(),
+            frame_counter: FrameCounter::default(),
+            cycles: 0,
+            sample_rate,
+            cycles_per_sample: CPU_CLOCK_RATE / sample_rate as f64,
+            sample_clock: 0.0,
+            sample_sum: 0.0,
+            sample_count: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Takes every sample produced since the last call
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    pub fn write_register(&mut self, address: u16, data: u8) {
+        match address {
+            PULSE_1..=PULSE_1_END => self.pulse1.write(address - PULSE_1, data),
+            PULSE_2..=PULSE_2_END => self.pulse2.write(address - PULSE_2, data),
+            TRIANGLE..=TRIANGLE_END => self.triangle.write(address - TRIANGLE, data),
+            NOISE..=NOISE_END => self.noise.write(address - NOISE, data),
+            DMC..=DMC_END => self.dmc.write(address - DMC, data),
+            STATUS => self.write_status(data),
+            FRAME_COUNTER => self.frame_counter.write(data, self.cycles % 2 == 1),
+            _ => {}
+        }
+    }
+
+    /// $4015 is the only readable APU register
+    pub fn read_status(&mut self) -> u8 {
+        let mut status = 0;
+        status |= self.pulse1.length.active() as u8;
+        status |= (self.pulse2.length.active() as u8) << 1;
+        status |= (self.triangle.length.active() as u8) << 2;
+        status |= (self.noise.length.active() as u8) << 3;
+        status |= (self.dmc.active() as u8) << 4;
+        status |= (self.frame_counter.irq as u8) << 6;
+        status |= (self.dmc.irq as u8) << 7;
+        self.frame_counter.irq = false;
+        status
+    }
+
+    /// Enables or silences each channel. Any write also acknowledges the
+    /// DMC interrupt.
+    fn write_status(&mut self, data: u8) {
+        self.pulse1.length.set_enabled(data & 0b0000_0001 != 0);
+        self.pulse2.length.set_enabled(data & 0b0000_0010 != 0);
+        self.triangle.length.set_enabled(data & 0b0000_0100 != 0);
+        self.noise.length.set_enabled(data & 0b0000_1000 != 0);
+        self.dmc.set_enabled(data & 0b0001_0000 != 0);
+        self.dmc.irq = false;
+    }
+
+    /// The reset button silences every channel, as if $4015 were written
+    /// with 0, and restarts the frame counter in the mode it was in
+    pub fn reset(&mut self) {
+        self.write_status(0);
+        self.frame_counter.reset(self.cycles % 2 == 1);
+    }
+
+    /// Sources in the APU holding the IRQ line asserted
+    pub fn irq(&self) -> IrqSource {
+        let mut sources = IrqSource::empty();
+        sources.set(IrqSource::APU_FRAME, self.frame_counter.irq);
+        sources.set(IrqSource::APU_DMC, self.dmc.irq);
+        sources
+    }
+
+    /// Address the DMC wants to read its next sample byte from. The bus
+    /// serves the read, stalling the CPU, and hands the byte back through
+    /// `fill_dmc_sample`.
+    pub fn dmc_sample_request(&self) -> Option<u16> {
+        self.dmc.sample_request()
+    }
+
+    pub fn fill_dmc_sample(&mut self, data: u8) {
+        self.dmc.fill_sample_buffer(data);
+    }
+
+    /// Advances the APU by one CPU cycle
+    pub fn tick(&mut self) {
+        let frame = self.frame_counter.tick();
+        if frame.quarter {
+            self.clock_quarter_frame();
+        }
+        if frame.half {
+            self.clock_half_frame();
+        }
+
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+        if self.cycles % 2 == 1 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.cycles += 1;
+
+        self.sample_sum += self.output();
+        self.sample_count += 1;
+        self.sample_clock += 1.0;
+        if self.sample_clock >= self.cycles_per_sample {
+            self.sample_clock -= self.cycles_per_sample;
+            self.samples
+                .push(self.sample_sum / self.sample_count as f32);
+            self.sample_sum = 0.0;
+            self.sample_count = 0;
+        }
+    }
+
+    /// Envelopes and the triangle's linear counter
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.clock_quarter_frame();
+        self.pulse2.clock_quarter_frame();
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+    }
+
+    /// Length counters and sweeps
+    fn clock_half_frame(&mut self) {
+        self.pulse1.clock_half_frame();
+        self.pulse2.clock_half_frame();
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    /// Mixer
+    /// =====
+    /// The channels are mixed through resistor networks rather than added,
+    /// so louder channels take up less of the range. These are the usual
+    /// approximations of the two networks, giving a level from 0.0 to
+    /// about 1.0.
+    pub fn output(&self) -> f32 {
+        let pulse = (self.pulse1.output() + self.pulse2.output()) as f32;
+        let pulse_out = if pulse == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse + 100.0)
+        };
+
+        let tnd = self.triangle.output() as f32 / 8227.0
+            + self.noise.output() as f32 / 12241.0
+            + self.dmc.output() as f32 / 22638.0;
+        let tnd_out = if tnd == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / tnd + 100.0)
+        };
+
+        pulse_out + tnd_out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_flat_at_power_on() {
+        let mut apu = Apu::new(48_000);
+        for _ in 0..1000 {
+            apu.tick();
+        }
+
+        // The triangle rests on step 0 of its sequence, so the level is
+        // steady but not 0
+        let samples = apu.take_samples();
+        assert!(samples.iter().all(|sample| *sample == samples[0]));
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        let mut apu = Apu::new(44_100);
+        for _ in 0..CPU_CLOCK_RATE as usize {
+            apu.tick();
+        }
+
+        let samples = apu.take_samples();
+        assert!((44_099..=44_100).contains(&samples.len()));
+        assert!(apu.take_samples().is_empty());
+    }
+
+    #[test]
+    fn test_mixer_levels() {
+        let mut apu = Apu::default();
+        apu.write_register(0x4015, 0b0000_0011);
+        apu.write_register(0x4000, 0b1111_1111);
+        apu.write_register(0x4003, 0b0000_1001);
+        apu.write_register(0x4004, 0b1111_1111);
+        apu.write_register(0x4007, 0b0000_1001);
+        // Both pulses at 15 is the loudest the pulse network gets. The
+        // triangle adds the same level to both.
+        let resting = Apu::default().output();
+        assert!((apu.output() - resting - 0.2585).abs() < 0.001);
+
+        // A full scale DMC shares the other network with the triangle
+        let mut apu = Apu::default();
+        apu.write_register(0x4011, 0x7f);
+        assert!((apu.output() - 0.6813).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_status_write_silences_channels() {
+        let mut apu = Apu::default();
+        apu.write_register(0x4015, 0b0000_0001);
+        apu.write_register(0x4000, 0b1111_1111);
+        apu.write_register(0x4002, 0xff);
+        apu.write_register(0x4003, 0b0000_1000);
+        let resting = Apu::default().output();
+        assert!(apu.output() > resting);
+
+        apu.write_register(0x4015, 0);
+        assert_eq!(apu.output(), resting);
+        // Length loads are ignored while disabled
+        apu.write_register(0x4003, 0b0000_1000);
+        assert_eq!(apu.output(), resting);
+    }
+
+    #[test]
+    fn test_reset_clears_status() {
+        let mut apu = Apu::default();
+        apu.write_register(0x4015, 0b0000_1111);
+        apu.write_register(0x4003, 0b0000_1000);
+        apu.write_register(0x400f, 0b0000_1000);
+        apu.frame_counter.irq = true;
+        assert_ne!(apu.read_status(), 0);
+        apu.frame_counter.irq = true;
+
+        apu.reset();
+        assert_eq!(apu.read_status(), 0);
+    }
+
+    #[test]
+    fn test_length_counters_run_from_frame_counter() {
+        let mut apu = Apu::default();
+        apu.write_register(0x4015, 0b0000_1111);
+        // Length index 3 loads 2 half frames
+        apu.write_register(0x4003, 0b0001_1000);
+        apu.write_register(0x4007, 0b0001_1000);
+        apu.write_register(0x4008, 0b0000_0001);
+        apu.write_register(0x400b, 0b0001_1000);
+        apu.write_register(0x400f, 0b0001_1000);
+        assert_eq!(apu.read_status() & 0x0f, 0x0f);
+
+        for _ in 0..14913 {
+            apu.tick();
+        }
+        assert_eq!(apu.read_status() & 0x0f, 0x0f);
+        for _ in 0..29829 - 14913 {
+            apu.tick();
+        }
+        assert_eq!(apu.read_status() & 0x0f, 0x00);
+    }
+
+    #[test]
+    fn test_status_read_clears_frame_irq() {
+        let mut apu = Apu::default();
+        for _ in 0..29829 {
+            apu.tick();
+        }
+        assert_eq!(apu.irq(), IrqSource::APU_FRAME);
+        assert_eq!(apu.read_status(), 0x40);
+        assert_eq!(apu.irq(), IrqSource::empty());
+        assert_eq!(apu.read_status(), 0x00);
+    }
+
+    #[test]
+    fn test_dmc_status_bits() {
+        let mut apu = Apu::default();
+        apu.write_register(0x4010, 0x8f);
+        apu.write_register(0x4015, 0b0001_0000);
+        assert_eq!(apu.read_status(), 0x10);
+
+        apu.fill_dmc_sample(0);
+        // The DMC interrupt survives a status read
+        assert_eq!(apu.read_status(), 0x80);
+        assert_eq!(apu.read_status(), 0x80);
+    }
+
+    #[test]
+    fn test_dmc_irq_and_requests() {
+        let mut apu = Apu::default();
+        apu.write_register(0x4010, 0x8f);
+        apu.write_register(0x4012, 0x00);
+        apu.write_register(0x4013, 0x00);
+        apu.write_register(0x4015, 0b0001_0000);
+        assert_eq!(apu.dmc_sample_request(), Some(0xC000));
+
+        apu.fill_dmc_sample(0xaa);
+        assert_eq!(apu.dmc_sample_request(), None);
+        assert_eq!(apu.irq(), IrqSource::APU_DMC);
+
+        apu.write_register(0x4015, 0);
+        assert_eq!(apu.irq(), IrqSource::empty());
+    }
+}
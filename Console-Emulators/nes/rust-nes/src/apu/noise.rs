@@ -0,0 +1,133 @@
+use super::units::{Envelope, LengthCounter};
+
+/// Noise Channel ($400C-$400F)
+/// ===========================
+/// Pseudo random output from a 15 bit linear feedback shift register.
+/// Each time the timer runs out bit 0 is XORed with bit 1, or with bit 6
+/// in short mode, and shifted in at the top. Short mode repeats every 93
+/// steps and sounds metallic, the normal mode every 32767.
+///
+/// Register | Bits      | Contents
+/// $400C    | --LC VVVV | Length halt/envelope loop, envelope
+/// $400E    | M--- PPPP | Short mode, period index
+/// $400F    | LLLL L--- | Length counter load
+///
+/// Periods are in CPU cycles (NTSC).
+const PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+pub(super) struct Noise {
+    pub(super) length: LengthCounter,
+    envelope: Envelope,
+    short_mode: bool,
+    shift_register: u16,
+    timer_period: u16,
+    timer: u16,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Noise {
+            length: LengthCounter::default(),
+            envelope: Envelope::default(),
+            short_mode: false,
+            // Loaded with 1 at power on, it would never leave 0
+            shift_register: 1,
+            timer_period: PERIODS[0],
+            timer: 0,
+        }
+    }
+}
+
+impl Noise {
+    pub(super) fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.length.halt = data & 0b0010_0000 != 0;
+                self.envelope.write(data);
+            }
+            1 => {}
+            2 => {
+                self.short_mode = data & 0b1000_0000 != 0;
+                self.timer_period = PERIODS[(data & 0b1111) as usize];
+            }
+            3 => {
+                self.length.load(data);
+                self.envelope.restart();
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Clocked every CPU cycle
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+            self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub(super) fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        if !self.length.active() || self.shift_register & 1 == 1 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sequence_length(short_mode: bool) -> usize {
+        let mut noise = Noise::default();
+        noise.write(2, (short_mode as u8) << 7);
+        let start = noise.shift_register;
+        let mut steps = 0;
+        loop {
+            for _ in 0..PERIODS[0] {
+                noise.clock_timer();
+            }
+            steps += 1;
+            if noise.shift_register == start {
+                return steps;
+            }
+        }
+    }
+
+    #[test]
+    fn test_lfsr_periods() {
+        assert_eq!(sequence_length(false), 32767);
+        assert_eq!(sequence_length(true), 93);
+    }
+
+    #[test]
+    fn test_output_follows_bit_0() {
+        let mut noise = Noise::default();
+        noise.length.set_enabled(true);
+        noise.write(0, 0b0011_1010);
+        noise.write(3, 0b0000_1000);
+        assert_eq!(noise.output(), 0);
+
+        // 1 shifts out after the first step
+        for _ in 0..4 {
+            noise.clock_timer();
+        }
+        assert_eq!(noise.shift_register, 0b100_0000_0000_0000);
+        assert_eq!(noise.output(), 10);
+    }
+}
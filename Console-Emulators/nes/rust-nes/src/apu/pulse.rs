@@ -0,0 +1,238 @@
+use super::units::{Envelope, LengthCounter};
+
+/// Pulse Channels ($4000-$4007)
+/// ============================
+/// Square waves with four duty cycles, an envelope and a sweep unit that
+/// bends the pitch up or down. The timer runs on every other CPU cycle and
+/// steps an 8 step duty sequence each time it reaches 0.
+///
+/// Register | Bits      | Contents
+/// $4000    | DDLC VVVV | Duty, length halt/envelope loop, envelope
+/// $4001    | EPPP NSSS | Sweep enable, period, negate and shift
+/// $4002    | TTTT TTTT | Timer low 8 bits
+/// $4003    | LLLL LTTT | Length counter load, timer high 3 bits
+const DUTY_SEQUENCES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+/// The two pulse channels differ only in how the sweep negates: pulse 1
+/// subtracts one more than pulse 2 does.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) enum PulseChannel {
+    One,
+    Two,
+}
+
+#[derive(Default)]
+struct Sweep {
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    divider: u8,
+    reload: bool,
+}
+
+pub(super) struct Pulse {
+    channel: PulseChannel,
+    pub(super) length: LengthCounter,
+    envelope: Envelope,
+    sweep: Sweep,
+    duty: usize,
+    sequence: usize,
+    timer_period: u16,
+    timer: u16,
+}
+
+impl Pulse {
+    pub(super) fn new(channel: PulseChannel) -> Self {
+        Pulse {
+            channel,
+            length: LengthCounter::default(),
+            envelope: Envelope::default(),
+            sweep: Sweep::default(),
+            duty: 0,
+            sequence: 0,
+            timer_period: 0,
+            timer: 0,
+        }
+    }
+
+    pub(super) fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.duty = (data >> 6) as usize;
+                self.length.halt = data & 0b0010_0000 != 0;
+                self.envelope.write(data);
+            }
+            1 => {
+                self.sweep.enabled = data & 0b1000_0000 != 0;
+                self.sweep.period = (data >> 4) & 0b111;
+                self.sweep.negate = data & 0b0000_1000 != 0;
+                self.sweep.shift = data & 0b111;
+                self.sweep.reload = true;
+            }
+            2 => self.timer_period = (self.timer_period & 0x0700) | data as u16,
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((data as u16 & 0b111) << 8);
+                self.length.load(data);
+                self.envelope.restart();
+                self.sequence = 0;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Clocked every other CPU cycle
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequence = (self.sequence + 7) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub(super) fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length.clock();
+
+        let target = self.sweep_target();
+        if self.sweep.divider == 0 && self.sweep.enabled && self.sweep.shift > 0 && !self.muted() {
+            self.timer_period = target;
+        }
+        if self.sweep.divider == 0 || self.sweep.reload {
+            self.sweep.divider = self.sweep.period;
+            self.sweep.reload = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    /// The period the sweep is heading for. It is worked out all the time,
+    /// not only when the sweep is enabled, and can mute the channel either
+    /// way.
+    fn sweep_target(&self) -> u16 {
+        let change = self.timer_period >> self.sweep.shift;
+        if self.sweep.negate {
+            let extra = (self.channel == PulseChannel::One) as u16;
+            self.timer_period.saturating_sub(change + extra)
+        } else {
+            self.timer_period + change
+        }
+    }
+
+    /// Periods under 8 would be ultrasonic and targets past $7FF cannot be
+    /// stored, so both silence the channel
+    fn muted(&self) -> bool {
+        self.timer_period < 8 || self.sweep_target() > 0x7FF
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        if !self.length.active() || self.muted() || DUTY_SEQUENCES[self.duty][self.sequence] == 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn playing_pulse(channel: PulseChannel) -> Pulse {
+        let mut pulse = Pulse::new(channel);
+        pulse.length.set_enabled(true);
+        pulse.write(0, 0b1011_1111);
+        pulse.write(2, 0x00);
+        pulse.write(3, 0b0000_1001);
+        pulse
+    }
+
+    #[test]
+    fn test_duty_sequence() {
+        let mut pulse = playing_pulse(PulseChannel::One);
+        pulse.write(0, 0b0011_1111);
+        let mut wave = vec![];
+        for _ in 0..8 {
+            wave.push(pulse.output());
+            for _ in 0..=0x100 {
+                pulse.clock_timer();
+            }
+        }
+
+        // 12.5% duty, read backwards from step 0
+        assert_eq!(wave, vec![0, 0, 0, 0, 0, 0, 0, 15]);
+    }
+
+    #[test]
+    fn test_timer_period() {
+        let mut pulse = playing_pulse(PulseChannel::One);
+        pulse.clock_timer();
+        assert_eq!(pulse.sequence, 7);
+        for _ in 0..0x100 {
+            pulse.clock_timer();
+        }
+        assert_eq!(pulse.sequence, 7);
+        pulse.clock_timer();
+        assert_eq!(pulse.sequence, 6);
+    }
+
+    #[test]
+    fn test_short_period_mutes() {
+        let mut pulse = playing_pulse(PulseChannel::One);
+        pulse.write(2, 0x07);
+        pulse.write(3, 0x00);
+        pulse.sequence = 1;
+
+        assert_eq!(pulse.output(), 0);
+    }
+
+    #[test]
+    fn test_sweep_target_overflow_mutes() {
+        let mut pulse = playing_pulse(PulseChannel::Two);
+        pulse.sequence = 4;
+        assert_eq!(pulse.output(), 15);
+
+        // Disabled, but a shift of 0 still targets twice the period
+        pulse.write(2, 0xff);
+        pulse.write(3, 0b0000_1100);
+        pulse.sequence = 4;
+        assert_eq!(pulse.output(), 0);
+    }
+
+    #[test]
+    fn test_sweep_negate_differs_between_channels() {
+        for (channel, expected) in [
+            (PulseChannel::One, 0x100 - 0x40 - 1),
+            (PulseChannel::Two, 0xc0),
+        ] {
+            let mut pulse = playing_pulse(channel);
+            // Enabled, period 0, negate, shift 2
+            pulse.write(1, 0b1000_1010);
+            pulse.clock_half_frame();
+            assert_eq!(pulse.timer_period, expected);
+        }
+    }
+
+    #[test]
+    fn test_length_counter_silences() {
+        let mut pulse = playing_pulse(PulseChannel::One);
+        pulse.write(0, 0b1001_1111);
+        pulse.write(3, 0b0001_1001);
+        pulse.sequence = 4;
+        assert_eq!(pulse.output(), 15);
+
+        // Index 3 loads 2
+        pulse.clock_half_frame();
+        pulse.clock_half_frame();
+        assert_eq!(pulse.output(), 0);
+    }
+}
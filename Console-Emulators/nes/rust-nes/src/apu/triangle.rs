@@ -0,0 +1,133 @@
+use super::units::LengthCounter;
+
+/// Triangle Channel ($4008-$400B)
+/// ==============================
+/// Steps through a 32 step triangle wave with no volume control. Besides
+/// the length counter it has a linear counter, clocked every quarter frame,
+/// which gives finer control over note length. The timer runs on every CPU
+/// cycle, so the triangle is an octave lower than a pulse with the same
+/// period.
+///
+/// Register | Bits      | Contents
+/// $4008    | CRRR RRRR | Control/length halt, linear counter reload
+/// $400A    | TTTT TTTT | Timer low 8 bits
+/// $400B    | LLLL LTTT | Length counter load, timer high 3 bits
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+#[derive(Default)]
+pub(super) struct Triangle {
+    pub(super) length: LengthCounter,
+    control: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+    sequence: usize,
+    timer_period: u16,
+    timer: u16,
+}
+
+impl Triangle {
+    pub(super) fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.control = data & 0b1000_0000 != 0;
+                self.length.halt = self.control;
+                self.linear_reload_value = data & 0b0111_1111;
+            }
+            1 => {}
+            2 => self.timer_period = (self.timer_period & 0x0700) | data as u16,
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((data as u16 & 0b111) << 8);
+                self.length.load(data);
+                self.linear_reload = true;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Clocked every CPU cycle. The sequence only moves while both counters
+    /// are non zero, so a silenced triangle holds its last level rather than
+    /// dropping to 0.
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.linear_counter > 0 && self.length.active() {
+                self.sequence = (self.sequence + 1) % 32;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub(super) fn clock_quarter_frame(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        SEQUENCE[self.sequence]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn playing_triangle(linear: u8) -> Triangle {
+        let mut triangle = Triangle::default();
+        triangle.length.set_enabled(true);
+        triangle.write(0, linear);
+        triangle.write(2, 0x01);
+        triangle.write(3, 0b0000_1000);
+        triangle.clock_quarter_frame();
+        triangle
+    }
+
+    #[test]
+    fn test_sequence() {
+        let mut triangle = playing_triangle(0x7f);
+        let mut wave = vec![];
+        for _ in 0..32 {
+            wave.push(triangle.output());
+            triangle.clock_timer();
+            triangle.clock_timer();
+        }
+
+        assert_eq!(wave, SEQUENCE);
+    }
+
+    #[test]
+    fn test_linear_counter_stops_sequence() {
+        let mut triangle = playing_triangle(0x02);
+        triangle.clock_quarter_frame();
+        triangle.clock_quarter_frame();
+        assert_eq!(triangle.linear_counter, 0);
+
+        for _ in 0..8 {
+            triangle.clock_timer();
+        }
+        assert_eq!(triangle.output(), 15);
+    }
+
+    #[test]
+    fn test_control_flag_keeps_reloading() {
+        let mut triangle = playing_triangle(0x82);
+        triangle.clock_quarter_frame();
+        triangle.clock_quarter_frame();
+
+        assert_eq!(triangle.linear_counter, 2);
+    }
+}
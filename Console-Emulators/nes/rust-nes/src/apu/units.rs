@@ -0,0 +1,162 @@
+/// Length Counter
+/// ==============
+/// Silences a channel after a number of half frames. Writes to a channel's
+/// last register load it from this table with the top 5 bits of the byte.
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+#[derive(Default)]
+pub(super) struct LengthCounter {
+    enabled: bool,
+    pub(super) halt: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    /// Loads are ignored while the channel is disabled in $4015
+    pub(super) fn load(&mut self, data: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[(data >> 3) as usize];
+        }
+    }
+
+    /// Disabling a channel clears its counter straight away
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub(super) fn clock(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    pub(super) fn active(&self) -> bool {
+        self.counter > 0
+    }
+}
+
+/// Envelope
+/// ========
+/// Either a constant volume or a sawtooth that decays from 15 to 0 once
+/// every (V + 1) quarter frames, optionally looping. Shared by the pulse
+/// and noise channels.
+///
+/// 7  bit  0
+/// ---- ----
+/// --LC VVVV
+///   || ||||
+///   || ++++- Volume, or the decay period
+///   |+------ Constant volume
+///   +------- Loop, which is also the length counter halt flag
+#[derive(Default)]
+pub(super) struct Envelope {
+    start: bool,
+    looping: bool,
+    constant: bool,
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    pub(super) fn write(&mut self, data: u8) {
+        self.looping = data & 0b0010_0000 != 0;
+        self.constant = data & 0b0001_0000 != 0;
+        self.volume = data & 0b0000_1111;
+    }
+
+    /// Restarts the decay on the next quarter frame
+    pub(super) fn restart(&mut self) {
+        self.start = true;
+    }
+
+    pub(super) fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        if self.constant {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_length_counter_load_and_halt() {
+        let mut length = LengthCounter::default();
+        length.load(0b0000_1000);
+        assert!(!length.active());
+
+        length.set_enabled(true);
+        length.load(0b0000_1000);
+        assert_eq!(length.counter, 254);
+
+        length.halt = true;
+        length.clock();
+        assert_eq!(length.counter, 254);
+        length.halt = false;
+        length.clock();
+        assert_eq!(length.counter, 253);
+
+        length.set_enabled(false);
+        assert!(!length.active());
+    }
+
+    #[test]
+    fn test_envelope_decay_and_loop() {
+        let mut envelope = Envelope::default();
+        envelope.write(0b0010_0001);
+        envelope.restart();
+        envelope.clock();
+        assert_eq!(envelope.output(), 15);
+
+        // Decays once every 2 clocks with a period of 1
+        envelope.clock();
+        assert_eq!(envelope.output(), 15);
+        envelope.clock();
+        assert_eq!(envelope.output(), 14);
+
+        for _ in 0..28 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.output(), 0);
+        envelope.clock();
+        envelope.clock();
+        assert_eq!(envelope.output(), 15);
+    }
+
+    #[test]
+    fn test_envelope_constant_volume() {
+        let mut envelope = Envelope::default();
+        envelope.write(0b0001_0111);
+        envelope.restart();
+        envelope.clock();
+
+        assert_eq!(envelope.output(), 7);
+    }
+}
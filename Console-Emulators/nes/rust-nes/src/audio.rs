@@ -0,0 +1,232 @@
+use std::f32::consts::PI;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Output rates the WAV sink writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleRate {
+    Hz44100,
+    Hz48000,
+}
+
+impl SampleRate {
+    pub fn hz(self) -> u32 {
+        match self {
+            SampleRate::Hz44100 => 44_100,
+            SampleRate::Hz48000 => 48_000,
+        }
+    }
+}
+
+impl FromStr for SampleRate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "44100" | "44.1k" => Ok(SampleRate::Hz44100),
+            "48000" | "48k" => Ok(SampleRate::Hz48000),
+            _ => Err(format!("unsupported sample rate {}, use 44100 or 48000", s)),
+        }
+    }
+}
+
+impl fmt::Display for SampleRate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.hz())
+    }
+}
+
+/// The console's output stage has a high-pass filter around 90Hz, which
+/// removes the DC offset of the APU mixer
+const HIGH_PASS_HZ: f32 = 90.0;
+
+/// WAV Sink
+/// ========
+/// Collects APU samples for a headless run and writes them out as a 16 bit
+/// mono PCM WAV. Input is high-pass filtered and scaled from the mixer's
+/// 0.0-1.0 to the full signed range. Run the APU at the output rate so the
+/// samples pass straight through, input at any other rate is linearly
+/// interpolated to it.
+///
+/// Offset | Size | Contents
+/// 0      | 4    | "RIFF"
+/// 4      | 4    | File size - 8
+/// 8      | 4    | "WAVE"
+/// 12     | 4    | "fmt "
+/// 16     | 4    | 16, size of the format chunk
+/// 20     | 2    | 1, PCM
+/// 22     | 2    | 1, channels
+/// 24     | 4    | Sample rate
+/// 28     | 4    | Byte rate
+/// 32     | 2    | Block align
+/// 34     | 2    | 16, bits per sample
+/// 36     | 4    | "data"
+/// 40     | 4    | Data size
+/// 44     |      | Samples, little endian
+pub struct WavSink {
+    sample_rate: SampleRate,
+    /// Input samples per output sample
+    step: f64,
+    /// Time of the next output sample, in input samples after `previous`
+    position: f64,
+    previous: f32,
+    high_pass_alpha: f32,
+    high_pass_input: f32,
+    high_pass_output: f32,
+    samples: Vec<i16>,
+}
+
+impl WavSink {
+    pub fn new(input_rate: u32, sample_rate: SampleRate) -> Self {
+        let rc = 1.0 / (2.0 * PI * HIGH_PASS_HZ);
+        let dt = 1.0 / sample_rate.hz() as f32;
+        WavSink {
+            sample_rate,
+            step: input_rate as f64 / sample_rate.hz() as f64,
+            position: 1.0,
+            previous: 0.0,
+            high_pass_alpha: rc / (rc + dt),
+            high_pass_input: 0.0,
+            high_pass_output: 0.0,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    /// The converted samples so far
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    pub fn push(&mut self, input: &[f32]) {
+        if self.step == 1.0 {
+            for &sample in input {
+                self.emit(sample);
+            }
+            return;
+        }
+
+        for &sample in input {
+            while self.position <= 1.0 {
+                let t = self.position as f32;
+                self.emit(self.previous + (sample - self.previous) * t);
+                self.position += self.step;
+            }
+            self.position -= 1.0;
+            self.previous = sample;
+        }
+    }
+
+    fn emit(&mut self, sample: f32) {
+        self.high_pass_output =
+            self.high_pass_alpha * (self.high_pass_output + sample - self.high_pass_input);
+        self.high_pass_input = sample;
+
+        let scaled = (self.high_pass_output * i16::MAX as f32).round();
+        self.samples
+            .push(scaled.clamp(i16::MIN as f32, i16::MAX as f32) as i16);
+    }
+
+    pub fn write<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let rate = self.sample_rate.hz();
+        let data_size = (self.samples.len() * 2) as u32;
+
+        out.write_all(b"RIFF")?;
+        out.write_all(&(36 + data_size).to_le_bytes())?;
+        out.write_all(b"WAVE")?;
+        out.write_all(b"fmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?;
+        out.write_all(&rate.to_le_bytes())?;
+        out.write_all(&(rate * 2).to_le_bytes())?;
+        out.write_all(&2u16.to_le_bytes())?;
+        out.write_all(&16u16.to_le_bytes())?;
+        out.write_all(b"data")?;
+        out.write_all(&data_size.to_le_bytes())?;
+        for sample in &self.samples {
+            out.write_all(&sample.to_le_bytes())?;
+        }
+        out.flush()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write(BufWriter::new(File::create(path)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_header() {
+        let mut sink = WavSink::new(48_000, SampleRate::Hz48000);
+        sink.push(&[0.0; 3]);
+        let mut out = vec![];
+        sink.write(&mut out).unwrap();
+
+        assert_eq!(out.len(), 44 + 6);
+        assert_eq!(&out[0..4], b"RIFF");
+        assert_eq!(out[4..8], 42u32.to_le_bytes());
+        assert_eq!(&out[8..16], b"WAVEfmt ");
+        assert_eq!(out[20..24], [1, 0, 1, 0]);
+        assert_eq!(out[24..28], 48_000u32.to_le_bytes());
+        assert_eq!(out[28..32], 96_000u32.to_le_bytes());
+        assert_eq!(out[32..36], [2, 0, 16, 0]);
+        assert_eq!(&out[36..40], b"data");
+        assert_eq!(out[40..44], 6u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_resamples_to_output_rate() {
+        for rate in [SampleRate::Hz44100, SampleRate::Hz48000] {
+            let mut sink = WavSink::new(44_100, rate);
+            sink.push(&vec![0.0; 44_100]);
+
+            let expected = rate.hz() as usize;
+            assert!((expected - 1..=expected).contains(&sink.samples().len()));
+        }
+    }
+
+    #[test]
+    fn test_passes_through_at_output_rate() {
+        let mut sink = WavSink::new(48_000, SampleRate::Hz48000);
+        sink.high_pass_alpha = 1.0;
+        sink.push(&[0.25, 0.5]);
+
+        assert_eq!(sink.samples(), &[8192, 16384]);
+    }
+
+    #[test]
+    fn test_interpolates_between_input_samples() {
+        let mut sink = WavSink::new(1, SampleRate::Hz44100);
+        sink.step = 0.5;
+        sink.high_pass_alpha = 1.0;
+        sink.push(&[0.0, 0.5]);
+
+        assert_eq!(sink.samples(), &[0, 8192, 16384]);
+    }
+
+    #[test]
+    fn test_high_pass_removes_dc() {
+        let mut sink = WavSink::new(44_100, SampleRate::Hz44100);
+        sink.push(&vec![0.5; 44_100]);
+
+        assert!(sink.samples()[0] > 10_000);
+        assert_eq!(*sink.samples().last().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_sample_rate_from_str() {
+        assert_eq!("44100".parse(), Ok(SampleRate::Hz44100));
+        assert_eq!("48k".parse(), Ok(SampleRate::Hz48000));
+        assert!("22050".parse::<SampleRate>().is_err());
+    }
+}
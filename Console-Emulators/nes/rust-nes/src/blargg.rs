@@ -0,0 +1,220 @@
+use crate::bus::{Bus, NesBus};
+use crate::cartridge::{Rom, RomError};
+use crate::cpu::{CpuError, CPU};
+use crate::mapper;
+
+/// Blargg Test ROM Protocol
+/// ========================
+/// Most of blargg's accuracy ROMs (instr_test, cpu_timing, ppu_vbl_nmi,
+/// apu_test and friends) report their progress through PRG-RAM, so they
+/// can be checked without looking at the screen.
+///
+/// Address     | Contents
+/// $6000       | Status: $80 running, $81 press reset, $00-$7F result
+/// $6001-$6003 | $DE $B0 $61 once the rest of the area is valid
+/// $6004       | Zero terminated text, what would be on screen
+///
+/// A result of $00 is a pass, anything else is the number of the test
+/// that failed.
+const STATUS: u16 = 0x6000;
+const SIGNATURE: u16 = 0x6001;
+const MESSAGE: u16 = 0x6004;
+const MESSAGE_END: u16 = 0x7FFF;
+
+const SIGNATURE_BYTES: [u8; 3] = [0xDE, 0xB0, 0x61];
+const RUNNING: u8 = 0x80;
+const RESET_REQUESTED: u8 = 0x81;
+
+/// The ROM wants reset pressed at least 100ms after it asks
+const RESET_DELAY_FRAMES: u64 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    /// Result code of the test that failed
+    Failed(u8),
+    /// No result within the frame limit
+    TimedOut,
+    /// The CPU stopped with an error, such as a JAM
+    Halted(CpuError),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub outcome: Outcome,
+    /// Text the ROM left at $6004, trimmed
+    pub message: String,
+    pub frames: u64,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.outcome == Outcome::Passed
+    }
+}
+
+/// Where the ROM says it is, if it has written the signature yet
+fn status(bus: &mut NesBus) -> Option<u8> {
+    let signature = [0, 1, 2].map(|offset| bus.memory_peek(SIGNATURE + offset));
+    if signature == SIGNATURE_BYTES {
+        Some(bus.memory_peek(STATUS))
+    } else {
+        None
+    }
+}
+
+fn message(bus: &mut NesBus) -> String {
+    let mut text = vec![];
+    for address in MESSAGE..=MESSAGE_END {
+        match bus.memory_peek(address) {
+            0 => break,
+            byte => text.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&text).trim().to_string()
+}
+
+/// Runs a test ROM headless until it reports a result or `max_frames` go
+/// by, pressing reset whenever it asks
+pub fn run(rom: Rom, max_frames: u64) -> Result<Report, RomError> {
+    let mut cpu = CPU::new(NesBus::new(mapper::from_rom(rom)?));
+    cpu.reset();
+
+    let mut reset_at = None;
+
+    // The status area is checked once a frame
+    let outcome = loop {
+        if let Err(error) = cpu.run_frame() {
+            break Outcome::Halted(error);
+        }
+        let frame = cpu.bus.ppu.frame_count;
+
+        match status(&mut cpu.bus) {
+            Some(RUNNING) | None => {}
+            Some(RESET_REQUESTED) => {
+                let due = *reset_at.get_or_insert(frame + RESET_DELAY_FRAMES);
+                if frame >= due {
+                    reset_at = None;
+                    cpu.bus.reset();
+                    cpu.reset();
+                }
+            }
+            Some(0) => break Outcome::Passed,
+            Some(code) => break Outcome::Failed(code),
+        }
+        if frame >= max_frames {
+            break Outcome::TimedOut;
+        }
+    };
+
+    Ok(Report {
+        outcome,
+        message: message(&mut cpu.bus),
+        frames: cpu.bus.ppu.frame_count,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::test::test_rom;
+
+    /// LDA #data, STA address
+    fn store(program: &mut Vec<u8>, address: u16, data: u8) {
+        program.extend([0xa9, data, 0x8d, address as u8, (address >> 8) as u8]);
+    }
+
+    /// JMP to itself
+    fn trap(program: &mut Vec<u8>) {
+        let address = 0x8000 + program.len() as u16;
+        program.extend([0x4c, address as u8, (address >> 8) as u8]);
+    }
+
+    /// A ROM which fills in the status area the way a test ROM would, from
+    /// a list of (address, byte) writes, then loops forever
+    fn status_rom(writes: &[(u16, u8)]) -> Rom {
+        let mut program = vec![];
+        for &(address, data) in writes {
+            store(&mut program, address, data);
+        }
+        trap(&mut program);
+        test_rom(program)
+    }
+
+    fn result_writes(status: u8, text: &str) -> Vec<(u16, u8)> {
+        let mut writes = vec![(STATUS, RUNNING)];
+        for (offset, byte) in SIGNATURE_BYTES.into_iter().enumerate() {
+            writes.push((SIGNATURE + offset as u16, byte));
+        }
+        for (offset, byte) in text.bytes().chain([0]).enumerate() {
+            writes.push((MESSAGE + offset as u16, byte));
+        }
+        writes.push((STATUS, status));
+        writes
+    }
+
+    #[test]
+    fn test_passed() {
+        let report = run(status_rom(&result_writes(0, "\nPassed\n")), 60).unwrap();
+
+        assert_eq!(report.outcome, Outcome::Passed);
+        assert_eq!(report.message, "Passed");
+        assert!(report.passed());
+        assert_eq!(report.frames, 1);
+    }
+
+    #[test]
+    fn test_failed() {
+        let report = run(status_rom(&result_writes(3, "Failed #3")), 60).unwrap();
+
+        assert_eq!(report.outcome, Outcome::Failed(3));
+        assert_eq!(report.message, "Failed #3");
+        assert!(!report.passed());
+    }
+
+    #[test]
+    fn test_result_needs_signature() {
+        let report = run(status_rom(&[(STATUS, 0)]), 10).unwrap();
+
+        assert_eq!(report.outcome, Outcome::TimedOut);
+        assert_eq!(report.frames, 10);
+    }
+
+    #[test]
+    fn test_presses_reset_when_asked() {
+        // Asks for reset the first time through and passes after it, PRG-RAM
+        // keeps the status across the reset
+        let mut ask = vec![];
+        for (offset, byte) in SIGNATURE_BYTES.into_iter().enumerate() {
+            store(&mut ask, SIGNATURE + offset as u16, byte);
+        }
+        store(&mut ask, STATUS, RESET_REQUESTED);
+
+        // LDA $6000, CMP #$81, BEQ over the request and its trap
+        let skip = ask.len() as u8 + 3;
+        let mut program = vec![0xad, 0x00, 0x60, 0xc9, RESET_REQUESTED, 0xf0, skip];
+        program.extend(ask);
+        trap(&mut program);
+        store(&mut program, STATUS, 0);
+        trap(&mut program);
+
+        let report = run(test_rom(program), 60).unwrap();
+
+        assert_eq!(report.outcome, Outcome::Passed);
+        assert!(report.frames > RESET_DELAY_FRAMES);
+    }
+
+    #[test]
+    fn test_jam_ends_the_run() {
+        let mut program = vec![0xea, 0x02];
+        trap(&mut program);
+
+        let report = run(test_rom(program), 60).unwrap();
+
+        assert_eq!(
+            report.outcome,
+            Outcome::Halted(CpuError::Jam { pc: 0x8001 })
+        );
+        assert!(!report.passed());
+    }
+}
@@ -0,0 +1,518 @@
+use crate::apu::Apu;
+use crate::interrupt::IrqSource;
+use crate::joypad::Joypad;
+use crate::mapper::Mapper;
+use crate::ppu::Ppu;
+
+/// CPU Memory Map
+/// ==============
+///  _______________ $10000  _______________
+/// | PRG-ROM       |       |               |
+/// | Upper Bank    |       |               |
+/// |_ _ _ _ _ _ _ _| $C000 | PRG-ROM       |
+/// | PRG-ROM       |       |               |
+/// | Lower Bank    |       |               |
+/// |_______________| $8000 |_______________|
+/// | SRAM          |       | SRAM          |
+/// |_______________| $6000 |_______________|
+/// | Expansion ROM |       | Expansion ROM |
+/// |_______________| $4020 |_______________|
+/// | I/O Registers |       |               |
+/// |_ _ _ _ _ _ _ _| $4000 |               |
+/// | Mirrors       |       | I/O Registers |
+/// | $2000-$2007   |       |               |
+/// |_ _ _ _ _ _ _ _| $2008 |               |
+/// | I/O Registers |       |               |
+/// |_______________| $2000 |_______________|
+/// | Mirrors       |       |               |
+/// | $0000-$07FF   |       |               |
+/// |_ _ _ _ _ _ _ _| $0800 |               |
+/// | RAM           |       | RAM           |
+/// |_ _ _ _ _ _ _ _| $0200 |               |
+/// | Stack         |       |               |
+/// |_ _ _ _ _ _ _ _| $0100 |               |
+/// | Zero Page     |       |               |
+/// |_______________| $0000 |_______________|
+const RAM: u16 = 0x0000;
+const RAM_MIRRORS_END: u16 = 0x1FFF;
+const PPU_REGISTERS: u16 = 0x2000;
+const PPU_REGISTERS_MIRRORS_END: u16 = 0x3FFF;
+const APU_IO_REGISTERS: u16 = 0x4000;
+const APU_IO_REGISTERS_END: u16 = 0x401F;
+const OAM_DMA: u16 = 0x4014;
+const APU_STATUS: u16 = 0x4015;
+const JOYPAD_1: u16 = 0x4016;
+const JOYPAD_2: u16 = 0x4017;
+const CARTRIDGE: u16 = 0x4020;
+const CARTRIDGE_END: u16 = 0xFFFF;
+
+/// Everything the CPU can see lives behind a `Bus`. Reads take `&mut self`
+/// because on real hardware reading a register can change state, such as
+/// the PPU clearing its vblank flag when PPUSTATUS is read.
+pub trait Bus {
+    fn memory_read(&mut self, address: u16) -> u8;
+
+    fn memory_write(&mut self, address: u16, data: u8);
+
+    /// Reads a byte for a debugger or trace log without the side effects
+    /// `memory_read` can have
+    fn memory_peek(&mut self, address: u16) -> u8 {
+        self.memory_read(address)
+    }
+
+    /// Implement NES Little-Endian addressing for reading
+    fn memory_read_u16(&mut self, position: u16) -> u16 {
+        let lsb = self.memory_read(position) as u16;
+        let msb = self.memory_read(position.wrapping_add(1)) as u16;
+        (msb << 8) | lsb
+    }
+
+    /// Implement NES Little-Endian addressing for writing
+    fn memory_write_u16(&mut self, position: u16, data: u16) {
+        let msb = (data >> 8) as u8;
+        let lsb = (data & 0xFF) as u8;
+        self.memory_write(position, lsb);
+        self.memory_write(position.wrapping_add(1), msb);
+    }
+
+    /// Advances every other device on the bus by a number of CPU cycles so
+    /// the PPU and APU run in lockstep with the CPU.
+    fn tick(&mut self, _cycles: u8) {}
+
+    /// Cycles the CPU has to sit out because a device such as OAM DMA has
+    /// taken over the bus. Taking them clears the count.
+    fn take_stall_cycles(&mut self) -> u16 {
+        0
+    }
+
+    /// An address the bus could not serve since the last call, if any. The
+    /// CPU reports it as a `CpuError::BusFault` once the instruction that
+    /// made the access finishes.
+    fn take_fault(&mut self) -> Option<u16> {
+        None
+    }
+
+    /// Level of the NMI line as driven by devices on the bus
+    fn nmi_line(&self) -> bool {
+        false
+    }
+
+    /// Devices on the bus currently holding the IRQ line asserted
+    fn irq_line(&self) -> IrqSource {
+        IrqSource::empty()
+    }
+
+    /// Scanline and dot the PPU has reached, if there is one
+    fn ppu_position(&self) -> Option<(u16, u16)> {
+        None
+    }
+}
+
+/// Flat RAM
+/// ========
+/// 64KB of plain RAM with nothing else attached. Every address is readable
+/// and writable, which is what unit tests and stand alone 6502 test suites
+/// expect.
+pub struct FlatRam {
+    memory: [u8; 0x10000],
+}
+
+impl Default for FlatRam {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlatRam {
+    pub fn new() -> Self {
+        FlatRam {
+            memory: [0; 0x10000],
+        }
+    }
+}
+
+impl Bus for FlatRam {
+    fn memory_read(&mut self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn memory_write(&mut self, address: u16, data: u8) {
+        self.memory[address as usize] = data;
+    }
+}
+
+/// NES Bus
+/// =======
+/// Decodes the NES CPU memory map. Only the top three bits of the address
+/// are used to select a device and each device ignores the address lines it
+/// does not need, which is where all the mirroring comes from.
+pub struct NesBus {
+    cpu_vram: [u8; 2048],
+    pub ppu: Ppu,
+    pub apu: Apu,
+    mapper: Box<dyn Mapper>,
+    pub joypad1: Joypad,
+    pub joypad2: Joypad,
+    /// CPU cycles ticked so far, for the parity of DMA transfers
+    cycles: u64,
+    stall_cycles: u16,
+}
+
+impl NesBus {
+    pub fn new(mapper: Box<dyn Mapper>) -> Self {
+        NesBus {
+            cpu_vram: [0; 2048],
+            ppu: Ppu::new(),
+            apu: Apu::default(),
+            mapper,
+            joypad1: Joypad::new(),
+            joypad2: Joypad::new(),
+            cycles: 0,
+            stall_cycles: 0,
+        }
+    }
+
+    /// Passes the reset button on to the PPU and APU, the CPU is reset
+    /// separately with `CPU::reset`
+    pub fn reset(&mut self) {
+        self.ppu.reset();
+        self.apu.reset();
+    }
+
+    /// OAM DMA
+    /// =======
+    /// Writing $XX to $4014 copies $XX00-$XXFF into OAM through OAMDATA,
+    /// so the copy starts at the current OAMADDR and wraps around. The CPU
+    /// is halted for 513 cycles while it happens: one to halt, then a read
+    /// and a write for each byte. DMA can only read on even cycles, so one
+    /// more is spent lining up when it starts on an odd cycle.
+    fn oam_dma(&mut self, page: u8) {
+        let base = (page as u16) << 8;
+        for offset in 0..=0xFF {
+            let data = self.memory_read(base | offset);
+            self.ppu.write_register(self.mapper.as_mut(), 0x2004, data);
+        }
+        self.stall_cycles += 513 + (self.cycles % 2) as u16;
+    }
+}
+
+impl Bus for NesBus {
+    fn memory_read(&mut self, address: u16) -> u8 {
+        match address {
+            // Internal RAM only decodes 11 address lines, so $0000-$07FF
+            // repeats four times up to $1FFF.
+            RAM..=RAM_MIRRORS_END => {
+                let mirror_down_address = address & 0b0000_0111_1111_1111;
+                self.cpu_vram[mirror_down_address as usize]
+            }
+
+            // The eight PPU registers repeat every 8 bytes up to $3FFF
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => {
+                self.ppu.read_register(self.mapper.as_mut(), address)
+            }
+
+            // Controllers only drive bit 0, and a couple more on some
+            // expansion devices. The rest of the byte is whatever was last
+            // on the data bus, normally the $40 from the address operand.
+            JOYPAD_1 => (address >> 8) as u8 & 0xE0 | self.joypad1.read(),
+            JOYPAD_2 => (address >> 8) as u8 & 0xE0 | self.joypad2.read(),
+
+            APU_STATUS => self.apu.read_status(),
+
+            // The other APU registers are write only
+            APU_IO_REGISTERS..=APU_IO_REGISTERS_END => 0,
+
+            // Expansion ROM, SRAM and PRG-ROM all belong to the cartridge
+            CARTRIDGE..=CARTRIDGE_END => self.mapper.cpu_read(address),
+        }
+    }
+
+    /// Registers are left alone and read back as $FF, the same as in the
+    /// nestest.log reference trace
+    fn memory_peek(&mut self, address: u16) -> u8 {
+        match address {
+            RAM..=RAM_MIRRORS_END => self.cpu_vram[(address & 0b0000_0111_1111_1111) as usize],
+            PPU_REGISTERS..=APU_IO_REGISTERS_END => 0xFF,
+            CARTRIDGE..=CARTRIDGE_END => self.mapper.cpu_read(address),
+        }
+    }
+
+    fn memory_write(&mut self, address: u16, data: u8) {
+        match address {
+            RAM..=RAM_MIRRORS_END => {
+                let mirror_down_address = address & 0b0000_0111_1111_1111;
+                self.cpu_vram[mirror_down_address as usize] = data;
+            }
+
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => {
+                self.ppu.write_register(self.mapper.as_mut(), address, data)
+            }
+
+            OAM_DMA => self.oam_dma(data),
+
+            // One strobe line goes to both controller ports
+            JOYPAD_1 => {
+                self.joypad1.write(data);
+                self.joypad2.write(data);
+            }
+
+            APU_IO_REGISTERS..=APU_IO_REGISTERS_END => self.apu.write_register(address, data),
+
+            // The mapper decides what a write means, usually a bank switch
+            CARTRIDGE..=CARTRIDGE_END => self.mapper.cpu_write(address, data),
+        }
+    }
+
+    /// The PPU runs three dots for every CPU cycle and the APU one. DMC
+    /// sample fetches take the bus from the CPU for 4 cycles each.
+    fn tick(&mut self, cycles: u8) {
+        for _ in 0..cycles {
+            self.cycles += 1;
+            for _ in 0..3 {
+                self.ppu.tick(self.mapper.as_mut());
+            }
+            self.apu.tick();
+
+            if let Some(address) = self.apu.dmc_sample_request() {
+                let data = self.memory_read(address);
+                self.apu.fill_dmc_sample(data);
+                self.stall_cycles += 4;
+            }
+        }
+    }
+
+    fn take_stall_cycles(&mut self) -> u16 {
+        std::mem::take(&mut self.stall_cycles)
+    }
+
+    fn nmi_line(&self) -> bool {
+        self.ppu.nmi_line()
+    }
+
+    fn ppu_position(&self) -> Option<(u16, u16)> {
+        Some((self.ppu.scanline, self.ppu.dot))
+    }
+
+    fn irq_line(&self) -> IrqSource {
+        let mut sources = self.apu.irq();
+        sources.set(IrqSource::MAPPER, self.mapper.irq());
+        sources
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::test::test_rom;
+    use crate::cpu::CPU;
+    use crate::joypad::JoypadButton;
+    use crate::mapper;
+
+    fn test_bus(rom: crate::cartridge::Rom) -> NesBus {
+        NesBus::new(mapper::from_rom(rom).unwrap())
+    }
+
+    #[test]
+    fn test_ram_is_mirrored() {
+        let mut bus = test_bus(test_rom(vec![]));
+        bus.memory_write(0x0012, 0x34);
+
+        assert_eq!(bus.memory_read(0x0812), 0x34);
+        assert_eq!(bus.memory_read(0x1012), 0x34);
+        assert_eq!(bus.memory_read(0x1812), 0x34);
+
+        bus.memory_write(0x1fff, 0x56);
+        assert_eq!(bus.memory_read(0x07ff), 0x56);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut bus = test_bus(test_rom(vec![]));
+        bus.memory_write(0x2000, 0x80);
+        bus.memory_write(0x4015, 0x01);
+        bus.memory_write(0x4003, 0x08);
+        assert_ne!(bus.memory_read(0x4015), 0);
+        bus.memory_write(0x0012, 0x34);
+
+        bus.reset();
+
+        assert!(bus.ppu.ctrl.is_empty());
+        assert_eq!(bus.memory_read(0x4015), 0);
+        assert_eq!(bus.memory_read(0x0012), 0x34);
+    }
+
+    #[test]
+    fn test_16kb_prg_rom_is_mirrored() {
+        let mut rom = test_rom(vec![]);
+        rom.prg_rom = vec![0; 0x4000];
+        rom.prg_rom[0x3ffc] = 0x00;
+        rom.prg_rom[0x3ffd] = 0xc0;
+        let mut bus = test_bus(rom);
+
+        assert_eq!(bus.memory_read_u16(0xbffc), 0xc000);
+        assert_eq!(bus.memory_read_u16(0xfffc), 0xc000);
+    }
+
+    #[test]
+    fn test_prg_rom_is_read_only() {
+        let mut bus = test_bus(test_rom(vec![0xea; 0x10]));
+        bus.memory_write(0x8000, 0x00);
+
+        assert_eq!(bus.memory_read(0x8000), 0xea);
+    }
+
+    #[test]
+    fn test_cartridge_reset_vector() {
+        let mut bus = test_bus(test_rom(vec![0xa9, 0x05, 0x00]));
+
+        assert_eq!(bus.memory_read_u16(0xfffc), 0x8000);
+        assert_eq!(bus.memory_read(0x8001), 0x05);
+    }
+
+    #[test]
+    fn test_mapper_drives_irq_line() {
+        let mut rom = test_rom(vec![]);
+        rom.mapper = 4;
+        let mut bus = test_bus(rom);
+        bus.memory_write(0xc000, 0);
+        bus.memory_write(0xc001, 0);
+        bus.memory_write(0xe001, 0);
+        assert_eq!(bus.irq_line(), IrqSource::empty());
+
+        bus.mapper.notify_ppu_address(0x1000, 260);
+        assert_eq!(bus.irq_line(), IrqSource::MAPPER);
+
+        bus.memory_write(0xe000, 0);
+        assert_eq!(bus.irq_line(), IrqSource::empty());
+    }
+
+    #[test]
+    fn test_ppu_registers_are_mirrored() {
+        let mut bus = test_bus(test_rom(vec![]));
+        bus.memory_write(0x3ffe, 0x23);
+        bus.memory_write(0x2006, 0x05);
+        bus.memory_write(0x3fff, 0x66);
+
+        assert_eq!(bus.ppu.vram[0x0305], 0x66);
+    }
+
+    #[test]
+    fn test_ppu_raises_nmi_in_vblank() {
+        let mut bus = test_bus(test_rom(vec![]));
+        bus.memory_write(0x2000, 0x80);
+
+        // Scanline 241 dot 1 is the 82183rd dot, reached during the
+        // 27395th CPU cycle
+        for _ in 0..27391 / 7 {
+            bus.tick(7);
+        }
+        assert!(!bus.nmi_line());
+        bus.tick(4);
+        assert!(bus.nmi_line());
+
+        assert_eq!(bus.memory_read(0x2002) & 0x80, 0x80);
+        assert!(!bus.nmi_line());
+    }
+
+    #[test]
+    fn test_oam_dma_copies_page_from_oamaddr() {
+        let mut bus = test_bus(test_rom(vec![]));
+        for offset in 0..=0xff {
+            bus.memory_write(0x0200 + offset, offset as u8);
+        }
+        bus.memory_write(0x2003, 0x10);
+        bus.memory_write(0x4014, 0x02);
+
+        assert_eq!(bus.ppu.oam_data[0x10], 0x00);
+        assert_eq!(bus.ppu.oam_data[0xff], 0xef);
+        assert_eq!(bus.ppu.oam_data[0x00], 0xf0);
+        // Unused attribute bits read back as 0
+        assert_eq!(bus.ppu.oam_data[0x16], 0x02);
+        assert_eq!(bus.ppu.oam_addr, 0x10);
+    }
+
+    #[test]
+    fn test_oam_dma_stall_depends_on_cycle_parity() {
+        let mut bus = test_bus(test_rom(vec![]));
+        bus.tick(2);
+        bus.memory_write(0x4014, 0x02);
+        assert_eq!(bus.take_stall_cycles(), 513);
+        assert_eq!(bus.take_stall_cycles(), 0);
+
+        bus.tick(1);
+        bus.memory_write(0x4014, 0x02);
+        assert_eq!(bus.take_stall_cycles(), 514);
+    }
+
+    #[test]
+    fn test_oam_dma_stalls_cpu() {
+        // LDA #$02, STA $4014, BRK
+        let rom = test_rom(vec![0xa9, 0x02, 0x8d, 0x14, 0x40, 0x00]);
+        let mut cpu = CPU::with_test_mode(test_bus(rom));
+        cpu.reset();
+        cpu.run().unwrap();
+
+        // The write lands at the end of cycle 13, an odd cycle
+        assert_eq!(cpu.cycles, 7 + 2 + 4 + 514);
+        assert_eq!(cpu.bus.ppu.cycles, cpu.cycles * 3);
+    }
+
+    #[test]
+    fn test_joypads() {
+        let mut bus = test_bus(test_rom(vec![]));
+        bus.joypad1.set_buttons(JoypadButton::BUTTON_A);
+        bus.joypad2.set_buttons(JoypadButton::BUTTON_B);
+        bus.memory_write(0x4016, 1);
+        bus.memory_write(0x4016, 0);
+
+        assert_eq!(bus.memory_read(0x4016), 0x41);
+        assert_eq!(bus.memory_read(0x4016), 0x40);
+        assert_eq!(bus.memory_read(0x4017), 0x40);
+        assert_eq!(bus.memory_read(0x4017), 0x41);
+    }
+
+    #[test]
+    fn test_dmc_fetches_stall_cpu_and_raise_irq() {
+        let mut bus = test_bus(test_rom(vec![]));
+        // IRQ on, fastest rate, one byte sample from $C000
+        bus.memory_write(0x4010, 0x8f);
+        bus.memory_write(0x4015, 0b0001_0000);
+        bus.tick(1);
+
+        assert_eq!(bus.take_stall_cycles(), 4);
+        assert_eq!(bus.irq_line(), IrqSource::APU_DMC);
+        bus.tick(100);
+        assert_eq!(bus.take_stall_cycles(), 0);
+
+        bus.memory_write(0x4015, 0);
+        assert_eq!(bus.irq_line(), IrqSource::empty());
+    }
+
+    #[test]
+    fn test_apu_frame_irq() {
+        let mut bus = test_bus(test_rom(vec![]));
+        for _ in 0..29829 / 3 {
+            bus.tick(3);
+        }
+        assert_eq!(bus.irq_line(), IrqSource::APU_FRAME);
+
+        assert_eq!(bus.memory_read(0x4015), 0x40);
+        assert_eq!(bus.irq_line(), IrqSource::empty());
+
+        // Inhibited from $4017, which the joypad does not see
+        bus.memory_write(0x4017, 0x40);
+        for _ in 0..29830 / 2 {
+            bus.tick(2);
+        }
+        assert_eq!(bus.irq_line(), IrqSource::empty());
+    }
+
+    #[test]
+    fn test_flat_ram_covers_whole_address_space() {
+        let mut bus = FlatRam::new();
+        bus.memory_write_u16(0xfffe, 0x1234);
+
+        assert_eq!(bus.memory_read(0xffff), 0x12);
+        assert_eq!(bus.memory_read_u16(0xfffe), 0x1234);
+    }
+}
@@ -0,0 +1,493 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// iNES File Layout
+/// ================
+/// Header (16 bytes)
+/// Trainer, if present (0 or 512 bytes)
+/// PRG-ROM data (16384 * x bytes)
+/// CHR-ROM data, if present (8192 * y bytes)
+///
+/// Header
+/// ======
+/// Byte | Contents
+/// 0-3  | Constant $4E $45 $53 $1A ("NES" followed by MS-DOS end-of-file)
+/// 4    | Size of PRG-ROM in 16KB units
+/// 5    | Size of CHR-ROM in 8KB units (0 means the board uses CHR-RAM)
+/// 6    | Flags 6 - Mapper low nibble, mirroring, battery, trainer
+/// 7    | Flags 7 - Mapper middle nibble, console type, NES 2.0 identifier
+/// 8    | iNES: PRG-RAM size in 8KB units
+///      | NES 2.0: Mapper high nibble and submapper
+/// 9    | iNES: TV system
+///      | NES 2.0: PRG-ROM and CHR-ROM size high nibbles
+/// 10   | NES 2.0: PRG-RAM and PRG-NVRAM shift counts
+/// 11   | NES 2.0: CHR-RAM and CHR-NVRAM shift counts
+/// 12   | NES 2.0: CPU/PPU timing
+/// 13   | NES 2.0: Vs. System type or extended console type
+/// 14   | NES 2.0: Miscellaneous ROMs
+/// 15   | NES 2.0: Default expansion device
+///
+/// Reference: https://www.nesdev.org/wiki/INES and
+/// https://www.nesdev.org/wiki/NES_2.0
+const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+const PRG_ROM_PAGE_SIZE: usize = 16384;
+const CHR_ROM_PAGE_SIZE: usize = 8192;
+const PRG_RAM_PAGE_SIZE: usize = 8192;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Mirroring {
+    Vertical,
+    Horizontal,
+    FourScreen,
+    /// Every nametable address maps to the first 1KB of VRAM
+    SingleScreenLower,
+    /// Every nametable address maps to the second 1KB of VRAM
+    SingleScreenUpper,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TvSystem {
+    Ntsc,
+    Pal,
+    MultiRegion,
+    Dendy,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RomFormat {
+    INes,
+    Nes20,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RomError {
+    /// The file is too short to hold a 16 byte header
+    MissingHeader,
+    /// The header does not start with `NES\x1A`
+    InvalidMagic,
+    /// The file ends before all of the data the header declares
+    Truncated { expected: usize, actual: usize },
+    /// The header declares no PRG-ROM, so there is no program to run
+    EmptyPrgRom,
+    /// The file could not be read
+    Io(io::ErrorKind),
+    /// The cartridge board is not emulated
+    UnsupportedMapper(u16),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::MissingHeader => write!(f, "file is too short for an iNES header"),
+            RomError::InvalidMagic => write!(f, "file is not in iNES format"),
+            RomError::Truncated { expected, actual } => write!(
+                f,
+                "file is truncated, header declares {} bytes but only {} are present",
+                expected, actual
+            ),
+            RomError::EmptyPrgRom => write!(f, "header declares no PRG-ROM"),
+            RomError::Io(kind) => write!(f, "unable to read ROM: {}", kind),
+            RomError::UnsupportedMapper(mapper) => write!(f, "mapper {} is not supported", mapper),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+impl From<io::Error> for RomError {
+    fn from(error: io::Error) -> Self {
+        RomError::Io(error.kind())
+    }
+}
+
+pub struct Rom {
+    pub format: RomFormat,
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>,
+    /// 512 bytes the board copies to $7000 before the game starts
+    pub trainer: Option<Vec<u8>>,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub screen_mirroring: Mirroring,
+    /// The board keeps its PRG-RAM (or other memory) powered by a battery
+    pub battery: bool,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub tv_system: TvSystem,
+}
+
+impl Rom {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Rom, RomError> {
+        let raw = fs::read(path)?;
+        Rom::new(&raw)
+    }
+
+    pub fn new(raw: &[u8]) -> Result<Rom, RomError> {
+        if raw.len() < HEADER_SIZE {
+            return Err(RomError::MissingHeader);
+        }
+        if raw[0..4] != NES_TAG {
+            return Err(RomError::InvalidMagic);
+        }
+
+        let flags_6 = raw[6];
+        let flags_7 = raw[7];
+        let format = if flags_7 & 0b0000_1100 == 0b0000_1000 {
+            RomFormat::Nes20
+        } else {
+            RomFormat::INes
+        };
+
+        let four_screen = flags_6 & 0b1000 != 0;
+        let vertical_mirroring = flags_6 & 0b1 != 0;
+        let screen_mirroring = match (four_screen, vertical_mirroring) {
+            (true, _) => Mirroring::FourScreen,
+            (false, true) => Mirroring::Vertical,
+            (false, false) => Mirroring::Horizontal,
+        };
+        let battery = flags_6 & 0b10 != 0;
+        let has_trainer = flags_6 & 0b100 != 0;
+
+        let rom = match format {
+            RomFormat::Nes20 => {
+                let mapper = (flags_6 >> 4) as u16
+                    | (flags_7 & 0b1111_0000) as u16
+                    | ((raw[8] & 0b1111) as u16) << 8;
+
+                Rom {
+                    format,
+                    prg_rom: Vec::new(),
+                    chr_rom: Vec::new(),
+                    trainer: None,
+                    mapper,
+                    submapper: raw[8] >> 4,
+                    screen_mirroring,
+                    battery,
+                    prg_ram_size: shift_count_size(raw[10] & 0b1111),
+                    prg_nvram_size: shift_count_size(raw[10] >> 4),
+                    chr_ram_size: shift_count_size(raw[11] & 0b1111),
+                    chr_nvram_size: shift_count_size(raw[11] >> 4),
+                    tv_system: match raw[12] & 0b11 {
+                        0 => TvSystem::Ntsc,
+                        1 => TvSystem::Pal,
+                        2 => TvSystem::MultiRegion,
+                        _ => TvSystem::Dendy,
+                    },
+                }
+            }
+            RomFormat::INes => {
+                // Some old dumping tools wrote their name into bytes 7-15
+                // (e.g. "DiskDude!"). If the unused tail of the header is
+                // not blank, byte 7 can not be trusted for the mapper.
+                let mapper_high = if raw[12..16].iter().all(|byte| *byte == 0) {
+                    flags_7 & 0b1111_0000
+                } else {
+                    0
+                };
+                let mapper = ((flags_6 >> 4) | mapper_high) as u16;
+
+                // A value of 0 infers 8KB for compatibility
+                let prg_ram_size = PRG_RAM_PAGE_SIZE * raw[8].max(1) as usize;
+
+                Rom {
+                    format,
+                    prg_rom: Vec::new(),
+                    chr_rom: Vec::new(),
+                    trainer: None,
+                    mapper,
+                    submapper: 0,
+                    screen_mirroring,
+                    battery,
+                    prg_ram_size: if battery { 0 } else { prg_ram_size },
+                    prg_nvram_size: if battery { prg_ram_size } else { 0 },
+                    chr_ram_size: if raw[5] == 0 { CHR_ROM_PAGE_SIZE } else { 0 },
+                    chr_nvram_size: 0,
+                    tv_system: if raw[9] & 0b1 != 0 {
+                        TvSystem::Pal
+                    } else {
+                        TvSystem::Ntsc
+                    },
+                }
+            }
+        };
+
+        let (prg_rom_size, chr_rom_size) = match format {
+            RomFormat::Nes20 => (
+                nes20_rom_size(raw[4], raw[9] & 0b1111, PRG_ROM_PAGE_SIZE),
+                nes20_rom_size(raw[5], raw[9] >> 4, CHR_ROM_PAGE_SIZE),
+            ),
+            RomFormat::INes => (
+                raw[4] as usize * PRG_ROM_PAGE_SIZE,
+                raw[5] as usize * CHR_ROM_PAGE_SIZE,
+            ),
+        };
+
+        if prg_rom_size == 0 {
+            return Err(RomError::EmptyPrgRom);
+        }
+
+        let trainer_size = if has_trainer { TRAINER_SIZE } else { 0 };
+        let trainer_start = HEADER_SIZE;
+        let prg_rom_start = trainer_start + trainer_size;
+        let chr_rom_start = prg_rom_start.saturating_add(prg_rom_size);
+        let end = chr_rom_start.saturating_add(chr_rom_size);
+
+        if raw.len() < end {
+            return Err(RomError::Truncated {
+                expected: end,
+                actual: raw.len(),
+            });
+        }
+
+        Ok(Rom {
+            prg_rom: raw[prg_rom_start..chr_rom_start].to_vec(),
+            chr_rom: raw[chr_rom_start..end].to_vec(),
+            trainer: has_trainer.then(|| raw[trainer_start..prg_rom_start].to_vec()),
+            ..rom
+        })
+    }
+}
+
+/// NES 2.0 RAM sizes are stored as a shift count, 64 << n bytes or none
+fn shift_count_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
+}
+
+/// NES 2.0 ROM sizes are normally a 12 bit count of pages. If the high
+/// nibble is $F the low byte instead holds an exponent and multiplier,
+/// EEEEEEMM giving 2^E * (MM * 2 + 1) bytes.
+fn nes20_rom_size(lsb: u8, msb: u8, page_size: usize) -> usize {
+    if msb == 0b1111 {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0b11) as usize * 2 + 1;
+        1usize
+            .checked_shl(exponent)
+            .and_then(|size| size.checked_mul(multiplier))
+            .unwrap_or(usize::MAX)
+    } else {
+        (((msb as usize) << 8) | lsb as usize) * page_size
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    struct TestRom {
+        header: Vec<u8>,
+        trainer: Option<Vec<u8>>,
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+    }
+
+    fn create_rom(rom: TestRom) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend(&rom.header);
+        if let Some(trainer) = rom.trainer {
+            result.extend(trainer);
+        }
+        result.extend(&rom.prg_rom);
+        result.extend(&rom.chr_rom);
+        result
+    }
+
+    /// An NROM image with the program at $8000 and the reset vector
+    /// pointing at it, for tests that need a real cartridge on the bus.
+    pub fn test_rom(program: Vec<u8>) -> Rom {
+        let mut prg_rom = vec![0; 2 * PRG_ROM_PAGE_SIZE];
+        prg_rom[..program.len()].copy_from_slice(&program);
+        prg_rom[0x7ffc] = 0x00;
+        prg_rom[0x7ffd] = 0x80;
+
+        let raw = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x01, 00, 00, 00, 00, 00, 00, 00, 00, 00,
+            ],
+            trainer: None,
+            prg_rom,
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+        });
+
+        Rom::new(&raw).unwrap()
+    }
+
+    #[test]
+    fn test_ines() {
+        let raw = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x31, 00, 00, 00, 00, 00, 00, 00, 00, 00,
+            ],
+            trainer: None,
+            prg_rom: vec![1; 2 * PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+        });
+
+        let rom = Rom::new(&raw).unwrap();
+
+        assert_eq!(rom.format, RomFormat::INes);
+        assert_eq!(rom.chr_rom, vec![2; CHR_ROM_PAGE_SIZE]);
+        assert_eq!(rom.prg_rom, vec![1; 2 * PRG_ROM_PAGE_SIZE]);
+        assert_eq!(rom.mapper, 3);
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+        assert_eq!(rom.prg_ram_size, PRG_RAM_PAGE_SIZE);
+        assert_eq!(rom.chr_ram_size, 0);
+        assert_eq!(rom.tv_system, TvSystem::Ntsc);
+        assert!(rom.trainer.is_none());
+    }
+
+    #[test]
+    fn test_ines_with_trainer_battery_and_chr_ram() {
+        let raw = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x01, 0x00, 0x1E, 0x10, 0x02, 0x01, 00, 00, 00, 00, 00, 00,
+            ],
+            trainer: Some(vec![0xAA; TRAINER_SIZE]),
+            prg_rom: vec![1; PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![],
+        });
+
+        let rom = Rom::new(&raw).unwrap();
+
+        assert_eq!(rom.mapper, 0x11);
+        assert_eq!(rom.screen_mirroring, Mirroring::FourScreen);
+        assert!(rom.battery);
+        assert_eq!(rom.trainer, Some(vec![0xAA; TRAINER_SIZE]));
+        assert_eq!(rom.prg_rom, vec![1; PRG_ROM_PAGE_SIZE]);
+        assert_eq!(rom.prg_nvram_size, 2 * PRG_RAM_PAGE_SIZE);
+        assert_eq!(rom.prg_ram_size, 0);
+        assert_eq!(rom.chr_ram_size, CHR_ROM_PAGE_SIZE);
+        assert_eq!(rom.tv_system, TvSystem::Pal);
+    }
+
+    #[test]
+    fn test_ines_ignores_dirty_header_mapper_nibble() {
+        let mut header = b"NES\x1A\x01\x01\x10DiskDude!".to_vec();
+        header.truncate(HEADER_SIZE);
+        let raw = create_rom(TestRom {
+            header,
+            trainer: None,
+            prg_rom: vec![1; PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+        });
+
+        let rom = Rom::new(&raw).unwrap();
+
+        assert_eq!(rom.mapper, 1);
+    }
+
+    #[test]
+    fn test_nes20() {
+        let raw = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x00, 0x40, 0x48, 0x31, 0x00, 0x07, 0x70, 0x01, 00,
+                00, 00,
+            ],
+            trainer: None,
+            prg_rom: vec![1; 2 * PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![],
+        });
+
+        let rom = Rom::new(&raw).unwrap();
+
+        assert_eq!(rom.format, RomFormat::Nes20);
+        assert_eq!(rom.mapper, 0x144);
+        assert_eq!(rom.submapper, 3);
+        assert_eq!(rom.screen_mirroring, Mirroring::Horizontal);
+        assert_eq!(rom.prg_ram_size, 8192);
+        assert_eq!(rom.prg_nvram_size, 0);
+        assert_eq!(rom.chr_ram_size, 0);
+        assert_eq!(rom.chr_nvram_size, 8192);
+        assert_eq!(rom.tv_system, TvSystem::Pal);
+    }
+
+    #[test]
+    fn test_nes20_exponent_multiplier_size() {
+        // 2^14 * (1 * 2 + 1) = 48KB of PRG-ROM
+        let raw = create_rom(TestRom {
+            header: vec![
+                0x4E,
+                0x45,
+                0x53,
+                0x1A,
+                0b0011_1001,
+                0x00,
+                0x00,
+                0x08,
+                0x00,
+                0x0F,
+                00,
+                00,
+                00,
+                00,
+                00,
+                00,
+            ],
+            trainer: None,
+            prg_rom: vec![1; 3 * PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![],
+        });
+
+        let rom = Rom::new(&raw).unwrap();
+
+        assert_eq!(rom.prg_rom.len(), 3 * PRG_ROM_PAGE_SIZE);
+    }
+
+    #[test]
+    fn test_invalid_magic() {
+        let raw = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x00, 0x01, 0x01, 0x00, 00, 00, 00, 00, 00, 00, 00, 00, 00,
+            ],
+            trainer: None,
+            prg_rom: vec![1; PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+        });
+
+        assert_eq!(Rom::new(&raw).err(), Some(RomError::InvalidMagic));
+        assert_eq!(Rom::new(&raw[..8]).err(), Some(RomError::MissingHeader));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let raw = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x00, 00, 00, 00, 00, 00, 00, 00, 00, 00,
+            ],
+            trainer: None,
+            prg_rom: vec![1; PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![],
+        });
+
+        assert_eq!(
+            Rom::new(&raw).err(),
+            Some(RomError::Truncated {
+                expected: HEADER_SIZE + 2 * PRG_ROM_PAGE_SIZE + CHR_ROM_PAGE_SIZE,
+                actual: HEADER_SIZE + PRG_ROM_PAGE_SIZE,
+            })
+        );
+    }
+
+    #[test]
+    fn test_empty_prg_rom() {
+        let raw = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x00, 0x01, 0x00, 00, 00, 00, 00, 00, 00, 00, 00, 00,
+            ],
+            trainer: None,
+            prg_rom: vec![],
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+        });
+
+        assert_eq!(Rom::new(&raw).err(), Some(RomError::EmptyPrgRom));
+    }
+}
@@ -1,16 +1,214 @@
+use crate::bus::{Bus, NesBus};
+use crate::interrupt::{self, InterruptType, IrqSource};
 use crate::opcodes;
+use bitflags::bitflags;
 use hashbrown::HashMap;
+use std::fmt;
+use std::ops::ControlFlow;
 
-pub struct CPU {
+bitflags! {
+    /// Status Register (P)
+    /// ===================
+    ///  7 6 5 4 3 2 1 0
+    ///  N V _ B D I Z C
+    ///
+    /// Bits 4 and 5 are not real storage in the 2A03. They only show up in
+    /// the copy of the register pushed to the stack, see `CpuFlags::pushed`
+    /// and `CpuFlags::pulled`.
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub struct CpuFlags: u8 {
+        const CARRY             = 0b0000_0001;
+        const ZERO              = 0b0000_0010;
+        const INTERRUPT_DISABLE = 0b0000_0100;
+        const DECIMAL_MODE      = 0b0000_1000;
+        const BREAK             = 0b0001_0000;
+        const UNUSED            = 0b0010_0000;
+        const OVERFLOW          = 0b0100_0000;
+        const NEGATIVE          = 0b1000_0000;
+    }
+}
+
+impl CpuFlags {
+    /// State of the register at power on, interrupts are disabled and
+    /// bit 5 reads back as set.
+    pub fn power_on_state() -> Self {
+        CpuFlags::INTERRUPT_DISABLE | CpuFlags::UNUSED
+    }
+
+    /// Value written to the stack when the status is pushed. The B flag is
+    /// set when pushed by PHP or BRK and clear when pushed by a hardware
+    /// interrupt, bit 5 is always set.
+    pub fn pushed(self, break_flag: bool) -> u8 {
+        let mut status = self | CpuFlags::UNUSED;
+        status.set(CpuFlags::BREAK, break_flag);
+        status.bits()
+    }
+
+    /// Register contents after the status is pulled by PLP or RTI. B and
+    /// bit 5 of the pulled value are ignored.
+    pub fn pulled(value: u8) -> Self {
+        let mut status = CpuFlags::from_bits_retain(value);
+        status.remove(CpuFlags::BREAK);
+        status.insert(CpuFlags::UNUSED);
+        status
+    }
+}
+
+/// Flags print as `NV-BDIZC`, upper case when set and lower case when clear
+impl fmt::Display for CpuFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = [
+            (CpuFlags::NEGATIVE, 'N'),
+            (CpuFlags::OVERFLOW, 'V'),
+            (CpuFlags::UNUSED, '-'),
+            (CpuFlags::BREAK, 'B'),
+            (CpuFlags::DECIMAL_MODE, 'D'),
+            (CpuFlags::INTERRUPT_DISABLE, 'I'),
+            (CpuFlags::ZERO, 'Z'),
+            (CpuFlags::CARRY, 'C'),
+        ];
+
+        for (flag, symbol) in flags {
+            if flag == CpuFlags::UNUSED || self.contains(flag) {
+                write!(f, "{}", symbol)?;
+            } else {
+                write!(f, "{}", symbol.to_ascii_lowercase())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for CpuFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CpuFlags({} ${:02X})", self, self.bits())
+    }
+}
+
+/// Stack
+/// =====
+/// The stack lives in page one of memory ($0100 - $01FF) and grows
+/// downwards. The stack pointer holds the low byte of the next free slot
+/// and wraps around within the page rather than spilling into page zero
+/// or page two.
+///
+/// At power on the stack pointer is $00, the reset sequence then performs
+/// three dummy pushes (without writing) leaving it at $FD.
+const STACK: u16 = 0x0100;
+const STACK_POWER_ON: u8 = 0x00;
+
+/// Unofficial Opcodes
+/// ==================
+/// The 105 opcodes missing from the documented instruction set still do
+/// something on the 2A03, and a handful of games and test ROMs rely on
+/// them. The 12 JAM (or KIL) opcodes lock the CPU up until it is reset.
+///
+/// Mode      | Unofficial opcodes | JAM
+/// Execute   | Executed           | Halts
+/// Trap      | Trap               | Traps
+/// HaltOnJam | Trap               | Halts
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UnofficialOpcodes {
+    #[default]
+    Execute,
+    Trap,
+    HaltOnJam,
+}
+
+/// Why the CPU stopped part way through a run. The program counter is the
+/// address of the instruction that caused it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuError {
+    /// The opcode is not in the opcode table
+    UnknownOpcode { pc: u16, opcode: u8 },
+    /// An unofficial opcode that `unofficial_opcodes` says to trap on
+    UnofficialOpcode { pc: u16, opcode: u8 },
+    /// A JAM opcode halted the CPU, only a reset gets it going again
+    Jam { pc: u16 },
+    /// The opcode table pairs an instruction with a mode it cannot use
+    InvalidAddressing { pc: u16, mode: AddressingMode },
+    /// The bus could not serve an access to this address
+    BusFault { pc: u16, address: u16 },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuError::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode ${:02X} at ${:04X}", opcode, pc)
+            }
+            CpuError::UnofficialOpcode { pc, opcode } => {
+                write!(f, "unofficial opcode ${:02X} at ${:04X}", opcode, pc)
+            }
+            CpuError::Jam { pc } => write!(f, "CPU jammed at ${:04X}", pc),
+            CpuError::InvalidAddressing { pc, mode } => {
+                write!(f, "invalid addressing mode {:?} at ${:04X}", mode, pc)
+            }
+            CpuError::BusFault { pc, address } => {
+                write!(f, "bus fault at ${:04X} accessing ${:04X}", pc, address)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
+
+/// What a call to `CPU::step` executed
+#[derive(Debug, Clone, Copy)]
+pub struct Step {
+    /// Address the instruction was fetched from
+    pub pc: u16,
+    pub opcode: &'static opcodes::OpCode,
+    /// Cycles taken, including any stall or interrupt sequence that ran
+    /// before the instruction
+    pub cycles: u64,
+}
+
+pub struct CPU<B: Bus> {
     pub register_a: u8,
     pub register_x: u8,
     pub register_y: u8,
-    pub status: u8,
+    pub status: CpuFlags,
     pub program_counter: u16,
-    memory: [u8; 0xFFFF], // -1
+    pub stack_pointer: u8,
+    /// Total CPU cycles executed since power on
+    pub cycles: u64,
+    pub bus: B,
+    /// How to treat opcodes outside the documented set
+    pub unofficial_opcodes: UnofficialOpcodes,
+    /// Test mode, BRK halts the CPU on the instruction itself rather than
+    /// entering the interrupt handler
+    test_mode: bool,
+    /// Set by BRK in test mode, cleared by a reset
+    halted: bool,
+    /// Set by JAM, only a reset gets the CPU going again
+    jammed: bool,
+    /// Set by instructions which load the program counter, so `execute`
+    /// doesn't step over their operands a second time
+    jumped: bool,
+    /// A problem found part way through an instruction, reported once it
+    /// finishes
+    fault: Option<CpuError>,
+
+    // Interrupt lines, see `set_nmi_line` and `set_irq_line`
+    nmi_line: bool,
+    nmi_level: bool,
+    nmi_pending: bool,
+    irq_line: IrqSource,
+    irq_inhibit: bool,
+}
+
+/// Indexed addressing takes an extra cycle to fix up the high byte of the
+/// address when adding the index carries into the next page.
+fn page_crossed(base: u16, address: u16) -> bool {
+    base & 0xFF00 != address & 0xFF00
 }
 
-#[derive(Debug)]
+/// Bits XAA and the immediate LAX OR into the accumulator. They come from
+/// analogue effects inside the chip, $EE is what most consoles show.
+const UNSTABLE_MAGIC: u8 = 0xEE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AddressingMode {
     Immediate,
     ZeroPage,
@@ -19,51 +217,46 @@ pub enum AddressingMode {
     Absolute,
     AbsoluteX,
     AbsoluteY,
+    Indirect,
     IndirectX,
     IndirectY,
+    Relative,
+    Accumulator,
     NoneAddressing,
 }
 
-trait Memory {
-    fn memory_read(&self, address: u16) -> u8;
-
-    fn memory_write(&mut self, address: u16, data: u8);
-
-    /// Implement NES Little-Endian addressing for reading
-    fn memory_read_u16(&self, position: u16) -> u16 {
-        let lsb = self.memory_read(position) as u16;
-        let msb = self.memory_read(position + 1) as u16;
-        (msb << 8) | (lsb as u16) //
-    }
-
-    /// Implement NES Little-Endian addressing for writing
-    fn memeory_read_u16(&mut self, position: u16, data: u16) {
-        let msb = (data >> 8) as u8;
-        let lsb = (data & 0xFF) as u8;
-        self.memory_write(position, lsb);
-        self.memory_write(position + 1, msb);
-    }
-}
-
-impl Memory for CPU {
-    fn memory_read(&self, address: u16) -> u8 {
-        self.memory[address as usize]
-    }
-
-    fn memory_write(&mut self, address: u16, data: u8) {
-        self.memory[address as usize] = data;
-    }
-}
-
-impl CPU {
-    pub fn new() -> Self {
+impl<B: Bus> CPU<B> {
+    pub fn new(bus: B) -> Self {
         CPU {
             register_a: 0,
             register_x: 0,
             register_y: 0,
-            status: 0,
+            status: CpuFlags::power_on_state(),
             program_counter: 0,
-            memory: [0; 0xFFFF], // -1
+            stack_pointer: STACK_POWER_ON,
+            cycles: 0,
+            bus,
+            unofficial_opcodes: UnofficialOpcodes::default(),
+            test_mode: false,
+            halted: false,
+            jammed: false,
+            jumped: false,
+            fault: None,
+            nmi_line: false,
+            nmi_level: false,
+            nmi_pending: false,
+            irq_line: IrqSource::empty(),
+            irq_inhibit: true,
+        }
+    }
+
+    /// Creates a CPU in test mode, where BRK ends the program. The CPU
+    /// halts with the program counter on the BRK and never enters the
+    /// interrupt handler.
+    pub fn with_test_mode(bus: B) -> Self {
+        CPU {
+            test_mode: true,
+            ..CPU::new(bus)
         }
     }
 
@@ -72,31 +265,33 @@ impl CPU {
     /// The 6502 uses a 16-bit address bus, where each byte is represented by
     /// two hex characters from $0000 - $FFFF
     /// Current reference: https://skilldrick.github.io/easy6502/#addressing
-    fn get_operand_address(&self, mode: &AddressingMode) -> u16 {
+    ///
+    /// Alongside the address this reports whether indexing crossed a page
+    /// boundary, which costs read instructions an extra cycle.
+    fn get_operand_address(&mut self, mode: &AddressingMode) -> (u16, bool) {
         match mode {
-            AddressingMode::Immediate => self.program_counter,
+            AddressingMode::Immediate => (self.program_counter, false),
 
             // Zero Page (C0)
             // ==============
             // All instructions which support absolute addressing (excluding
             // the jump instructions) also have the option to take a single-
             // byte address.
-            AddressingMode::ZeroPage => self.memory_read(self.program_counter) as u16,
+            AddressingMode::ZeroPage => (self.bus.memory_read(self.program_counter) as u16, false),
 
             // Absolute (C000)
             // ===============
             // With absolute addressing, the full memory locatoin is used as
             // the argument to the instruction.
-            AddressingMode::Absolute => self.memory_read_u16(self.program_counter),
+            AddressingMode::Absolute => (self.bus.memory_read_u16(self.program_counter), false),
 
             // Zero Page X (C0, X)
             // ===================
             // In this mode, a zero page address is given, and then the value
             // of the X register is added.
             AddressingMode::ZeroPageX => {
-                let position = self.memory_read(self.program_counter);
-                let address = position.wrapping_add(self.register_x) as u16;
-                address
+                let position = self.bus.memory_read(self.program_counter);
+                (position.wrapping_add(self.register_x) as u16, false)
             }
 
             // Zero Page Y (C0, Y)
@@ -104,18 +299,17 @@ impl CPU {
             // This is the equivalent of zero page, X, but can only be used
             // with LDX and STX
             AddressingMode::ZeroPageY => {
-                let position = self.memory_read(self.program_counter);
-                let address = position.wrapping_add(self.register_y) as u16;
-                address
+                let position = self.bus.memory_read(self.program_counter);
+                (position.wrapping_add(self.register_y) as u16, false)
             }
 
             // Absolute X (C000, X)
             // ====================
             // Absolute adressing version of Zero Page X
             AddressingMode::AbsoluteX => {
-                let base = self.memory_read_u16(self.program_counter);
+                let base = self.bus.memory_read_u16(self.program_counter);
                 let address = base.wrapping_add(self.register_x as u16);
-                address
+                (address, page_crossed(base, address))
             }
 
             // Absolute Y (C000, Y)
@@ -123,9 +317,25 @@ impl CPU {
             // Absolute addressing version of Zero Page Y
             // Cannot be used with STX but can be used with LDA and STA
             AddressingMode::AbsoluteY => {
-                let base = self.memory_read_u16(self.program_counter);
+                let base = self.bus.memory_read_u16(self.program_counter);
                 let address = base.wrapping_add(self.register_y as u16);
-                address
+                (address, page_crossed(base, address))
+            }
+
+            // Indirect (C000)
+            // ===============
+            // Only used by JMP. The operand is the address of a two-byte
+            // pointer. The 6502 never carries into the high byte when
+            // fetching the pointer, so a pointer at $xxFF reads its high
+            // byte from $xx00 rather than the next page.
+            AddressingMode::Indirect => {
+                let ptr = self.bus.memory_read_u16(self.program_counter);
+
+                let lsb = self.bus.memory_read(ptr);
+                let msb = self
+                    .bus
+                    .memory_read((ptr & 0xFF00) | (ptr.wrapping_add(1) & 0x00FF));
+                ((msb as u16) << 8 | (lsb as u16), false)
             }
 
             // Indexed Indirect ($C0, X)
@@ -133,30 +343,43 @@ impl CPU {
             // Takes the zero page address, add the value of the X register
             // then use that to loop up a two-byte address.
             AddressingMode::IndirectX => {
-                let base = self.memory_read(self.program_counter);
+                let base = self.bus.memory_read(self.program_counter);
 
-                let ptr: u8 = (base as u8).wrapping_add(self.register_x);
-                let lsb = self.memory_read(ptr as u16);
-                let msb = self.memory_read(ptr.wrapping_add(1) as u16);
-                (msb as u16) << 8 | (lsb as u16)
+                let ptr: u8 = base.wrapping_add(self.register_x);
+                let lsb = self.bus.memory_read(ptr as u16);
+                let msb = self.bus.memory_read(ptr.wrapping_add(1) as u16);
+                ((msb as u16) << 8 | (lsb as u16), false)
             }
 
             // Indirect Indexed (C0, Y)
             // ========================
             // Y address is added to the pre-dereferenced zero page address
             AddressingMode::IndirectY => {
-                let base = self.memory_read(self.program_counter);
+                let base = self.bus.memory_read(self.program_counter);
 
-                let lsb = self.memory_read(base as u16);
-                let msb = self.memory_read((base as u8).wrapping_add(1) as u16);
+                let lsb = self.bus.memory_read(base as u16);
+                let msb = self.bus.memory_read(base.wrapping_add(1) as u16);
                 let deref_base = (msb as u16) << 8 | (lsb as u16);
                 let deref = deref_base.wrapping_add(self.register_y as u16);
-                deref
+                (deref, page_crossed(deref_base, deref))
             }
 
-            // Default error handling.
-            AddressingMode::NoneAddressing => {
-                panic!("mode {:?} is not supported", mode);
+            // Relative (*+4)
+            // ==============
+            // Used by the branch instructions, the operand is a signed
+            // offset from the address of the next instruction, so the
+            // operand address is where that offset is stored.
+            AddressingMode::Relative => (self.program_counter, false),
+
+            // Neither mode has an operand address, so the opcode table is
+            // wrong. The instruction carries on with the address of the
+            // operand byte and the error is reported once it finishes.
+            AddressingMode::Accumulator | AddressingMode::NoneAddressing => {
+                self.fault.get_or_insert(CpuError::InvalidAddressing {
+                    pc: self.program_counter.wrapping_sub(1),
+                    mode: *mode,
+                });
+                (self.program_counter, false)
             }
         }
     }
@@ -176,241 +399,2813 @@ impl CPU {
     ///     V  | Overflow Flag     | Set if sign bit is incorrect
     ///     N  | Negative Flag     | Set if bit 7 is set
     fn adc(&mut self, mode: &AddressingMode) {
-        todo!()
+        let value = self.read_operand(mode);
+
+        self.add_to_register_a(value);
     }
 
-    /// BRK (0x00) - Force Interrupt
-    /// ============================
-    /// The BRK instruction forces the generation of an interrupt request.
-    /// The program count and processor status are pushed on the stack then
-    /// the IRQ interrupt vector at $FFFE/F is loaded into the PC and the break
-    /// flag in the status is set to one.
+    /// AND (0x29) - Logical AND
+    /// ========================
+    /// A logical AND is performed, bit by bit, on the accumulator contents
+    /// using the contents of a byte of memory.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if A = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of A is set
+    fn and(&mut self, mode: &AddressingMode) {
+        let value = self.read_operand(mode);
+
+        self.register_a &= value;
+        self.set_zero_negative(self.register_a);
+    }
+
+    /// ASL (0x0A) - Arithmetic Shift Left
+    /// ==================================
+    /// Shifts all the bits of the accumulator or memory contents one bit
+    /// left. Bit 0 is set to 0 and bit 7 is placed in the carry flag.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Set to contents of old bit 7
+    ///    Z   | Zero Flag         | Set if result = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of the result is set
+    fn asl(&mut self, mode: &AddressingMode) {
+        let result = self.modify_operand(mode, Self::shift_left);
+        self.set_zero_negative(result);
+    }
+
+    /// BCC (0x90) - Branch if Carry Clear
+    /// ==================================
+    /// If the carry flag is clear then add the relative displacement to the
+    /// program counter to cause a branch to a new location.
     ///
     /// Symbol |        Label      |        Description
     ///    C   | Carry Flag        | Not affected
     ///    Z   | Zero Flag         | Not affected
     ///    I   | Interrupt Disable | Not affected
     ///    D   | Decimal Mode Flag | Not affected
-    ///    B   | Break Command     | Set to 1
+    ///    B   | Break Command     | Not affected
     ///    V   | Overflow Flag     | Not affected
     ///    N   | Negative Flag     | Not affected
-    fn brk(&mut self) {
-        todo!()
+    fn bcc(&mut self) {
+        self.branch(!self.status.contains(CpuFlags::CARRY));
     }
 
-    /// INX (0xE8) - Load Accumulator
-    /// =============================
-    /// Loads a byte of memory into the accumulator setting the
-    /// zero and negative flags as appropriate.
+    /// BCS (0xB0) - Branch if Carry Set
+    /// ================================
+    /// If the carry flag is set then add the relative displacement to the
+    /// program counter to cause a branch to a new location.
     ///
     /// Symbol |        Label      |        Description
     ///    C   | Carry Flag        | Not affected
-    ///    Z   | Zero Flag         | Set if A = 0
+    ///    Z   | Zero Flag         | Not affected
     ///    I   | Interrupt Disable | Not affected
     ///    D   | Decimal Mode Flag | Not affected
     ///    B   | Break Command     | Not affected
     ///    V   | Overflow Flag     | Not affected
-    ///    N   | Negative Flag     | Set if bit 7 of A is set
-    fn inx(&mut self) {
-        self.register_x = self.register_x.wrapping_add(1);
-        self.set_zero_negative(self.register_x);
+    ///    N   | Negative Flag     | Not affected
+    fn bcs(&mut self) {
+        self.branch(self.status.contains(CpuFlags::CARRY));
     }
 
-    /// LDA (0xA9) - Load Accumulator
-    /// =============================
-    /// Loads a byte of memory into the accumulator setting the
-    /// zero and negative flags as appropriate.
+    /// BEQ (0xF0) - Branch if Equal
+    /// ============================
+    /// If the zero flag is set then add the relative displacement to the
+    /// program counter to cause a branch to a new location.
     ///
     /// Symbol |        Label      |        Description
     ///    C   | Carry Flag        | Not affected
-    ///    Z   | Zero Flag         | Set if A = 0
+    ///    Z   | Zero Flag         | Not affected
     ///    I   | Interrupt Disable | Not affected
     ///    D   | Decimal Mode Flag | Not affected
     ///    B   | Break Command     | Not affected
     ///    V   | Overflow Flag     | Not affected
-    ///    N   | Negative Flag     | Set if bit 7 of A is set
-    fn lda(&mut self, mode: &AddressingMode) {
-        let address = self.get_operand_address(&mode);
-        let value = self.memory_read(address);
+    ///    N   | Negative Flag     | Not affected
+    fn beq(&mut self) {
+        self.branch(self.status.contains(CpuFlags::ZERO));
+    }
 
-        self.register_a = value;
-        self.set_zero_negative(self.register_a);
+    /// BIT (0x24) - Bit Test
+    /// =====================
+    /// Tests if one or more bits are set in a memory location. The mask in
+    /// the accumulator is ANDed with the value in memory to set or clear
+    /// the zero flag, but the result is not kept. Bits 7 and 6 of the value
+    /// from memory are copied into the N and V flags.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if the result of the AND is 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Set to bit 6 of the memory value
+    ///    N   | Negative Flag     | Set to bit 7 of the memory value
+    fn bit(&mut self, mode: &AddressingMode) {
+        let value = self.read_operand(mode);
+
+        self.status
+            .set(CpuFlags::ZERO, self.register_a & value == 0);
+        self.status
+            .set(CpuFlags::OVERFLOW, value & 0b0100_0000 != 0);
+        self.status
+            .set(CpuFlags::NEGATIVE, value & 0b1000_0000 != 0);
     }
 
-    /// STA (0x85) - Store Accumulator
+    /// BMI (0x30) - Branch if Minus
     /// ============================
-    /// Stires tge contents of the accumulator into memory
+    /// If the negative flag is set then add the relative displacement to the
+    /// program counter to cause a branch to a new location.
     ///
     /// Symbol |        Label      |        Description
     ///    C   | Carry Flag        | Not affected
     ///    Z   | Zero Flag         | Not affected
     ///    I   | Interrupt Disable | Not affected
     ///    D   | Decimal Mode Flag | Not affected
-    ///    B   | Break Flag        | Not affected
+    ///    B   | Break Command     | Not affected
     ///    V   | Overflow Flag     | Not affected
     ///    N   | Negative Flag     | Not affected
-    fn sta(&mut self, mode: &AddressingMode) {
-        let address = self.get_operand_address(mode);
-        self.memory_write(address, self.register_a);
+    fn bmi(&mut self) {
+        self.branch(self.status.contains(CpuFlags::NEGATIVE));
     }
 
-    /// TAX (0xAA) - Transfer Accumulator to X
-    /// ======================================
-    /// Copies the current contents of the accumulator into the
-    /// X register and sets the zero and negative flags as
-    /// appropriate
+    /// BNE (0xD0) - Branch if Not Equal
+    /// ================================
+    /// If the zero flag is clear then add the relative displacement to the
+    /// program counter to cause a branch to a new location.
     ///
     /// Symbol |        Label      |        Description
     ///    C   | Carry Flag        | Not affected
-    ///    Z   | Zero Flag         | Set if X = 0
+    ///    Z   | Zero Flag         | Not affected
     ///    I   | Interrupt Disable | Not affected
     ///    D   | Decimal Mode Flag | Not affected
     ///    B   | Break Command     | Not affected
     ///    V   | Overflow Flag     | Not affected
-    ///    N   | Negative Flag     | Set if bit 7 of A is set
-    fn tax(&mut self) {
-        self.register_x = self.register_a;
-        self.set_zero_negative(self.register_x);
+    ///    N   | Negative Flag     | Not affected
+    fn bne(&mut self) {
+        self.branch(!self.status.contains(CpuFlags::ZERO));
     }
 
-    /// Flag Setting
-    fn set_zero_negative(&mut self, result: u8) {
-        // Set Zero Flag (Z) if result = 0
-        if result == 0 {
-            self.status = self.status | 0b0000_0010; // 2
-        } else {
-            self.status = self.status & 0b1111_1101; // -3
-        }
-
-        // Set Negative Flag (N) if bit 7 of result is set
-        if result & 0b1000_0000 != 0 {
-            self.status = self.status | 0b1000_0000; // -128
-        } else {
-            self.status = self.status & 0b0111_1111; // 127
-        }
+    /// BPL (0x10) - Branch if Positive
+    /// ===============================
+    /// If the negative flag is clear then add the relative displacement to
+    /// the program counter to cause a branch to a new location.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn bpl(&mut self) {
+        self.branch(!self.status.contains(CpuFlags::NEGATIVE));
     }
 
-    /// Memory
+    /// BRK (0x00) - Force Interrupt
+    /// ============================
+    /// The BRK instruction forces the generation of an interrupt request.
+    /// The program count and processor status are pushed on the stack then
+    /// the IRQ interrupt vector at $FFFE/F is loaded into the PC and the break
+    /// flag in the status is set to one.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Set to 1 in the pushed copy
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn brk(&mut self) {
+        // BRK is a two byte instruction, the byte after the opcode is
+        // skipped when the interrupt handler returns.
+        self.program_counter = self.program_counter.wrapping_add(1);
+        self.interrupt(interrupt::BRK);
+        self.jumped = true;
+    }
 
-    fn memory_read(&self, address: u16) -> u8 {
-        self.memory[address as usize]
+    /// BVC (0x50) - Branch if Overflow Clear
+    /// =====================================
+    /// If the overflow flag is clear then add the relative displacement to
+    /// the program counter to cause a branch to a new location.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn bvc(&mut self) {
+        self.branch(!self.status.contains(CpuFlags::OVERFLOW));
     }
 
-    fn memory_write(&mut self, address: u16, data: u8) {
-        self.memory[address as usize] = data;
+    /// BVS (0x70) - Branch if Overflow Set
+    /// ===================================
+    /// If the overflow flag is set then add the relative displacement to the
+    /// program counter to cause a branch to a new location.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn bvs(&mut self) {
+        self.branch(self.status.contains(CpuFlags::OVERFLOW));
     }
 
-    pub fn load_and_run(&mut self, program: Vec<u8>) {
-        self.load(program);
-        self.reset();
-        self.run();
+    /// CLC (0x18) - Clear Carry Flag
+    /// =============================
+    /// Set the carry flag to zero.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Set to 0
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn clc(&mut self) {
+        self.status.set(CpuFlags::CARRY, false);
     }
 
-    /// [0x8000 .. 0xFFFF] is reserved for program ROM
-    pub fn load(&mut self, program: Vec<u8>) {
-        self.memory[0x8000..(0x8000 + program.len())].copy_from_slice(&program[..]);
-        self.program_counter = 0x8000;
+    /// CLD (0xD8) - Clear Decimal Mode
+    /// ===============================
+    /// Sets the decimal mode flag to zero. The 2A03 has no decimal mode so
+    /// the flag is stored but otherwise ignored.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Set to 0
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn cld(&mut self) {
+        self.status.set(CpuFlags::DECIMAL_MODE, false);
     }
 
-    pub fn reset(&mut self) {
-        self.register_a = 0;
-        self.register_x = 0;
-        self.register_y = 0;
-        self.status = 0;
+    /// CLI (0x58) - Clear Interrupt Disable
+    /// ====================================
+    /// Clears the interrupt disable flag allowing normal interrupt requests
+    /// to be serviced.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Set to 0
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn cli(&mut self) {
+        self.status.set(CpuFlags::INTERRUPT_DISABLE, false);
+    }
 
-        // TODO Document magic number
-        self.program_counter = self.memory_read_u16(0xFFFC); //
+    /// CLV (0xB8) - Clear Overflow Flag
+    /// ================================
+    /// Clears the overflow flag.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Set to 0
+    ///    N   | Negative Flag     | Not affected
+    fn clv(&mut self) {
+        self.status.set(CpuFlags::OVERFLOW, false);
     }
 
-    pub fn run(&mut self) {
-        let ref opcodes: HashMap<u8, &'static opcodes::OpCode> = *opcodes::OPCODES_MAP;
+    /// CMP (0xC9) - Compare
+    /// ====================
+    /// Compares the contents of the accumulator with another memory held
+    /// value and sets the zero and carry flags as appropriate.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Set if A >= M
+    ///    Z   | Zero Flag         | Set if A = M
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of the result is set
+    fn cmp(&mut self, mode: &AddressingMode) {
+        self.compare(mode, self.register_a);
+    }
 
-        loop {
-            let code = self.memory_read(self.program_counter);
-            self.program_counter += 1;
-            let program_counter_state = self.program_counter;
+    /// CPX (0xE0) - Compare X Register
+    /// ===============================
+    /// Compares the contents of the X register with another memory held
+    /// value and sets the zero and carry flags as appropriate.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Set if X >= M
+    ///    Z   | Zero Flag         | Set if X = M
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of the result is set
+    fn cpx(&mut self, mode: &AddressingMode) {
+        self.compare(mode, self.register_x);
+    }
 
-            let opcode = opcodes
-                .get(&code)
-                .expect(&format!("OpCode {:x} is not recognised", code));
+    /// CPY (0xC0) - Compare Y Register
+    /// ===============================
+    /// Compares the contents of the Y register with another memory held
+    /// value and sets the zero and carry flags as appropriate.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Set if Y >= M
+    ///    Z   | Zero Flag         | Set if Y = M
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of the result is set
+    fn cpy(&mut self, mode: &AddressingMode) {
+        self.compare(mode, self.register_y);
+    }
 
-            match code {
-                0xA9 | 0xA5 | 0xB5 | 0xAD | 0xBD | 0xB9 | 0xA1 | 0xB1 => {
-                    self.lda(&opcode.mode);
-                }
+    /// DEC (0xC6) - Decrement Memory
+    /// =============================
+    /// Subtracts one from the value held at a specified memory location
+    /// setting the zero and negative flags as appropriate.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if result is 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of the result is set
+    fn dec(&mut self, mode: &AddressingMode) {
+        let result = self.modify_operand(mode, |_, data| data.wrapping_sub(1));
+        self.set_zero_negative(result);
+    }
+
+    /// DEX (0xCA) - Decrement X Register
+    /// =================================
+    /// Subtracts one from the X register setting the zero and negative
+    /// flags as appropriate.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if X = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of X is set
+    fn dex(&mut self) {
+        self.register_x = self.register_x.wrapping_sub(1);
+        self.set_zero_negative(self.register_x);
+    }
+
+    /// DEY (0x88) - Decrement Y Register
+    /// =================================
+    /// Subtracts one from the Y register setting the zero and negative
+    /// flags as appropriate.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if Y = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of Y is set
+    fn dey(&mut self) {
+        self.register_y = self.register_y.wrapping_sub(1);
+        self.set_zero_negative(self.register_y);
+    }
+
+    /// EOR (0x49) - Exclusive OR
+    /// =========================
+    /// An exclusive OR is performed, bit by bit, on the accumulator
+    /// contents using the contents of a byte of memory.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if A = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of A is set
+    fn eor(&mut self, mode: &AddressingMode) {
+        let value = self.read_operand(mode);
+
+        self.register_a ^= value;
+        self.set_zero_negative(self.register_a);
+    }
+
+    /// INC (0xE6) - Increment Memory
+    /// =============================
+    /// Adds one to the value held at a specified memory location setting
+    /// the zero and negative flags as appropriate.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if result is 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of the result is set
+    fn inc(&mut self, mode: &AddressingMode) {
+        let result = self.modify_operand(mode, |_, data| data.wrapping_add(1));
+        self.set_zero_negative(result);
+    }
+
+    /// INX (0xE8) - Increment X Register
+    /// =================================
+    /// Adds one to the X register setting the zero and negative flags
+    /// as appropriate.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if X = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of X is set
+    fn inx(&mut self) {
+        self.register_x = self.register_x.wrapping_add(1);
+        self.set_zero_negative(self.register_x);
+    }
+
+    /// INY (0xC8) - Increment Y Register
+    /// =================================
+    /// Adds one to the Y register setting the zero and negative flags
+    /// as appropriate.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if Y = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of Y is set
+    fn iny(&mut self) {
+        self.register_y = self.register_y.wrapping_add(1);
+        self.set_zero_negative(self.register_y);
+    }
+
+    /// JMP (0x4C) - Jump
+    /// =================
+    /// Sets the program counter to the address specified by the operand.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn jmp(&mut self, mode: &AddressingMode) {
+        (self.program_counter, _) = self.get_operand_address(mode);
+        self.jumped = true;
+    }
+
+    /// JSR (0x20) - Jump to Subroutine
+    /// ===============================
+    /// Pushes the address (minus one) of the return point on to the stack
+    /// and then sets the program counter to the target memory address.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn jsr(&mut self, mode: &AddressingMode) {
+        let (target, _) = self.get_operand_address(mode);
+
+        // The program counter is on the first operand byte, so the last
+        // byte of this instruction is one further along.
+        self.stack_push_u16(self.program_counter.wrapping_add(1));
+        self.program_counter = target;
+        self.jumped = true;
+    }
+
+    /// LDA (0xA9) - Load Accumulator
+    /// =============================
+    /// Loads a byte of memory into the accumulator setting the
+    /// zero and negative flags as appropriate.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if A = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of A is set
+    fn lda(&mut self, mode: &AddressingMode) {
+        let value = self.read_operand(mode);
+
+        self.register_a = value;
+        self.set_zero_negative(self.register_a);
+    }
+
+    /// LDX (0xA2) - Load X Register
+    /// ============================
+    /// Loads a byte of memory into the X register setting the
+    /// zero and negative flags as appropriate.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if X = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of X is set
+    fn ldx(&mut self, mode: &AddressingMode) {
+        let value = self.read_operand(mode);
+
+        self.register_x = value;
+        self.set_zero_negative(self.register_x);
+    }
+
+    /// LDY (0xA0) - Load Y Register
+    /// ============================
+    /// Loads a byte of memory into the Y register setting the
+    /// zero and negative flags as appropriate.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if Y = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of Y is set
+    fn ldy(&mut self, mode: &AddressingMode) {
+        let value = self.read_operand(mode);
+
+        self.register_y = value;
+        self.set_zero_negative(self.register_y);
+    }
+
+    /// LSR (0x4A) - Logical Shift Right
+    /// ================================
+    /// Each of the bits in A or M is shifted one place to the right. The bit
+    /// that was in bit 0 is shifted into the carry flag. Bit 7 is set to 0.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Set to contents of old bit 0
+    ///    Z   | Zero Flag         | Set if result = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of the result is set
+    fn lsr(&mut self, mode: &AddressingMode) {
+        let result = self.modify_operand(mode, Self::shift_right);
+        self.set_zero_negative(result);
+    }
+
+    /// NOP (0xEA) - No Operation
+    /// =========================
+    /// Causes no changes to the processor other than the normal
+    /// incrementing of the program counter to the next instruction.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn nop(&mut self) {}
+
+    /// ORA (0x09) - Logical Inclusive OR
+    /// =================================
+    /// An inclusive OR is performed, bit by bit, on the accumulator
+    /// contents using the contents of a byte of memory.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if A = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of A is set
+    fn ora(&mut self, mode: &AddressingMode) {
+        let value = self.read_operand(mode);
+
+        self.register_a |= value;
+        self.set_zero_negative(self.register_a);
+    }
+
+    /// PHA (0x48) - Push Accumulator
+    /// =============================
+    /// Pushes a copy of the accumulator on to the stack.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn pha(&mut self) {
+        self.stack_push(self.register_a);
+    }
+
+    /// PHP (0x08) - Push Processor Status
+    /// ==================================
+    /// Pushes a copy of the status flags on to the stack. The break flag
+    /// and bit 5 are always set in the pushed copy.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn php(&mut self) {
+        self.stack_push_status(true);
+    }
+
+    /// PLA (0x68) - Pull Accumulator
+    /// =============================
+    /// Pulls an 8 bit value from the stack and into the accumulator. The
+    /// zero and negative flags are set as appropriate.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if A = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of A is set
+    fn pla(&mut self) {
+        self.register_a = self.stack_pop();
+        self.set_zero_negative(self.register_a);
+    }
+
+    /// PLP (0x28) - Pull Processor Status
+    /// ==================================
+    /// Pulls an 8 bit value from the stack and into the processor flags.
+    /// The flags will take on new states as determined by the value pulled.
+    /// The break flag and bit 5 do not exist in the register, so those bits
+    /// of the pulled value are ignored.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Set from stack
+    ///    Z   | Zero Flag         | Set from stack
+    ///    I   | Interrupt Disable | Set from stack
+    ///    D   | Decimal Mode Flag | Set from stack
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Set from stack
+    ///    N   | Negative Flag     | Set from stack
+    fn plp(&mut self) {
+        self.stack_pop_status();
+    }
+
+    /// ROL (0x2A) - Rotate Left
+    /// ========================
+    /// Move each of the bits in either A or M one place to the left. Bit 0
+    /// is filled with the current value of the carry flag whilst the old
+    /// bit 7 becomes the new carry flag value.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Set to contents of old bit 7
+    ///    Z   | Zero Flag         | Set if result = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of the result is set
+    fn rol(&mut self, mode: &AddressingMode) {
+        let result = self.modify_operand(mode, Self::rotate_left);
+        self.set_zero_negative(result);
+    }
+
+    /// ROR (0x6A) - Rotate Right
+    /// =========================
+    /// Move each of the bits in either A or M one place to the right. Bit 7
+    /// is filled with the current value of the carry flag whilst the old
+    /// bit 0 becomes the new carry flag value.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Set to contents of old bit 0
+    ///    Z   | Zero Flag         | Set if result = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of the result is set
+    fn ror(&mut self, mode: &AddressingMode) {
+        let result = self.modify_operand(mode, Self::rotate_right);
+        self.set_zero_negative(result);
+    }
+
+    /// RTI (0x40) - Return from Interrupt
+    /// ==================================
+    /// Used at the end of an interrupt processing routine. It pulls the
+    /// processor flags from the stack followed by the program counter.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Set from stack
+    ///    Z   | Zero Flag         | Set from stack
+    ///    I   | Interrupt Disable | Set from stack
+    ///    D   | Decimal Mode Flag | Set from stack
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Set from stack
+    ///    N   | Negative Flag     | Set from stack
+    fn rti(&mut self) {
+        self.stack_pop_status();
+        self.program_counter = self.stack_pop_u16();
+        self.jumped = true;
+    }
+
+    /// RTS (0x60) - Return from Subroutine
+    /// ===================================
+    /// Used at the end of a subroutine to return to the calling routine. It
+    /// pulls the program counter (minus one) from the stack.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn rts(&mut self) {
+        self.program_counter = self.stack_pop_u16().wrapping_add(1);
+        self.jumped = true;
+    }
+
+    /// SBC (0xE9) - Subtract with Carry
+    /// ================================
+    /// Subtracts the contents of a memory location to the accumulator
+    /// together with the not of the carry bit. If overflow occurs the carry
+    /// bit is clear, this enables multiple byte subtraction to be performed.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Clear if overflow in bit 7
+    ///    Z   | Zero Flag         | Set if A = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Set if sign bit is incorrect
+    ///    N   | Negative Flag     | Set if bit 7 is set
+    fn sbc(&mut self, mode: &AddressingMode) {
+        let value = self.read_operand(mode);
+
+        // A - M - (1 - C) is the same as A + !M + C in two's complement
+        self.add_to_register_a(!value);
+    }
+
+    /// SEC (0x38) - Set Carry Flag
+    /// ===========================
+    /// Set the carry flag to one.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Set to 1
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn sec(&mut self) {
+        self.status.set(CpuFlags::CARRY, true);
+    }
+
+    /// SED (0xF8) - Set Decimal Flag
+    /// =============================
+    /// Set the decimal mode flag to one. The 2A03 has no decimal mode so
+    /// the flag is stored but otherwise ignored.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Set to 1
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn sed(&mut self) {
+        self.status.set(CpuFlags::DECIMAL_MODE, true);
+    }
+
+    /// SEI (0x78) - Set Interrupt Disable
+    /// ==================================
+    /// Set the interrupt disable flag to one.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Set to 1
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn sei(&mut self) {
+        self.status.set(CpuFlags::INTERRUPT_DISABLE, true);
+    }
+
+    /// STA (0x85) - Store Accumulator
+    /// ============================
+    /// Stires tge contents of the accumulator into memory
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Flag        | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn sta(&mut self, mode: &AddressingMode) {
+        let (address, _) = self.get_operand_address(mode);
+        self.bus.memory_write(address, self.register_a);
+    }
+
+    /// STX (0x86) - Store X Register
+    /// =============================
+    /// Stores the contents of the X register into memory.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn stx(&mut self, mode: &AddressingMode) {
+        let (address, _) = self.get_operand_address(mode);
+        self.bus.memory_write(address, self.register_x);
+    }
+
+    /// STY (0x84) - Store Y Register
+    /// =============================
+    /// Stores the contents of the Y register into memory.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn sty(&mut self, mode: &AddressingMode) {
+        let (address, _) = self.get_operand_address(mode);
+        self.bus.memory_write(address, self.register_y);
+    }
+
+    /// TAX (0xAA) - Transfer Accumulator to X
+    /// ======================================
+    /// Copies the current contents of the accumulator into the
+    /// X register and sets the zero and negative flags as
+    /// appropriate
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if X = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of A is set
+    fn tax(&mut self) {
+        self.register_x = self.register_a;
+        self.set_zero_negative(self.register_x);
+    }
+
+    /// TAY (0xA8) - Transfer Accumulator to Y
+    /// ======================================
+    /// Copies the current contents of the accumulator into the
+    /// Y register and sets the zero and negative flags as
+    /// appropriate
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if Y = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of Y is set
+    fn tay(&mut self) {
+        self.register_y = self.register_a;
+        self.set_zero_negative(self.register_y);
+    }
+
+    /// TSX (0xBA) - Transfer Stack Pointer to X
+    /// ========================================
+    /// Copies the current contents of the stack register into the
+    /// X register and sets the zero and negative flags as
+    /// appropriate
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if X = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of X is set
+    fn tsx(&mut self) {
+        self.register_x = self.stack_pointer;
+        self.set_zero_negative(self.register_x);
+    }
+
+    /// TXA (0x8A) - Transfer X to Accumulator
+    /// ======================================
+    /// Copies the current contents of the X register into the
+    /// accumulator and sets the zero and negative flags as
+    /// appropriate
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if A = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of A is set
+    fn txa(&mut self) {
+        self.register_a = self.register_x;
+        self.set_zero_negative(self.register_a);
+    }
+
+    /// TXS (0x9A) - Transfer X to Stack Pointer
+    /// ========================================
+    /// Copies the current contents of the X register into the
+    /// stack register.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Not affected
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Not affected
+    fn txs(&mut self) {
+        self.stack_pointer = self.register_x;
+    }
+
+    /// TYA (0x98) - Transfer Y to Accumulator
+    /// ======================================
+    /// Copies the current contents of the Y register into the
+    /// accumulator and sets the zero and negative flags as
+    /// appropriate
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if A = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of A is set
+    fn tya(&mut self) {
+        self.register_a = self.register_y;
+        self.set_zero_negative(self.register_a);
+    }
+
+    // Unofficial Instructions
+
+    /// NOP - No Operation (unofficial)
+    /// ===============================
+    /// The unofficial NOPs with an operand still read it, which takes the
+    /// same time as a load and can trigger side effects of registers.
+    fn nop_read(&mut self, mode: &AddressingMode) {
+        self.read_operand(mode);
+    }
+
+    /// LAX - Load Accumulator and X Register
+    /// =====================================
+    /// Loads a byte of memory into both the accumulator and X register.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Not affected
+    ///    Z   | Zero Flag         | Set if A = 0
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of A is set
+    fn lax(&mut self, mode: &AddressingMode) {
+        let value = self.read_operand(mode);
+
+        self.register_a = value;
+        self.register_x = value;
+        self.set_zero_negative(value);
+    }
+
+    /// SAX - Store Accumulator AND X Register
+    /// ======================================
+    /// Stores the bitwise AND of the accumulator and X register, without
+    /// changing either or any flags.
+    fn sax(&mut self, mode: &AddressingMode) {
+        let (address, _) = self.get_operand_address(mode);
+        self.bus
+            .memory_write(address, self.register_a & self.register_x);
+    }
+
+    /// DCP - Decrement Memory then Compare
+    /// ===================================
+    /// DEC followed by CMP on the decremented value.
+    ///
+    /// Symbol |        Label      |        Description
+    ///    C   | Carry Flag        | Set if A >= M - 1
+    ///    Z   | Zero Flag         | Set if A = M - 1
+    ///    I   | Interrupt Disable | Not affected
+    ///    D   | Decimal Mode Flag | Not affected
+    ///    B   | Break Command     | Not affected
+    ///    V   | Overflow Flag     | Not affected
+    ///    N   | Negative Flag     | Set if bit 7 of A - (M - 1) is set
+    fn dcp(&mut self, mode: &AddressingMode) {
+        let value = self.modify_operand(mode, |_, data| data.wrapping_sub(1));
+
+        self.status.set(CpuFlags::CARRY, self.register_a >= value);
+        self.set_zero_negative(self.register_a.wrapping_sub(value));
+    }
+
+    /// ISB - Increment Memory then Subtract with Carry
+    /// ===============================================
+    /// INC followed by SBC of the incremented value, flags are set as SBC
+    /// sets them.
+    fn isb(&mut self, mode: &AddressingMode) {
+        let value = self.modify_operand(mode, |_, data| data.wrapping_add(1));
+        self.add_to_register_a(!value);
+    }
+
+    /// SLO - Arithmetic Shift Left then Logical Inclusive OR
+    /// =====================================================
+    /// ASL on memory followed by ORA of the result. Carry comes from the
+    /// shift, Z and N from the accumulator.
+    fn slo(&mut self, mode: &AddressingMode) {
+        let value = self.modify_operand(mode, Self::shift_left);
+
+        self.register_a |= value;
+        self.set_zero_negative(self.register_a);
+    }
+
+    /// RLA - Rotate Left then Logical AND
+    /// ==================================
+    /// ROL on memory followed by AND of the result. Carry comes from the
+    /// rotate, Z and N from the accumulator.
+    fn rla(&mut self, mode: &AddressingMode) {
+        let value = self.modify_operand(mode, Self::rotate_left);
+
+        self.register_a &= value;
+        self.set_zero_negative(self.register_a);
+    }
+
+    /// SRE - Logical Shift Right then Exclusive OR
+    /// ===========================================
+    /// LSR on memory followed by EOR of the result. Carry comes from the
+    /// shift, Z and N from the accumulator.
+    fn sre(&mut self, mode: &AddressingMode) {
+        let value = self.modify_operand(mode, Self::shift_right);
+
+        self.register_a ^= value;
+        self.set_zero_negative(self.register_a);
+    }
+
+    /// RRA - Rotate Right then Add with Carry
+    /// ======================================
+    /// ROR on memory followed by ADC of the result, using the carry the
+    /// rotate shifted out.
+    fn rra(&mut self, mode: &AddressingMode) {
+        let value = self.modify_operand(mode, Self::rotate_right);
+        self.add_to_register_a(value);
+    }
+
+    /// ANC - Logical AND, Carry from Bit 7
+    /// ===================================
+    /// AND with an immediate value, then copies N into the carry.
+    fn anc(&mut self, mode: &AddressingMode) {
+        self.and(mode);
+        self.status
+            .set(CpuFlags::CARRY, self.status.contains(CpuFlags::NEGATIVE));
+    }
+
+    /// ALR - Logical AND then Logical Shift Right
+    /// ==========================================
+    /// AND with an immediate value followed by LSR A.
+    fn alr(&mut self, mode: &AddressingMode) {
+        self.and(mode);
+        self.lsr(&AddressingMode::Accumulator);
+    }
+
+    /// ARR - Logical AND then Rotate Right
+    /// ===================================
+    /// AND with an immediate value followed by ROR A, except the carry and
+    /// overflow flags come from the adder: C is bit 6 of the result and V
+    /// is bit 6 XOR bit 5.
+    fn arr(&mut self, mode: &AddressingMode) {
+        self.and(mode);
+        self.ror(&AddressingMode::Accumulator);
+
+        let result = self.register_a;
+        self.status.set(CpuFlags::CARRY, result & 0b0100_0000 != 0);
+        self.status
+            .set(CpuFlags::OVERFLOW, ((result >> 6) ^ (result >> 5)) & 1 != 0);
+    }
+
+    /// AXS - AND X Register with Accumulator then Subtract
+    /// ===================================================
+    /// Sets X to (A AND X) minus an immediate value, without borrow. The
+    /// flags are set like CMP.
+    fn axs(&mut self, mode: &AddressingMode) {
+        let value = self.read_operand(mode);
+        let and = self.register_a & self.register_x;
+
+        self.status.set(CpuFlags::CARRY, and >= value);
+        self.register_x = and.wrapping_sub(value);
+        self.set_zero_negative(self.register_x);
+    }
+
+    /// XAA - Transfer X to Accumulator then AND (unstable)
+    /// ===================================================
+    /// A = (A | magic) & X & value. The magic constant depends on the chip
+    /// and temperature, $EE is the common choice.
+    fn xaa(&mut self, mode: &AddressingMode) {
+        let value = self.read_operand(mode);
+
+        self.register_a = (self.register_a | UNSTABLE_MAGIC) & self.register_x & value;
+        self.set_zero_negative(self.register_a);
+    }
+
+    /// LAX # - Load Accumulator and X Register (unstable)
+    /// ==================================================
+    /// The immediate LAX goes through the same path as XAA, so it is
+    /// A = X = (A | magic) & value.
+    fn lax_immediate(&mut self, mode: &AddressingMode) {
+        let value = self.read_operand(mode);
+
+        self.register_a = (self.register_a | UNSTABLE_MAGIC) & value;
+        self.register_x = self.register_a;
+        self.set_zero_negative(self.register_a);
+    }
+
+    /// AHX, SHX, SHY and TAS (unstable)
+    /// ================================
+    /// Store a register ANDed with the high byte of the base address plus
+    /// one. When indexing crosses a page the stored value also replaces the
+    /// high byte of the address written to.
+    fn store_high_byte_and(&mut self, mode: &AddressingMode, index: u8, value: u8) {
+        let (address, page_crossed) = self.get_operand_address(mode);
+        let base_high = (address.wrapping_sub(index as u16) >> 8) as u8;
+        let data = value & base_high.wrapping_add(1);
+
+        let address = if page_crossed {
+            (data as u16) << 8 | (address & 0x00FF)
+        } else {
+            address
+        };
+        self.bus.memory_write(address, data);
+    }
+
+    fn ahx(&mut self, mode: &AddressingMode) {
+        self.store_high_byte_and(mode, self.register_y, self.register_a & self.register_x);
+    }
+
+    fn shx(&mut self, mode: &AddressingMode) {
+        self.store_high_byte_and(mode, self.register_y, self.register_x);
+    }
+
+    fn shy(&mut self, mode: &AddressingMode) {
+        self.store_high_byte_and(mode, self.register_x, self.register_y);
+    }
+
+    /// TAS also sets the stack pointer to A AND X before storing it
+    fn tas(&mut self, mode: &AddressingMode) {
+        self.stack_pointer = self.register_a & self.register_x;
+        self.store_high_byte_and(mode, self.register_y, self.stack_pointer);
+    }
+
+    /// LAS - Load Accumulator, X and Stack Pointer
+    /// ===========================================
+    /// ANDs memory with the stack pointer and loads the result into A, X
+    /// and the stack pointer.
+    fn las(&mut self, mode: &AddressingMode) {
+        let value = self.read_operand(mode) & self.stack_pointer;
+
+        self.register_a = value;
+        self.register_x = value;
+        self.stack_pointer = value;
+        self.set_zero_negative(value);
+    }
+
+    /// JAM - Halt
+    /// ==========
+    /// The CPU stops fetching instructions and stays on the JAM opcode
+    /// until it is reset.
+    fn jam(&mut self) {
+        self.program_counter = self.program_counter.wrapping_sub(1);
+        self.jammed = true;
+    }
+
+    /// Whether a JAM has halted the CPU
+    pub fn jammed(&self) -> bool {
+        self.jammed
+    }
+
+    // Shared Instruction Helpers
+
+    /// Binary addition used by both ADC and SBC. The 2A03 has no decimal
+    /// mode, so the D flag is ignored.
+    fn add_to_register_a(&mut self, data: u8) {
+        let sum =
+            self.register_a as u16 + data as u16 + self.status.contains(CpuFlags::CARRY) as u16;
+        let result = sum as u8;
+
+        self.status.set(CpuFlags::CARRY, sum > 0xFF);
+
+        // Overflow is set when both inputs share a sign which differs from
+        // the sign of the result.
+        self.status.set(
+            CpuFlags::OVERFLOW,
+            (data ^ result) & (result ^ self.register_a) & 0b1000_0000 != 0,
+        );
+
+        self.register_a = result;
+        self.set_zero_negative(self.register_a);
+    }
+
+    /// Branches take one extra cycle when taken and another when the
+    /// destination is on a different page to the next instruction.
+    fn branch(&mut self, condition: bool) {
+        if condition {
+            let offset = self.bus.memory_read(self.program_counter) as i8;
+            let next = self.program_counter.wrapping_add(1);
+            let target = next.wrapping_add(offset as u16);
+
+            self.tick(1);
+            if page_crossed(next, target) {
+                self.tick(1);
+            }
+
+            self.program_counter = target;
+            self.jumped = true;
+        }
+    }
+
+    fn compare(&mut self, mode: &AddressingMode, register: u8) {
+        let value = self.read_operand(mode);
+
+        self.status.set(CpuFlags::CARRY, register >= value);
+        self.set_zero_negative(register.wrapping_sub(value));
+    }
+
+    /// Fetches the operand of an instruction that only reads memory. Unlike
+    /// writes and read-modify-write instructions, reads skip the dummy cycle
+    /// used to fix up the address unless indexing crossed a page.
+    fn read_operand(&mut self, mode: &AddressingMode) -> u8 {
+        let (address, page_crossed) = self.get_operand_address(mode);
+        if page_crossed {
+            self.tick(1);
+        }
+        self.bus.memory_read(address)
+    }
+
+    /// Read-modify-write instructions operate on either the accumulator or
+    /// memory, returning the value written back.
+    fn modify_operand(&mut self, mode: &AddressingMode, operation: fn(&mut Self, u8) -> u8) -> u8 {
+        match mode {
+            AddressingMode::Accumulator => {
+                self.register_a = operation(self, self.register_a);
+                self.register_a
+            }
+            _ => {
+                let (address, _) = self.get_operand_address(mode);
+                let data = self.bus.memory_read(address);
+                let result = operation(self, data);
+                self.bus.memory_write(address, result);
+                result
+            }
+        }
+    }
+
+    // Shifts and rotates for `modify_operand`, the bit shifted out goes
+    // into the carry
+
+    fn shift_left(&mut self, data: u8) -> u8 {
+        self.status.set(CpuFlags::CARRY, data & 0b1000_0000 != 0);
+        data << 1
+    }
+
+    fn shift_right(&mut self, data: u8) -> u8 {
+        self.status.set(CpuFlags::CARRY, data & 0b0000_0001 != 0);
+        data >> 1
+    }
+
+    fn rotate_left(&mut self, data: u8) -> u8 {
+        let carry_in = self.status.contains(CpuFlags::CARRY) as u8;
+        self.status.set(CpuFlags::CARRY, data & 0b1000_0000 != 0);
+        (data << 1) | carry_in
+    }
+
+    fn rotate_right(&mut self, data: u8) -> u8 {
+        let carry_in = (self.status.contains(CpuFlags::CARRY) as u8) << 7;
+        self.status.set(CpuFlags::CARRY, data & 0b0000_0001 != 0);
+        (data >> 1) | carry_in
+    }
+
+    /// Flag Setting
+    fn set_zero_negative(&mut self, result: u8) {
+        // Set Zero Flag (Z) if result = 0
+        self.status.set(CpuFlags::ZERO, result == 0);
+
+        // Set Negative Flag (N) if bit 7 of result is set
+        self.status
+            .set(CpuFlags::NEGATIVE, result & 0b1000_0000 != 0);
+    }
+
+    // Interrupts
+
+    /// Sets the level of the NMI input. The 2A03 detects NMIs on the edge
+    /// from released to asserted, so an NMI is latched once per assertion
+    /// and holding the line does not fire it again.
+    pub fn set_nmi_line(&mut self, asserted: bool) {
+        self.nmi_line = asserted;
+        self.sample_nmi();
+    }
+
+    /// Runs the NMI edge detector over the external line and the line
+    /// driven by devices on the bus.
+    fn sample_nmi(&mut self) {
+        let level = self.nmi_line || self.bus.nmi_line();
+        if level && !self.nmi_level {
+            self.nmi_pending = true;
+        }
+        self.nmi_level = level;
+    }
+
+    /// Asserts or releases the IRQ input on behalf of one device. IRQs are
+    /// level triggered, one is taken at every instruction boundary while
+    /// any source is asserted and the I flag is clear.
+    pub fn set_irq_line(&mut self, source: IrqSource, asserted: bool) {
+        self.irq_line.set(source, asserted);
+    }
+
+    /// Interrupts are polled at the end of each instruction. An NMI wins
+    /// over an IRQ when both are pending.
+    fn poll_interrupts(&mut self) {
+        self.sample_nmi();
+        let irq_line = self.irq_line | self.bus.irq_line();
+
+        let interrupt = if self.nmi_pending {
+            self.nmi_pending = false;
+            interrupt::NMI
+        } else if !irq_line.is_empty() && !self.irq_inhibit {
+            interrupt::IRQ
+        } else {
+            return;
+        };
+
+        self.tick(interrupt.cpu_cycles);
+        self.interrupt(interrupt);
+    }
+
+    /// Interrupt Sequence
+    /// ==================
+    /// Takes 7 cycles for NMI, IRQ and BRK alike: the program counter and
+    /// status are pushed, interrupts are disabled and the program counter
+    /// is loaded from the vector.
+    ///
+    /// The vector is only chosen in the last cycles of the sequence. If an
+    /// NMI has been latched by then it hijacks a BRK or IRQ, which runs the
+    /// NMI handler with the B flag of the pushed status left as it was.
+    fn interrupt(&mut self, interrupt: interrupt::Interrupt) {
+        self.stack_push_u16(self.program_counter);
+        self.stack_push_status(interrupt.break_flag);
+        self.status.insert(CpuFlags::INTERRUPT_DISABLE);
+        self.irq_inhibit = true;
+
+        self.sample_nmi();
+        let vector = if interrupt.itype != InterruptType::Nmi && self.nmi_pending {
+            self.nmi_pending = false;
+            interrupt::NMI.vector_address
+        } else {
+            interrupt.vector_address
+        };
+        self.program_counter = self.bus.memory_read_u16(vector);
+    }
+
+    // Stack
+
+    fn stack_push(&mut self, data: u8) {
+        self.bus
+            .memory_write(STACK + self.stack_pointer as u16, data);
+        self.stack_pointer = self.stack_pointer.wrapping_sub(1);
+    }
+
+    fn stack_pop(&mut self) -> u8 {
+        self.stack_pointer = self.stack_pointer.wrapping_add(1);
+        self.bus.memory_read(STACK + self.stack_pointer as u16)
+    }
+
+    fn stack_push_u16(&mut self, data: u16) {
+        self.stack_push((data >> 8) as u8);
+        self.stack_push((data & 0xFF) as u8);
+    }
+
+    fn stack_pop_u16(&mut self) -> u16 {
+        let lsb = self.stack_pop() as u16;
+        let msb = self.stack_pop() as u16;
+        (msb << 8) | lsb
+    }
+
+    fn stack_push_status(&mut self, break_flag: bool) {
+        self.stack_push(self.status.pushed(break_flag));
+    }
+
+    fn stack_pop_status(&mut self) {
+        self.status = CpuFlags::pulled(self.stack_pop());
+    }
+
+    // Execution
+
+    /// Advances the cycle counter and lets the rest of the console catch up
+    fn tick(&mut self, cycles: u8) {
+        self.cycles += cycles as u64;
+        self.bus.tick(cycles);
+    }
+
+    /// Lets the bus finish anything that halted the CPU, such as OAM DMA,
+    /// before the next instruction starts
+    fn stall(&mut self) {
+        let mut cycles = self.bus.take_stall_cycles();
+        while cycles > 0 {
+            let step = cycles.min(u8::MAX as u16);
+            self.tick(step as u8);
+            cycles -= step;
+        }
+    }
+
+    pub fn load_and_run(&mut self, program: Vec<u8>) -> Result<(), CpuError> {
+        self.load(program);
+        self.reset();
+        self.run()
+    }
+
+    /// [0x8000 .. 0xFFFF] is reserved for program ROM. The program and the
+    /// reset vector are written through the bus, so this needs a bus with
+    /// RAM behind that range such as `FlatRam`. A program which reaches
+    /// $FFFC supplies its own reset vector.
+    ///
+    /// Panics if the program is larger than 32KB.
+    pub fn load(&mut self, program: Vec<u8>) {
+        assert!(
+            program.len() <= 0x8000,
+            "program of {} bytes does not fit in the 32KB at $8000",
+            program.len()
+        );
+        for (offset, data) in program.iter().enumerate() {
+            self.bus.memory_write(0x8000 + offset as u16, *data);
+        }
+        if program.len() <= 0x7FFC {
+            self.bus.memory_write_u16(0xFFFC, 0x8000);
+        }
+    }
+
+    /// Runs the reset sequence, used both after power on and when the
+    /// reset button is pressed. A, X and Y keep their values, the three
+    /// dummy pushes move the stack pointer down without writing and only
+    /// the I flag is changed.
+    pub fn reset(&mut self) {
+        self.stack_pointer = self.stack_pointer.wrapping_sub(3);
+        self.status.insert(CpuFlags::INTERRUPT_DISABLE);
+        self.nmi_pending = false;
+        self.irq_inhibit = true;
+        self.halted = false;
+        self.jammed = false;
+        self.fault = None;
+        self.tick(interrupt::RESET.cpu_cycles);
+
+        // The reset vector at $FFFC/D holds the address of the first
+        // instruction to execute.
+        self.program_counter = self.bus.memory_read_u16(interrupt::RESET.vector_address);
+    }
+
+    /// Runs until BRK halts the CPU in test mode, or an error
+    pub fn run(&mut self) -> Result<(), CpuError> {
+        self.run_with_callback(|_| ControlFlow::Continue(()))
+    }
+
+    /// Runs like `run`, calling back before every instruction. The callback
+    /// sees the CPU as the instruction is about to be fetched and can stop
+    /// the run by returning `ControlFlow::Break`.
+    pub fn run_with_callback<F>(&mut self, mut callback: F) -> Result<(), CpuError>
+    where
+        F: FnMut(&mut CPU<B>) -> ControlFlow<()>,
+    {
+        loop {
+            if self.halted {
+                return Ok(());
+            }
+            self.check_jammed()?;
+            self.stall();
+            self.poll_interrupts();
+
+            if callback(self).is_break() {
+                return Ok(());
+            }
+
+            self.execute()?;
+        }
+    }
+
+    /// Runs until `predicate` holds before an instruction is fetched
+    pub fn run_until<F>(&mut self, mut predicate: F) -> Result<(), CpuError>
+    where
+        F: FnMut(&mut CPU<B>) -> bool,
+    {
+        self.run_with_callback(|cpu| {
+            if predicate(cpu) {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })
+    }
+
+    /// Runs whole instructions until at least `cycles` more have gone by
+    pub fn run_for_cycles(&mut self, cycles: u64) -> Result<(), CpuError> {
+        let end = self.cycles + cycles;
+        self.run_until(|cpu| cpu.cycles >= end)
+    }
+
+    /// Executes a single instruction, along with any stall or interrupt
+    /// sequence due before it. Returns `None` without doing anything once
+    /// the CPU has halted.
+    ///
+    /// In test mode the step which reaches BRK returns it and halts. The
+    /// BRK itself doesn't run, so the step's cycles are only those of any
+    /// stall or interrupt sequence served before it.
+    pub fn step(&mut self) -> Result<Option<Step>, CpuError> {
+        if self.halted {
+            return Ok(None);
+        }
+        self.check_jammed()?;
+
+        let start = self.cycles;
+        self.stall();
+        self.poll_interrupts();
+        let pc = self.program_counter;
+        let opcode = self.execute()?;
+
+        Ok(Some(Step {
+            pc,
+            opcode,
+            cycles: self.cycles - start,
+        }))
+    }
+
+    /// Whether BRK has halted the CPU in test mode, the program counter is
+    /// left on the BRK
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    fn check_jammed(&self) -> Result<(), CpuError> {
+        if self.jammed {
+            Err(CpuError::Jam {
+                pc: self.program_counter,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Fetches, decodes and executes the instruction at the program counter
+    /// and returns its opcode. An opcode which is refused leaves the program
+    /// counter pointing at it, as does BRK when it halts the CPU in test
+    /// mode.
+    fn execute(&mut self) -> Result<&'static opcodes::OpCode, CpuError> {
+        let opcodes: &'static HashMap<u8, &'static opcodes::OpCode> = &opcodes::OPCODES_MAP;
+
+        let pc = self.program_counter;
+        let code = self.bus.memory_read(pc);
+        self.program_counter = self.program_counter.wrapping_add(1);
+        self.jumped = false;
+        let irq_inhibit = self.status.contains(CpuFlags::INTERRUPT_DISABLE);
+
+        let Some(opcode) = opcodes.get(&code) else {
+            self.program_counter = pc;
+            return Err(CpuError::UnknownOpcode { pc, opcode: code });
+        };
+
+        let trap = match self.unofficial_opcodes {
+            UnofficialOpcodes::Execute => false,
+            UnofficialOpcodes::Trap => !opcode.official,
+            UnofficialOpcodes::HaltOnJam => !opcode.official && opcode.mnemonic != "JAM",
+        };
+        if trap {
+            self.program_counter = pc;
+            return Err(CpuError::UnofficialOpcode { pc, opcode: code });
+        }
+
+        // In test mode BRK ends the program, the CPU stops on it without
+        // spending any cycles or entering the handler.
+        if code == 0x00 && self.test_mode {
+            self.program_counter = pc;
+            self.halted = true;
+            return Ok(opcode);
+        }
+
+        // Base cost from the table, page crossing and branch penalties
+        // are added by the instructions themselves.
+        self.tick(opcode.cycles);
+
+        match code {
+            /* ADC */
+            0x69 | 0x65 | 0x75 | 0x6D | 0x7D | 0x79 | 0x61 | 0x71 => {
+                self.adc(&opcode.mode);
+            }
+
+            /* AND */
+            0x29 | 0x25 | 0x35 | 0x2D | 0x3D | 0x39 | 0x21 | 0x31 => {
+                self.and(&opcode.mode);
+            }
+
+            /* ASL */
+            0x0A | 0x06 | 0x16 | 0x0E | 0x1E => self.asl(&opcode.mode),
+
+            0x90 => self.bcc(),
+            0xB0 => self.bcs(),
+            0xF0 => self.beq(),
+
+            /* BIT */
+            0x24 | 0x2C => self.bit(&opcode.mode),
+
+            0x30 => self.bmi(),
+            0xD0 => self.bne(),
+            0x10 => self.bpl(),
+
+            0x00 => self.brk(),
+
+            0x50 => self.bvc(),
+            0x70 => self.bvs(),
+
+            0x18 => self.clc(),
+            0xD8 => self.cld(),
+            0x58 => self.cli(),
+            0xB8 => self.clv(),
+
+            /* CMP */
+            0xC9 | 0xC5 | 0xD5 | 0xCD | 0xDD | 0xD9 | 0xC1 | 0xD1 => {
+                self.cmp(&opcode.mode);
+            }
+
+            /* CPX */
+            0xE0 | 0xE4 | 0xEC => self.cpx(&opcode.mode),
+
+            /* CPY */
+            0xC0 | 0xC4 | 0xCC => self.cpy(&opcode.mode),
+
+            /* DEC */
+            0xC6 | 0xD6 | 0xCE | 0xDE => self.dec(&opcode.mode),
+
+            0xCA => self.dex(),
+            0x88 => self.dey(),
+
+            /* EOR */
+            0x49 | 0x45 | 0x55 | 0x4D | 0x5D | 0x59 | 0x41 | 0x51 => {
+                self.eor(&opcode.mode);
+            }
+
+            /* INC */
+            0xE6 | 0xF6 | 0xEE | 0xFE => self.inc(&opcode.mode),
+
+            0xE8 => self.inx(),
+            0xC8 => self.iny(),
+
+            /* JMP */
+            0x4C | 0x6C => self.jmp(&opcode.mode),
+
+            0x20 => self.jsr(&opcode.mode),
+
+            /* LDA */
+            0xA9 | 0xA5 | 0xB5 | 0xAD | 0xBD | 0xB9 | 0xA1 | 0xB1 => {
+                self.lda(&opcode.mode);
+            }
+
+            /* LDX */
+            0xA2 | 0xA6 | 0xB6 | 0xAE | 0xBE => self.ldx(&opcode.mode),
+
+            /* LDY */
+            0xA0 | 0xA4 | 0xB4 | 0xAC | 0xBC => self.ldy(&opcode.mode),
+
+            /* LSR */
+            0x4A | 0x46 | 0x56 | 0x4E | 0x5E => self.lsr(&opcode.mode),
+
+            0xEA => self.nop(),
+
+            /* ORA */
+            0x09 | 0x05 | 0x15 | 0x0D | 0x1D | 0x19 | 0x01 | 0x11 => {
+                self.ora(&opcode.mode);
+            }
+
+            0x48 => self.pha(),
+            0x08 => self.php(),
+            0x68 => self.pla(),
+            0x28 => self.plp(),
+
+            /* ROL */
+            0x2A | 0x26 | 0x36 | 0x2E | 0x3E => self.rol(&opcode.mode),
+
+            /* ROR */
+            0x6A | 0x66 | 0x76 | 0x6E | 0x7E => self.ror(&opcode.mode),
+
+            0x40 => self.rti(),
+            0x60 => self.rts(),
+
+            /* SBC */
+            0xE9 | 0xE5 | 0xF5 | 0xED | 0xFD | 0xF9 | 0xE1 | 0xF1 => {
+                self.sbc(&opcode.mode);
+            }
+
+            0x38 => self.sec(),
+            0xF8 => self.sed(),
+            0x78 => self.sei(),
+
+            /* STA */
+            0x85 | 0x95 | 0x8d | 0x9d | 0x99 | 0x81 | 0x91 => {
+                self.sta(&opcode.mode);
+            }
+
+            /* STX */
+            0x86 | 0x96 | 0x8E => self.stx(&opcode.mode),
+
+            /* STY */
+            0x84 | 0x94 | 0x8C => self.sty(&opcode.mode),
+
+            0xAA => self.tax(),
+            0xA8 => self.tay(),
+            0xBA => self.tsx(),
+            0x8A => self.txa(),
+            0x9A => self.txs(),
+            0x98 => self.tya(),
+
+            /* Unofficial NOP */
+            0x1A | 0x3A | 0x5A | 0x7A | 0xDA | 0xFA => self.nop(),
+            0x80 | 0x82 | 0x89 | 0xC2 | 0xE2 | 0x04 | 0x44 | 0x64 | 0x14 | 0x34 | 0x54 | 0x74
+            | 0xD4 | 0xF4 | 0x0C | 0x1C | 0x3C | 0x5C | 0x7C | 0xDC | 0xFC => {
+                self.nop_read(&opcode.mode);
+            }
+
+            /* LAX */
+            0xA7 | 0xB7 | 0xAF | 0xBF | 0xA3 | 0xB3 => self.lax(&opcode.mode),
+
+            /* SAX */
+            0x87 | 0x97 | 0x8F | 0x83 => self.sax(&opcode.mode),
+
+            0xEB => self.sbc(&opcode.mode),
+
+            /* DCP */
+            0xC7 | 0xD7 | 0xCF | 0xDF | 0xDB | 0xC3 | 0xD3 => self.dcp(&opcode.mode),
+
+            /* ISB */
+            0xE7 | 0xF7 | 0xEF | 0xFF | 0xFB | 0xE3 | 0xF3 => self.isb(&opcode.mode),
+
+            /* SLO */
+            0x07 | 0x17 | 0x0F | 0x1F | 0x1B | 0x03 | 0x13 => self.slo(&opcode.mode),
+
+            /* RLA */
+            0x27 | 0x37 | 0x2F | 0x3F | 0x3B | 0x23 | 0x33 => self.rla(&opcode.mode),
+
+            /* SRE */
+            0x47 | 0x57 | 0x4F | 0x5F | 0x5B | 0x43 | 0x53 => self.sre(&opcode.mode),
+
+            /* RRA */
+            0x67 | 0x77 | 0x6F | 0x7F | 0x7B | 0x63 | 0x73 => self.rra(&opcode.mode),
+
+            0x0B | 0x2B => self.anc(&opcode.mode),
+            0x4B => self.alr(&opcode.mode),
+            0x6B => self.arr(&opcode.mode),
+            0xCB => self.axs(&opcode.mode),
+
+            0x8B => self.xaa(&opcode.mode),
+            0xAB => self.lax_immediate(&opcode.mode),
+            0x9F | 0x93 => self.ahx(&opcode.mode),
+            0x9E => self.shx(&opcode.mode),
+            0x9C => self.shy(&opcode.mode),
+            0x9B => self.tas(&opcode.mode),
+            0xBB => self.las(&opcode.mode),
+
+            /* JAM */
+            0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xB2 | 0xD2 | 0xF2 => {
+                self.jam();
+                return Err(CpuError::Jam { pc });
+            }
+        }
+
+        if !self.jumped {
+            self.program_counter = self.program_counter.wrapping_add((opcode.len - 1) as u16);
+        }
+
+        // CLI, SEI and PLP change the I flag after this instruction's
+        // interrupt poll, so the new value only takes effect at the end
+        // of the following instruction. RTI takes effect immediately.
+        self.irq_inhibit = match code {
+            0x58 | 0x78 | 0x28 => irq_inhibit,
+            _ => self.status.contains(CpuFlags::INTERRUPT_DISABLE),
+        };
+
+        if let Some(error) = self.fault.take() {
+            return Err(error);
+        }
+        if let Some(address) = self.bus.take_fault() {
+            return Err(CpuError::BusFault { pc, address });
+        }
+        Ok(opcode)
+    }
+}
+
+impl CPU<NesBus> {
+    /// Runs until the PPU finishes the current frame
+    pub fn run_frame(&mut self) -> Result<(), CpuError> {
+        let frame = self.bus.ppu.frame_count;
+        self.run_until(|cpu| cpu.bus.ppu.frame_count != frame)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::FlatRam;
+
+    #[test]
+    fn test_0xa9_lda_immidiate_load_data() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.load_and_run(vec![0xa9, 0x05, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 5);
+        assert!(!cpu.status.contains(CpuFlags::ZERO));
+        assert!(!cpu.status.contains(CpuFlags::NEGATIVE));
+    }
+
+    #[test]
+    fn test_0xa9_lda_zero_flag() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.load_and_run(vec![0xa9, 0x00, 0x00]).unwrap();
+        assert!(cpu.status.contains(CpuFlags::ZERO));
+    }
+
+    #[test]
+    fn test_0xaa_tax_move_a_to_x() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.load_and_run(vec![0xa9, 0x0A, 0xaa, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_x, 10)
+    }
+
+    #[test]
+    fn test_5_ops_working_together() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.load_and_run(vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00])
+            .unwrap();
+
+        assert_eq!(cpu.register_x, 0xc1)
+    }
+
+    #[test]
+    fn test_inx_overflow() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.load_and_run(vec![0xa9, 0xff, 0xaa, 0xe8, 0xe8, 0x00])
+            .unwrap();
+
+        assert_eq!(cpu.register_x, 1)
+    }
+
+    #[test]
+    fn test_lda_from_memory() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.bus.memory_write(0x10, 0x55);
+
+        cpu.load_and_run(vec![0xa5, 0x10, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 0x55);
+    }
+
+    #[test]
+    fn test_every_official_opcode_is_registered() {
+        let official = opcodes::CPU_OPS_CODES.iter().filter(|op| op.official);
+        assert_eq!(official.count(), 151);
+    }
 
-                /* STA */
-                0x85 | 0x95 | 0x8d | 0x9d | 0x99 | 0x81 | 0x91 => {
-                    self.sta(&opcode.mode);
-                }
+    #[test]
+    fn test_every_opcode_is_registered() {
+        assert_eq!(opcodes::CPU_OPS_CODES.len(), 256);
+        assert_eq!(opcodes::OPCODES_MAP.len(), 256);
+    }
 
-                0xAA => self.tax(),
+    #[test]
+    fn test_adc_carry_and_overflow() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.load_and_run(vec![0xa9, 0x50, 0x69, 0x50, 0x00])
+            .unwrap();
 
-                0xE8 => self.inx(),
+        assert_eq!(cpu.register_a, 0xa0);
+        assert!(cpu.status.contains(CpuFlags::OVERFLOW));
+        assert!(!cpu.status.contains(CpuFlags::CARRY));
 
-                0x00 => self.brk(),
+        cpu.load_and_run(vec![0xa9, 0xff, 0x69, 0x01, 0x00])
+            .unwrap();
 
-                _ => todo!(),
-            }
+        assert_eq!(cpu.register_a, 0x00);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+        assert!(cpu.status.contains(CpuFlags::ZERO));
+    }
+
+    #[test]
+    fn test_sbc_borrow() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // SEC; LDA #$05; SBC #$06
+        cpu.load_and_run(vec![0x38, 0xa9, 0x05, 0xe9, 0x06, 0x00])
+            .unwrap();
+
+        assert_eq!(cpu.register_a, 0xff);
+        assert!(!cpu.status.contains(CpuFlags::CARRY));
+        assert!(cpu.status.contains(CpuFlags::NEGATIVE));
+    }
+
+    #[test]
+    fn test_asl_and_ror_accumulator() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // LDA #$81; ASL A; ROR A
+        cpu.load_and_run(vec![0xa9, 0x81, 0x0a, 0x6a, 0x00])
+            .unwrap();
+
+        assert_eq!(cpu.register_a, 0x81);
+        assert!(!cpu.status.contains(CpuFlags::CARRY));
+    }
+
+    #[test]
+    fn test_inc_and_dec_memory() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.bus.memory_write(0x10, 0xff);
+        // INC $10; INC $10; DEC $11
+        cpu.load_and_run(vec![0xe6, 0x10, 0xe6, 0x10, 0xc6, 0x11, 0x00])
+            .unwrap();
+
+        assert_eq!(cpu.bus.memory_read(0x10), 0x01);
+        assert_eq!(cpu.bus.memory_read(0x11), 0xff);
+        assert!(cpu.status.contains(CpuFlags::NEGATIVE));
+    }
+
+    #[test]
+    fn test_cmp_sets_carry_and_zero() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.load_and_run(vec![0xa9, 0x40, 0xc9, 0x40, 0x00])
+            .unwrap();
+
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+        assert!(cpu.status.contains(CpuFlags::ZERO));
+    }
+
+    #[test]
+    fn test_branch_loop() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // LDX #$08; loop: DEX; INY; BNE loop (on X)
+        cpu.load_and_run(vec![0xa2, 0x08, 0xca, 0xc8, 0xe0, 0x00, 0xd0, 0xf9, 0x00])
+            .unwrap();
+
+        assert_eq!(cpu.register_x, 0);
+        assert_eq!(cpu.register_y, 8);
+    }
+
+    #[test]
+    fn test_jsr_and_rts() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // JSR sub; LDX #$01; BRK; sub: LDA #$42; RTS
+        cpu.load_and_run(vec![0x20, 0x06, 0x80, 0xa2, 0x01, 0x00, 0xa9, 0x42, 0x60])
+            .unwrap();
+
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.register_x, 0x01);
+    }
+
+    #[test]
+    fn test_pha_and_pla() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // LDA #$37; PHA; LDA #$00; PLA
+        cpu.load_and_run(vec![0xa9, 0x37, 0x48, 0xa9, 0x00, 0x68, 0x00])
+            .unwrap();
+
+        assert_eq!(cpu.register_a, 0x37);
+    }
+
+    #[test]
+    fn test_jmp_indirect_page_wrap_bug() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.bus.memory_write(0x02ff, 0x05);
+        cpu.bus.memory_write(0x0200, 0x80);
+        cpu.bus.memory_write(0x0300, 0x90);
+        // JMP ($02FF) lands on $8005: LDA #$11
+        cpu.load_and_run(vec![0x6c, 0xff, 0x02, 0x00, 0x00, 0xa9, 0x11, 0x00])
+            .unwrap();
+
+        assert_eq!(cpu.register_a, 0x11);
+    }
+
+    #[test]
+    fn test_bit_copies_high_bits() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.bus.memory_write(0x10, 0xc0);
+        cpu.load_and_run(vec![0xa9, 0x01, 0x24, 0x10, 0x00])
+            .unwrap();
+
+        assert!(cpu.status.contains(CpuFlags::ZERO));
+        assert!(cpu.status.contains(CpuFlags::OVERFLOW));
+        assert!(cpu.status.contains(CpuFlags::NEGATIVE));
+    }
+
+    #[test]
+    fn test_reset_initialises_stack_pointer() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.stack_pointer = 0x00;
+        cpu.load(vec![0x00]);
+        cpu.reset();
+
+        assert_eq!(cpu.stack_pointer, 0xfd);
+    }
+
+    #[test]
+    fn test_load_keeps_reset_vector_of_full_image() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        let mut program = vec![0xea; 0x8000];
+        program[0x7ffc..].copy_from_slice(&[0x00, 0x90, 0x00, 0x00]);
+        cpu.load(program);
+        cpu.reset();
+
+        assert_eq!(cpu.program_counter, 0x9000);
+    }
+
+    #[test]
+    #[should_panic(expected = "does not fit")]
+    fn test_load_rejects_oversize_program() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.load(vec![0xea; 0x8001]);
+    }
+
+    #[test]
+    fn test_reset_keeps_registers() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.reset();
+        cpu.register_a = 0x12;
+        cpu.register_x = 0x34;
+        cpu.register_y = 0x56;
+        cpu.status = CpuFlags::CARRY | CpuFlags::UNUSED;
+        cpu.stack_pointer = 0xf0;
+
+        cpu.reset();
+
+        assert_eq!(cpu.register_a, 0x12);
+        assert_eq!(cpu.register_x, 0x34);
+        assert_eq!(cpu.register_y, 0x56);
+        assert_eq!(
+            cpu.status,
+            CpuFlags::CARRY | CpuFlags::UNUSED | CpuFlags::INTERRUPT_DISABLE
+        );
+        assert_eq!(cpu.stack_pointer, 0xed);
+    }
+
+    #[test]
+    fn test_stack_push_pop_u16() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.reset();
+        cpu.stack_push_u16(0xbeef);
+
+        assert_eq!(cpu.stack_pointer, 0xfb);
+        assert_eq!(cpu.bus.memory_read(0x01fd), 0xbe);
+        assert_eq!(cpu.bus.memory_read(0x01fc), 0xef);
+        assert_eq!(cpu.stack_pop_u16(), 0xbeef);
+        assert_eq!(cpu.stack_pointer, 0xfd);
+    }
+
+    #[test]
+    fn test_stack_wraps_within_page_one() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.stack_pointer = 0x00;
+        cpu.stack_push(0x12);
+        cpu.stack_push(0x34);
+
+        assert_eq!(cpu.bus.memory_read(0x0100), 0x12);
+        assert_eq!(cpu.bus.memory_read(0x01ff), 0x34);
+        assert_eq!(cpu.bus.memory_read(0x00ff), 0x00);
+        assert_eq!(cpu.stack_pop(), 0x34);
+        assert_eq!(cpu.stack_pop(), 0x12);
+        assert_eq!(cpu.stack_pointer, 0x00);
+    }
+
+    #[test]
+    fn test_php_pushes_break_and_unused_bits() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // SEC; PHP
+        cpu.load_and_run(vec![0x38, 0x08, 0x00]).unwrap();
+
+        assert_eq!(cpu.bus.memory_read(0x01fd), 0b0011_0101);
+        assert!(!cpu.status.contains(CpuFlags::BREAK));
+    }
+
+    #[test]
+    fn test_plp_ignores_break_bit() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // LDA #$FF; PHA; LDA #$00; PLP
+        cpu.load_and_run(vec![0xa9, 0xff, 0x48, 0xa9, 0x00, 0x28, 0x00])
+            .unwrap();
+
+        assert_eq!(cpu.status, CpuFlags::all() - CpuFlags::BREAK);
+    }
+
+    #[test]
+    fn test_brk_pushes_return_address_and_status() {
+        let mut cpu = CPU::new(FlatRam::new());
+        cpu.load(vec![0xea, 0x00]);
+        cpu.reset();
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+
+        // BRK at $8001 returns to $8003, skipping its padding byte
+        assert_eq!(cpu.bus.memory_read(0x01fd), 0x80);
+        assert_eq!(cpu.bus.memory_read(0x01fc), 0x03);
+        assert_eq!(cpu.bus.memory_read(0x01fb), 0b0011_0100);
+        assert_eq!(cpu.stack_pointer, 0xfa);
+        assert!(cpu.status.contains(CpuFlags::INTERRUPT_DISABLE));
+    }
+
+    #[test]
+    fn test_cpu_flags_display() {
+        assert_eq!(CpuFlags::power_on_state().to_string(), "nv-bdIzc");
+        assert_eq!(CpuFlags::all().to_string(), "NV-BDIZC");
+        assert_eq!(
+            format!("{:?}", CpuFlags::NEGATIVE | CpuFlags::CARRY),
+            "CpuFlags(Nv-bdizC $81)"
+        );
+    }
+
+    #[test]
+    fn test_cpu_flags_push_and_pull() {
+        let flags = CpuFlags::CARRY | CpuFlags::ZERO;
+
+        assert_eq!(flags.pushed(true), 0b0011_0011);
+        assert_eq!(flags.pushed(false), 0b0010_0011);
+        assert_eq!(
+            CpuFlags::pulled(0b0001_0011),
+            CpuFlags::CARRY | CpuFlags::ZERO | CpuFlags::UNUSED
+        );
+    }
+
+    #[test]
+    fn test_reset_status() {
+        let mut cpu = CPU::new(FlatRam::new());
+        cpu.load(vec![0x00]);
+        cpu.reset();
+        cpu.step().unwrap();
+
+        assert_eq!(cpu.bus.memory_read(0x01fb), 0x34);
+        assert_eq!(cpu.status.bits(), 0x24);
+    }
+
+    #[test]
+    fn test_nmi_runs_handler_once_per_edge() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.bus.memory_write_u16(0xfffa, 0x9000);
+        // NMI handler: INY; RTI
+        cpu.bus.memory_write(0x9000, 0xc8);
+        cpu.bus.memory_write(0x9001, 0x40);
+        cpu.load(vec![0xe8, 0x00]);
+        cpu.reset();
+
+        cpu.set_nmi_line(true);
+        cpu.run().unwrap();
+
+        assert_eq!(cpu.register_x, 1);
+        assert_eq!(cpu.register_y, 1);
+    }
+
+    #[test]
+    fn test_irq_is_masked_by_interrupt_disable() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.bus.memory_write_u16(0xfffe, 0x9000);
+        cpu.bus.memory_write(0x9000, 0xa9);
+        cpu.bus.memory_write(0x9001, 0x42);
+        cpu.load(vec![0xa2, 0x01, 0x00]);
+        cpu.reset();
+
+        cpu.set_irq_line(IrqSource::EXTERNAL, true);
+        cpu.run().unwrap();
+
+        assert_eq!(cpu.register_x, 1);
+        assert_eq!(cpu.register_a, 0);
+    }
+
+    #[test]
+    fn test_irq_is_taken_one_instruction_after_cli() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.bus.memory_write_u16(0xfffe, 0x9000);
+        // IRQ handler: LDA #$42; BRK
+        cpu.bus.memory_write(0x9000, 0xa9);
+        cpu.bus.memory_write(0x9001, 0x42);
+        // CLI; LDX #$01; LDX #$02; BRK
+        cpu.load(vec![0x58, 0xa2, 0x01, 0xa2, 0x02, 0x00]);
+        cpu.reset();
+
+        cpu.set_irq_line(IrqSource::EXTERNAL, true);
+        cpu.run().unwrap();
+
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.register_x, 1);
+        // Return address is the second LDX, status has B clear and I clear
+        assert_eq!(cpu.bus.memory_read_u16(0x01fc), 0x8003);
+        assert_eq!(cpu.bus.memory_read(0x01fb), 0x20);
+    }
+
+    #[test]
+    fn test_irq_line_stays_asserted_until_every_source_releases() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.set_irq_line(IrqSource::APU_FRAME, true);
+        cpu.set_irq_line(IrqSource::MAPPER, true);
+        cpu.set_irq_line(IrqSource::APU_FRAME, false);
+
+        assert_eq!(cpu.irq_line, IrqSource::MAPPER);
+    }
+
+    #[test]
+    fn test_nmi_hijacks_brk() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.bus.memory_write_u16(0xfffa, 0x9000);
+        cpu.bus.memory_write_u16(0xfffe, 0xa000);
+        cpu.reset();
+        cpu.program_counter = 0x8001;
+
+        cpu.set_nmi_line(true);
+        cpu.brk();
+
+        assert_eq!(cpu.program_counter, 0x9000);
+        assert_eq!(cpu.bus.memory_read(0x01fb), 0x34);
+        assert!(!cpu.nmi_pending);
+    }
 
-            if program_counter_state == self.program_counter {
-                self.program_counter += (opcode.len - 1) as u16;
+    #[test]
+    fn test_run_with_callback_stops_on_break() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // INX in a loop: INX; JMP $8000
+        cpu.load(vec![0xe8, 0x4c, 0x00, 0x80]);
+        cpu.reset();
+        let mut instructions = 0;
+        cpu.run_with_callback(|cpu| {
+            if cpu.register_x == 10 {
+                return ControlFlow::Break(());
             }
+            instructions += 1;
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+
+        assert_eq!(instructions, 19);
+        assert_eq!(cpu.program_counter, 0x8001);
+    }
+
+    #[test]
+    fn test_cycles_count_reset_and_instructions() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // LDA #$01 (2); STA $0200 (4); BRK
+        cpu.load_and_run(vec![0xa9, 0x01, 0x8d, 0x00, 0x02, 0x00])
+            .unwrap();
+
+        assert_eq!(cpu.cycles, 7 + 2 + 4);
+    }
+
+    #[test]
+    fn test_cycles_page_cross_on_indexed_reads() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // LDX #$01 (2); LDA $02FF,X (4+1); LDA $0200,X (4); BRK
+        cpu.load_and_run(vec![0xa2, 0x01, 0xbd, 0xff, 0x02, 0xbd, 0x00, 0x02, 0x00])
+            .unwrap();
+
+        assert_eq!(cpu.cycles, 7 + 2 + 5 + 4);
+    }
+
+    #[test]
+    fn test_cycles_no_page_cross_penalty_on_writes() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // LDY #$01 (2); STA $02FF,Y (5); BRK
+        cpu.load_and_run(vec![0xa0, 0x01, 0x99, 0xff, 0x02, 0x00])
+            .unwrap();
+
+        assert_eq!(cpu.cycles, 7 + 2 + 5);
+    }
+
+    #[test]
+    fn test_cycles_page_cross_on_indirect_y() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.bus.memory_write_u16(0x10, 0x02ff);
+        // LDY #$01 (2); LDA ($10),Y (5+1); BRK
+        cpu.load_and_run(vec![0xa0, 0x01, 0xb1, 0x10, 0x00])
+            .unwrap();
+
+        assert_eq!(cpu.cycles, 7 + 2 + 6);
+    }
+
+    #[test]
+    fn test_cycles_branch_penalties() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // BNE not taken (2); BEQ +0 taken (3); BRK
+        cpu.load_and_run(vec![0xa9, 0x00, 0xd0, 0x00, 0xf0, 0x00, 0x00])
+            .unwrap();
+
+        assert_eq!(cpu.cycles, 7 + 2 + 2 + 3);
+        assert_eq!(cpu.program_counter, 0x8006);
+
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        let mut program = vec![0xea; 0x100];
+        // $8000: JMP $80F0; $80F0: BEQ to $8100 (taken, crosses a page)
+        program[0x00..0x03].copy_from_slice(&[0x4c, 0xf0, 0x80]);
+        program[0xf0..0xf4].copy_from_slice(&[0xa9, 0x00, 0xf0, 0x0c]);
+        program.extend_from_slice(&[0x00]);
+        cpu.load_and_run(program).unwrap();
+
+        // Halted on the BRK after the taken branch
+        assert_eq!(cpu.program_counter, 0x8100);
+        assert_eq!(cpu.cycles, 7 + 3 + 2 + 4);
+    }
+
+    #[test]
+    fn test_jump_to_next_byte() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // BNE $FF, taken, lands on its own operand
+        cpu.bus.memory_write(0x0200, 0xd0);
+        cpu.bus.memory_write(0x0201, 0xff);
+        cpu.program_counter = 0x0200;
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x0201);
+
+        // JMP $0301, the first operand byte
+        cpu.bus.memory_write(0x0300, 0x4c);
+        cpu.bus.memory_write_u16(0x0301, 0x0301);
+        cpu.program_counter = 0x0300;
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x0301);
+    }
+
+    /// Flat RAM with an NMI source that asserts once enough cycles pass
+    struct NmiAfterBus {
+        ram: FlatRam,
+        cycles: u64,
+        nmi_at: u64,
+    }
+
+    impl Bus for NmiAfterBus {
+        fn memory_read(&mut self, address: u16) -> u8 {
+            self.ram.memory_read(address)
+        }
+
+        fn memory_write(&mut self, address: u16, data: u8) {
+            self.ram.memory_write(address, data);
+        }
+
+        fn tick(&mut self, cycles: u8) {
+            self.cycles += cycles as u64;
+        }
+
+        fn nmi_line(&self) -> bool {
+            self.cycles >= self.nmi_at
         }
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    #[test]
+    fn test_nmi_from_bus_hijacks_irq_sequence() {
+        let mut cpu = CPU::with_test_mode(NmiAfterBus {
+            ram: FlatRam::new(),
+            cycles: 0,
+            // Reset (7) + CLI (2) + NOP (2), then part way into the IRQ
+            nmi_at: 7 + 2 + 2 + 3,
+        });
+        cpu.bus.memory_write_u16(0xfffa, 0x9000);
+        cpu.bus.memory_write_u16(0xfffe, 0xa000);
+        cpu.bus.memory_write(0x9000, 0x00);
+        cpu.load(vec![0x58, 0xea, 0xea, 0x00]);
+        cpu.reset();
+
+        cpu.set_irq_line(IrqSource::EXTERNAL, true);
+        cpu.run().unwrap();
+
+        // The IRQ frame was pushed with B clear but the NMI handler ran
+        assert_eq!(cpu.bus.memory_read_u16(0x01fc), 0x8002);
+        assert_eq!(cpu.bus.memory_read(0x01fb), 0x20);
+        // Halted on the BRK at the start of the NMI handler
+        assert_eq!(cpu.program_counter, 0x9000);
+        assert_eq!(cpu.stack_pointer, 0xfa);
+        assert!(!cpu.nmi_pending);
+    }
 
     #[test]
-    fn test_0xa9_lda_immidiate_load_data() {
-        let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0x05, 0x00]);
-        assert_eq!(cpu.register_a, 5);
-        assert!(cpu.status & 0b0000_0010 == 0);
-        assert!(cpu.status & 0b1000_0000 == 0);
+    fn test_lax_and_sax() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.bus.memory_write(0x10, 0x8f);
+        // LAX $10; LDX #$F0; SAX $11
+        cpu.load_and_run(vec![0xa7, 0x10, 0xa2, 0xf0, 0x87, 0x11, 0x00])
+            .unwrap();
+
+        assert_eq!(cpu.register_a, 0x8f);
+        assert_eq!(cpu.bus.memory_read(0x11), 0x80);
+        assert!(cpu.status.contains(CpuFlags::NEGATIVE));
     }
 
     #[test]
-    fn test_0xa9_lda_zero_flag() {
-        let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0x00, 0x00]);
-        assert!(cpu.status & 0b0000_0010 == 0b10);
+    fn test_dcp_and_isb() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.bus.memory_write(0x10, 0x41);
+        // LDA #$40; DCP $10
+        cpu.load_and_run(vec![0xa9, 0x40, 0xc7, 0x10, 0x00])
+            .unwrap();
+
+        assert_eq!(cpu.bus.memory_read(0x10), 0x40);
+        assert!(cpu.status.contains(CpuFlags::ZERO));
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+
+        // SEC; LDA #$40; ISB $11
+        cpu.load_and_run(vec![0x38, 0xa9, 0x40, 0xe7, 0x11, 0x00])
+            .unwrap();
+
+        assert_eq!(cpu.bus.memory_read(0x11), 0x01);
+        assert_eq!(cpu.register_a, 0x3f);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
     }
 
     #[test]
-    fn test_0xaa_tax_move_a_to_x() {
-        let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0x0A, 0xaa, 0x00]);
+    fn test_read_modify_write_combinations() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.bus.memory_write(0x10, 0x81);
+        cpu.bus.memory_write(0x11, 0x81);
+        cpu.bus.memory_write(0x12, 0x03);
+        cpu.bus.memory_write(0x13, 0x02);
+        // LDA #$01; SLO $10; RLA $11; SRE $12; RRA $13
+        cpu.load_and_run(vec![
+            0xa9, 0x01, 0x07, 0x10, 0x27, 0x11, 0x47, 0x12, 0x67, 0x13, 0x00,
+        ])
+        .unwrap();
 
-        assert_eq!(cpu.register_x, 10)
+        assert_eq!(cpu.bus.memory_read(0x10), 0x02);
+        assert_eq!(cpu.bus.memory_read(0x11), 0x03);
+        assert_eq!(cpu.bus.memory_read(0x12), 0x01);
+        assert_eq!(cpu.bus.memory_read(0x13), 0x81);
+        // ((($01 | $02) & $03) ^ $01) + $81
+        assert_eq!(cpu.register_a, 0x83);
+        assert!(!cpu.status.contains(CpuFlags::CARRY));
+        assert!(cpu.status.contains(CpuFlags::NEGATIVE));
     }
 
     #[test]
-    fn test_5_ops_working_together() {
-        let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00]);
+    fn test_unofficial_nop_lengths_and_cycles() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // NOP (2); NOP #$FF (2); NOP $10 (3); NOP $10,X (4); NOP $0200 (4);
+        // LDX #$01 (2); NOP $02FF,X (4+1); BRK
+        cpu.load_and_run(vec![
+            0x1a, 0x80, 0xff, 0x04, 0x10, 0x14, 0x10, 0x0c, 0x00, 0x02, 0xa2, 0x01, 0x1c, 0xff,
+            0x02, 0x00,
+        ])
+        .unwrap();
 
-        assert_eq!(cpu.register_x, 0xc1)
+        assert_eq!(cpu.register_a, 0x00);
+        assert_eq!(cpu.cycles, 7 + 2 + 2 + 3 + 4 + 4 + 2 + 5);
     }
 
     #[test]
-    fn test_inx_overflow() {
-        let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0xff, 0xaa, 0xe8, 0xe8, 0x00]);
+    fn test_immediate_combinations() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // LDA #$F0; ANC #$80
+        cpu.load_and_run(vec![0xa9, 0xf0, 0x0b, 0x80, 0x00])
+            .unwrap();
+        assert_eq!(cpu.register_a, 0x80);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
 
-        assert_eq!(cpu.register_x, 1)
+        // LDA #$FF; ALR #$03
+        cpu.load_and_run(vec![0xa9, 0xff, 0x4b, 0x03, 0x00])
+            .unwrap();
+        assert_eq!(cpu.register_a, 0x01);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+
+        // LDA #$FF; CLC; ARR #$80
+        cpu.load_and_run(vec![0xa9, 0xff, 0x18, 0x6b, 0x80, 0x00])
+            .unwrap();
+        assert_eq!(cpu.register_a, 0x40);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+        assert!(cpu.status.contains(CpuFlags::OVERFLOW));
+
+        // LDA #$0F; LDX #$07; AXS #$02
+        cpu.load_and_run(vec![0xa9, 0x0f, 0xa2, 0x07, 0xcb, 0x02, 0x00])
+            .unwrap();
+        assert_eq!(cpu.register_x, 0x05);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
     }
 
     #[test]
-    fn test_lda_from_memory() {
-        let mut cpu = CPU::new();
-        cpu.memory_write(0x10, 0x55);
+    fn test_shx_page_cross_replaces_high_byte() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // LDX #$12; LDY #$01; SHX $02FF,Y
+        cpu.load_and_run(vec![0xa2, 0x12, 0xa0, 0x01, 0x9e, 0xff, 0x02, 0x00])
+            .unwrap();
 
-        cpu.load_and_run(vec![0xa5, 0x10, 0x00]);
+        // $12 & ($02 + 1) lands on $0200 rather than $0300
+        assert_eq!(cpu.bus.memory_read(0x0200), 0x02);
+        assert_eq!(cpu.bus.memory_read(0x0300), 0x00);
+    }
 
-        assert_eq!(cpu.register_a, 0x55);
+    #[test]
+    fn test_jam_halts_until_reset() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // LDA #$01; JAM; LDA #$02
+        let result = cpu.load_and_run(vec![0xa9, 0x01, 0x02, 0xa9, 0x02, 0x00]);
+
+        assert_eq!(result, Err(CpuError::Jam { pc: 0x8002 }));
+        assert!(cpu.jammed());
+        assert_eq!(cpu.register_a, 0x01);
+
+        assert_eq!(cpu.step().unwrap_err(), CpuError::Jam { pc: 0x8002 });
+        assert_eq!(cpu.program_counter, 0x8002);
+
+        cpu.reset();
+        assert!(!cpu.jammed());
+    }
+
+    #[test]
+    fn test_trap_on_unofficial_opcode() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.unofficial_opcodes = UnofficialOpcodes::Trap;
+        let result = cpu.load_and_run(vec![0xa7, 0x10, 0x00]);
+
+        assert_eq!(
+            result,
+            Err(CpuError::UnofficialOpcode {
+                pc: 0x8000,
+                opcode: 0xa7
+            })
+        );
+        // Nothing ran, so the program counter is left on the opcode
+        assert_eq!(cpu.program_counter, 0x8000);
+        assert_eq!(cpu.cycles, 7);
+    }
+
+    #[test]
+    fn test_halt_on_jam_only_lets_jam_through() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.unofficial_opcodes = UnofficialOpcodes::HaltOnJam;
+        let result = cpu.load_and_run(vec![0xea, 0x12, 0x00]);
+        assert_eq!(result, Err(CpuError::Jam { pc: 0x8001 }));
+
+        let result = cpu.load_and_run(vec![0x1a, 0x00]);
+        assert_eq!(
+            result,
+            Err(CpuError::UnofficialOpcode {
+                pc: 0x8000,
+                opcode: 0x1a
+            })
+        );
+    }
+
+    #[test]
+    fn test_step_runs_one_instruction() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // LDA #$01; LDX #$02
+        cpu.load(vec![0xa9, 0x01, 0xa2, 0x02]);
+        cpu.reset();
+
+        let step = cpu.step().unwrap().unwrap();
+        assert_eq!(step.pc, 0x8000);
+        assert_eq!(step.opcode.mnemonic, "LDA");
+        assert_eq!(step.cycles, 2);
+        assert_eq!(cpu.register_a, 0x01);
+        assert_eq!(cpu.register_x, 0x00);
+        assert_eq!(cpu.program_counter, 0x8002);
+    }
+
+    #[test]
+    fn test_step_cycles_include_interrupt_sequence() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.bus.memory_write_u16(0xfffa, 0x9000);
+        cpu.bus.memory_write(0x9000, 0xea);
+        cpu.load(vec![0xea]);
+        cpu.reset();
+
+        cpu.set_nmi_line(true);
+        let step = cpu.step().unwrap().unwrap();
+
+        // The NMI sequence (7) and then the NOP in the handler (2)
+        assert_eq!(step.pc, 0x9000);
+        assert_eq!(step.cycles, 7 + 2);
+    }
+
+    #[test]
+    fn test_brk_halts_in_test_mode() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // LDA #$01; BRK; LDA #$02
+        cpu.load_and_run(vec![0xa9, 0x01, 0x00, 0xa9, 0x02])
+            .unwrap();
+
+        assert!(cpu.halted());
+        assert!(cpu.step().unwrap().is_none());
+        assert_eq!(cpu.register_a, 0x01);
+        // Stopped on the BRK without entering the handler
+        assert_eq!(cpu.program_counter, 0x8002);
+        assert_eq!(cpu.stack_pointer, 0xfd);
+        assert_eq!(cpu.cycles, 7 + 2);
+
+        cpu.reset();
+        assert!(!cpu.halted());
+    }
+
+    #[test]
+    fn test_step_reaching_brk_counts_interrupt_cycles() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.bus.memory_write_u16(0xfffa, 0x9000);
+        cpu.bus.memory_write(0x9000, 0x00);
+        cpu.load(vec![0xea]);
+        cpu.reset();
+
+        cpu.set_nmi_line(true);
+        let step = cpu.step().unwrap().unwrap();
+
+        assert!(cpu.halted());
+        assert_eq!(step.pc, 0x9000);
+        assert_eq!(step.opcode.mnemonic, "BRK");
+        assert_eq!(step.cycles, 7);
+        assert_eq!(cpu.program_counter, 0x9000);
+        assert!(cpu.step().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_brk_enters_handler_outside_test_mode() {
+        let mut cpu = CPU::new(FlatRam::new());
+        cpu.bus.memory_write_u16(0xfffe, 0x9000);
+        // LDA #$01
+        cpu.bus.memory_write(0x9000, 0xa9);
+        cpu.bus.memory_write(0x9001, 0x01);
+        cpu.load(vec![0x00]);
+        cpu.reset();
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+
+        assert!(!cpu.halted());
+        assert_eq!(cpu.register_a, 0x01);
+        assert_eq!(cpu.program_counter, 0x9002);
+    }
+
+    #[test]
+    fn test_run_until() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // INX; JMP $8000
+        cpu.load(vec![0xe8, 0x4c, 0x00, 0x80]);
+        cpu.reset();
+
+        cpu.run_until(|cpu| cpu.register_x == 5).unwrap();
+
+        assert_eq!(cpu.register_x, 5);
+        assert_eq!(cpu.program_counter, 0x8001);
+    }
+
+    #[test]
+    fn test_run_for_cycles() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // INX (2); JMP $8000 (3)
+        cpu.load(vec![0xe8, 0x4c, 0x00, 0x80]);
+        cpu.reset();
+
+        // Whole instructions only, so asking for 11 runs on to the end of
+        // the third INX at 12
+        cpu.run_for_cycles(11).unwrap();
+
+        assert_eq!(cpu.cycles, 7 + 12);
+        assert_eq!(cpu.register_x, 3);
+    }
+
+    #[test]
+    fn test_run_frame() {
+        let rom = crate::cartridge::test::test_rom(vec![0x4c, 0x00, 0x80]);
+        let mut cpu = CPU::new(NesBus::new(crate::mapper::from_rom(rom).unwrap()));
+        cpu.reset();
+
+        cpu.run_frame().unwrap();
+        assert_eq!(cpu.bus.ppu.frame_count, 1);
+        cpu.run_frame().unwrap();
+        assert_eq!(cpu.bus.ppu.frame_count, 2);
+    }
+
+    #[test]
+    fn test_program_counter_wraps_at_end_of_memory() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        // LDA #$42 with its operand wrapped round to $0000, then NOP at $FFFF
+        cpu.bus.memory_write(0xffff, 0xa9);
+        cpu.bus.memory_write(0x0000, 0x42);
+        cpu.program_counter = 0xffff;
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.program_counter, 0x0001);
+
+        cpu.bus.memory_write(0xffff, 0xea);
+        cpu.program_counter = 0xffff;
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x0000);
+    }
+
+    #[test]
+    fn test_invalid_addressing_is_reported() {
+        let mut cpu = CPU::with_test_mode(FlatRam::new());
+        cpu.program_counter = 0x8001;
+        cpu.lda(&AddressingMode::NoneAddressing);
+
+        assert_eq!(
+            cpu.fault,
+            Some(CpuError::InvalidAddressing {
+                pc: 0x8000,
+                mode: AddressingMode::NoneAddressing
+            })
+        );
+    }
+
+    /// Flat RAM with nothing behind $5000
+    struct FaultyBus {
+        ram: FlatRam,
+        fault: Option<u16>,
+    }
+
+    impl Bus for FaultyBus {
+        fn memory_read(&mut self, address: u16) -> u8 {
+            if address == 0x5000 {
+                self.fault = Some(address);
+            }
+            self.ram.memory_read(address)
+        }
+
+        fn memory_write(&mut self, address: u16, data: u8) {
+            self.ram.memory_write(address, data);
+        }
+
+        fn take_fault(&mut self) -> Option<u16> {
+            self.fault.take()
+        }
+    }
+
+    #[test]
+    fn test_bus_fault_is_reported() {
+        let mut cpu = CPU::with_test_mode(FaultyBus {
+            ram: FlatRam::new(),
+            fault: None,
+        });
+        // LDA #$01; LDA $5000
+        let result = cpu.load_and_run(vec![0xa9, 0x01, 0xad, 0x00, 0x50, 0x00]);
+
+        assert_eq!(
+            result,
+            Err(CpuError::BusFault {
+                pc: 0x8002,
+                address: 0x5000
+            })
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "bus fault at $8002 accessing $5000"
+        );
     }
 }
@@ -0,0 +1,72 @@
+use bitflags::bitflags;
+
+/// Interrupts
+/// ==========
+/// The 2A03 has three interrupt vectors at the top of memory. BRK shares
+/// the IRQ vector and is only told apart from a real IRQ by the B flag in
+/// the copy of the status register pushed to the stack.
+///
+/// Vector | Address | Source
+/// NMI    | $FFFA/B | PPU vblank (edge triggered, cannot be masked)
+/// RESET  | $FFFC/D | Power on and the reset button
+/// IRQ    | $FFFE/F | APU frame counter, DMC and mappers (level
+///        |         | triggered, masked by the I flag), and BRK
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum InterruptType {
+    Nmi,
+    Reset,
+    Irq,
+    Brk,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Interrupt {
+    pub itype: InterruptType,
+    pub vector_address: u16,
+    pub break_flag: bool,
+    pub cpu_cycles: u8,
+}
+
+pub const NMI: Interrupt = Interrupt {
+    itype: InterruptType::Nmi,
+    vector_address: 0xFFFA,
+    break_flag: false,
+    cpu_cycles: 7,
+};
+
+pub const RESET: Interrupt = Interrupt {
+    itype: InterruptType::Reset,
+    vector_address: 0xFFFC,
+    break_flag: false,
+    cpu_cycles: 7,
+};
+
+pub const IRQ: Interrupt = Interrupt {
+    itype: InterruptType::Irq,
+    vector_address: 0xFFFE,
+    break_flag: false,
+    cpu_cycles: 7,
+};
+
+pub const BRK: Interrupt = Interrupt {
+    itype: InterruptType::Brk,
+    vector_address: 0xFFFE,
+    break_flag: true,
+    cpu_cycles: 7,
+};
+
+bitflags! {
+    /// IRQ Sources
+    /// ===========
+    /// The IRQ line is open collector, any device can hold it low and it
+    /// stays asserted until every device has let go. Each source is tracked
+    /// separately so one device acknowledging its interrupt does not clear
+    /// another's.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct IrqSource: u8 {
+        const EXTERNAL  = 0b0000_0001;
+        const APU_FRAME = 0b0000_0010;
+        const APU_DMC   = 0b0000_0100;
+        const MAPPER    = 0b0000_1000;
+    }
+}
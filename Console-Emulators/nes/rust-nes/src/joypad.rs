@@ -0,0 +1,149 @@
+use bitflags::bitflags;
+
+bitflags! {
+    /// Standard Controller Buttons
+    /// 7  bit  0
+    /// ---- ----
+    /// RLDU TSBA
+    /// |||| ||||
+    /// |||| |||+- A
+    /// |||| ||+-- B
+    /// |||| |+--- Select
+    /// |||| +---- Start
+    /// |||+------ Up
+    /// ||+------- Down
+    /// |+-------- Left
+    /// +--------- Right
+    ///
+    /// Bit 0 is the first button shifted out after a strobe.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct JoypadButton: u8 {
+        const BUTTON_A = 0b0000_0001;
+        const BUTTON_B = 0b0000_0010;
+        const SELECT   = 0b0000_0100;
+        const START    = 0b0000_1000;
+        const UP       = 0b0001_0000;
+        const DOWN     = 0b0010_0000;
+        const LEFT     = 0b0100_0000;
+        const RIGHT    = 0b1000_0000;
+    }
+}
+
+/// Standard Controller
+/// ===================
+/// A parallel in, serial out shift register. Writing 1 to bit 0 of $4016
+/// holds it in strobe mode, where it keeps latching the buttons and every
+/// read returns A. Writing 0 stops latching and each read of $4016 for
+/// player 1 or $4017 for player 2 shifts out the next button. After all
+/// eight the official controllers return 1.
+///
+/// Only bit 0 of a read comes from the controller, the rest of the byte is
+/// left to the CPU bus.
+#[derive(Debug, Default)]
+pub struct Joypad {
+    strobe: bool,
+    shift_register: u8,
+    buttons: JoypadButton,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Joypad::default()
+    }
+
+    /// Sets the whole button state, which is how a host hands over the
+    /// input for a frame
+    pub fn set_buttons(&mut self, buttons: JoypadButton) {
+        self.buttons = buttons;
+        self.latch();
+    }
+
+    pub fn set_button_pressed(&mut self, button: JoypadButton, pressed: bool) {
+        self.buttons.set(button, pressed);
+        self.latch();
+    }
+
+    pub fn buttons(&self) -> JoypadButton {
+        self.buttons
+    }
+
+    pub fn write(&mut self, data: u8) {
+        self.strobe = data & 1 == 1;
+        self.latch();
+    }
+
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            return self.buttons.bits() & 1;
+        }
+        let bit = self.shift_register & 1;
+        self.shift_register = (self.shift_register >> 1) | 0x80;
+        bit
+    }
+
+    fn latch(&mut self) {
+        if self.strobe {
+            self.shift_register = self.buttons.bits();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn read_all(joypad: &mut Joypad) -> Vec<u8> {
+        (0..8).map(|_| joypad.read()).collect()
+    }
+
+    #[test]
+    fn test_reads_buttons_in_order_after_strobe() {
+        let mut joypad = Joypad::new();
+        joypad.set_buttons(JoypadButton::BUTTON_A | JoypadButton::START | JoypadButton::RIGHT);
+        joypad.write(1);
+        joypad.write(0);
+
+        assert_eq!(read_all(&mut joypad), vec![1, 0, 0, 1, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_returns_1_after_eight_reads() {
+        let mut joypad = Joypad::new();
+        joypad.write(1);
+        joypad.write(0);
+        read_all(&mut joypad);
+
+        assert_eq!(joypad.read(), 1);
+        assert_eq!(joypad.read(), 1);
+    }
+
+    #[test]
+    fn test_strobe_held_high_returns_a() {
+        let mut joypad = Joypad::new();
+        joypad.write(1);
+        joypad.set_button_pressed(JoypadButton::BUTTON_A, true);
+
+        assert_eq!(joypad.read(), 1);
+        assert_eq!(joypad.read(), 1);
+
+        joypad.set_button_pressed(JoypadButton::BUTTON_A, false);
+        assert_eq!(joypad.read(), 0);
+    }
+
+    #[test]
+    fn test_buttons_latched_when_strobe_ends() {
+        let mut joypad = Joypad::new();
+        joypad.set_button_pressed(JoypadButton::BUTTON_B, true);
+        joypad.write(1);
+        joypad.write(0);
+        // Changes after the strobe wait for the next one
+        joypad.set_button_pressed(JoypadButton::BUTTON_A, true);
+
+        assert_eq!(joypad.read(), 0);
+        assert_eq!(joypad.read(), 1);
+        assert_eq!(
+            joypad.buttons(),
+            JoypadButton::BUTTON_A | JoypadButton::BUTTON_B
+        );
+    }
+}
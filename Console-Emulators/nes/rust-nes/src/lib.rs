@@ -0,0 +1,15 @@
+pub mod apu;
+pub mod audio;
+pub mod blargg;
+pub mod bus;
+pub mod cartridge;
+pub mod cpu;
+pub mod interrupt;
+pub mod joypad;
+pub mod mapper;
+pub mod opcodes;
+pub mod ppu;
+pub mod trace;
+
+#[macro_use]
+extern crate lazy_static;
@@ -1,9 +1,190 @@
-pub mod cpu;
-pub mod opcodes;
+use rust_nes::apu::Apu;
+use rust_nes::audio::{SampleRate, WavSink};
+use rust_nes::bus::NesBus;
+use rust_nes::cartridge::Rom;
+use rust_nes::cpu::CPU;
+use rust_nes::{mapper, trace};
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::ControlFlow;
+use std::process;
 
-#[macro_use]
-extern crate lazy_static;
+const USAGE: &str =
+    "usage: rust-nes <rom.nes> [--frames N] [--wav out.wav] [--sample-rate 44100|48000] [--trace out.log]";
+
+/// Ten seconds of NTSC video
+const DEFAULT_FRAMES: u64 = 600;
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    rom: String,
+    frames: u64,
+    wav: Option<String>,
+    sample_rate: SampleRate,
+    trace: Option<String>,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut rom = None;
+    let mut frames = DEFAULT_FRAMES;
+    let mut wav = None;
+    let mut sample_rate = SampleRate::Hz44100;
+    let mut trace = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        match arg.as_str() {
+            "--frames" => {
+                frames = value("--frames")?
+                    .parse()
+                    .map_err(|_| "--frames must be a whole number".to_string())?
+            }
+            "--wav" => wav = Some(value("--wav")?),
+            "--sample-rate" => sample_rate = value("--sample-rate")?.parse()?,
+            "--trace" => trace = Some(value("--trace")?),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ if rom.is_none() => rom = Some(arg),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+
+    Ok(Options {
+        rom: rom.ok_or("no ROM given")?,
+        frames,
+        wav,
+        sample_rate,
+        trace,
+    })
+}
+
+/// Runs the ROM headless for the requested number of frames, collecting
+/// audio and optionally an instruction trace along the way
+fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    let rom = Rom::from_file(&options.rom)?;
+    let mut cpu = CPU::new(NesBus::new(mapper::from_rom(rom)?));
+    // Generating samples at the output rate saves the sink resampling them
+    cpu.bus.apu = Apu::new(options.sample_rate.hz());
+    cpu.reset();
+
+    let mut log = match &options.trace {
+        Some(path) => Some(BufWriter::new(File::create(path)?)),
+        None => None,
+    };
+    let mut log_error = None;
+
+    let mut sink = WavSink::new(cpu.bus.apu.sample_rate(), options.sample_rate);
+    let mut frame = cpu.bus.ppu.frame_count;
+    let result = cpu.run_with_callback(|cpu| {
+        // Samples are drained once a frame, not after every instruction
+        if cpu.bus.ppu.frame_count != frame {
+            frame = cpu.bus.ppu.frame_count;
+            sink.push(&cpu.bus.apu.take_samples());
+        }
+        if frame >= options.frames {
+            return ControlFlow::Break(());
+        }
+
+        if let Some(log) = &mut log {
+            if let Err(error) = writeln!(log, "{}", trace::trace(cpu)) {
+                log_error = Some(error);
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    });
+
+    sink.push(&cpu.bus.apu.take_samples());
+
+    if let Some(error) = log_error {
+        return Err(error.into());
+    }
+    if let Some(log) = &mut log {
+        log.flush()?;
+    }
+    // The trace up to a CPU error is worth keeping, so it is flushed first
+    result?;
+
+    if let Some(path) = &options.wav {
+        sink.save(path)?;
+        println!(
+            "Wrote {} samples at {}Hz to {}",
+            sink.samples().len(),
+            sink.sample_rate(),
+            path
+        );
+    }
+    Ok(())
+}
 
 fn main() {
-    println!("Hello, world!");
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(error) = run(&options) {
+        eprintln!("{}: {}", options.rom, error);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse(&[
+            "game.nes",
+            "--frames",
+            "120",
+            "--wav",
+            "out.wav",
+            "--sample-rate",
+            "48000",
+            "--trace",
+            "trace.log",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            options,
+            Options {
+                rom: "game.nes".to_string(),
+                frames: 120,
+                wav: Some("out.wav".to_string()),
+                sample_rate: SampleRate::Hz48000,
+                trace: Some("trace.log".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_args_defaults() {
+        let options = parse(&["game.nes"]).unwrap();
+
+        assert_eq!(options.frames, DEFAULT_FRAMES);
+        assert_eq!(options.wav, None);
+        assert_eq!(options.sample_rate, SampleRate::Hz44100);
+        assert_eq!(options.trace, None);
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["game.nes", "--frames"]).is_err());
+        assert!(parse(&["game.nes", "--frames", "ten"]).is_err());
+        assert!(parse(&["game.nes", "--sample-rate", "22050"]).is_err());
+        assert!(parse(&["game.nes", "--speed", "2"]).is_err());
+        assert!(parse(&["game.nes", "other.nes"]).is_err());
+    }
 }
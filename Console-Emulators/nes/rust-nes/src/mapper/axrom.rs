@@ -0,0 +1,99 @@
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::{self, Chr, Mapper, PRG_ROM, PRG_ROM_END};
+
+/// AxROM (Mapper 7)
+/// ================
+/// Switches the whole $8000-$FFFF window in 32KB banks and picks which
+/// 1KB of VRAM every nametable uses. CHR is 8KB of RAM.
+///
+/// Register ($8000-$FFFF)
+/// 7  bit  0
+/// ---- ----
+/// xxxM xPPP
+///    |  |||
+///    |  +++- Select 32KB PRG-ROM bank for $8000-$FFFF
+///    +------ Select 1KB VRAM page for all 4 nametables
+pub struct AxRom {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    prg_bank: usize,
+    mirroring: Mirroring,
+}
+
+impl AxRom {
+    pub fn new(mut rom: Rom) -> Self {
+        AxRom {
+            chr: Chr::new(&mut rom),
+            prg_rom: rom.prg_rom,
+            prg_bank: 0,
+            mirroring: Mirroring::SingleScreenLower,
+        }
+    }
+}
+
+impl Mapper for AxRom {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            PRG_ROM..=PRG_ROM_END => {
+                let offset =
+                    mapper::bank_offset(self.prg_rom.len(), self.prg_bank, 0x8000, address);
+                self.prg_rom[offset]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        if let PRG_ROM..=PRG_ROM_END = address {
+            self.prg_bank = data as usize & 0b111;
+            self.mirroring = if data & 0b0001_0000 == 0 {
+                Mirroring::SingleScreenLower
+            } else {
+                Mirroring::SingleScreenUpper
+            };
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        self.chr.read(0, 0x2000, address)
+    }
+
+    fn chr_write(&mut self, address: u16, data: u8) {
+        self.chr.write(0, 0x2000, address, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::test::banked_rom;
+
+    #[test]
+    fn test_32kb_bank_select() {
+        let mut axrom = AxRom::new(banked_rom(7, 4, 0x8000, 0, 0x2000));
+
+        assert_eq!(axrom.cpu_read(0x8000), 0);
+
+        axrom.cpu_write(0x8000, 0b0000_0010);
+        assert_eq!(axrom.cpu_read(0x8000), 2);
+        assert_eq!(axrom.cpu_read(0xffff), 2);
+    }
+
+    #[test]
+    fn test_single_screen_select() {
+        let mut axrom = AxRom::new(banked_rom(7, 4, 0x8000, 0, 0x2000));
+
+        assert_eq!(axrom.mirroring(), Mirroring::SingleScreenLower);
+
+        axrom.cpu_write(0x8000, 0b0001_0001);
+        assert_eq!(axrom.mirroring(), Mirroring::SingleScreenUpper);
+        assert_eq!(axrom.cpu_read(0x8000), 1);
+
+        axrom.cpu_write(0x8000, 0b0000_0001);
+        assert_eq!(axrom.mirroring(), Mirroring::SingleScreenLower);
+    }
+}
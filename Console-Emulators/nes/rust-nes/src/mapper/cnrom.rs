@@ -0,0 +1,92 @@
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::{self, Chr, Mapper, PRG_ROM, PRG_ROM_END};
+
+/// CNROM (Mapper 3)
+/// ================
+/// PRG-ROM is laid out as NROM. Any write to $8000-$FFFF selects which
+/// 8KB CHR-ROM bank the PPU sees.
+///
+/// Register ($8000-$FFFF)
+/// 7  bit  0
+/// ---- ----
+/// xxxx xxCC
+///        ||
+///        ++- Select 8KB CHR-ROM bank for PPU $0000-$1FFF
+pub struct CnRom {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    mirroring: Mirroring,
+    chr_bank: usize,
+}
+
+impl CnRom {
+    pub fn new(mut rom: Rom) -> Self {
+        CnRom {
+            chr: Chr::new(&mut rom),
+            mirroring: rom.screen_mirroring,
+            prg_rom: rom.prg_rom,
+            chr_bank: 0,
+        }
+    }
+}
+
+impl Mapper for CnRom {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            PRG_ROM..=PRG_ROM_END => {
+                let offset = mapper::bank_offset(self.prg_rom.len(), 0, 0x8000, address);
+                self.prg_rom[offset]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        if let PRG_ROM..=PRG_ROM_END = address {
+            // Some boards decode more than two bits, the bank number wraps
+            // to the CHR-ROM that is actually present either way
+            self.chr_bank = data as usize;
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        self.chr.read(self.chr_bank, 0x2000, address)
+    }
+
+    fn chr_write(&mut self, address: u16, data: u8) {
+        self.chr.write(self.chr_bank, 0x2000, address, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::test::banked_rom;
+
+    #[test]
+    fn test_chr_bank_select() {
+        let mut cnrom = CnRom::new(banked_rom(3, 2, 0x4000, 4, 0x2000));
+
+        assert_eq!(cnrom.chr_read(0x0000), 0);
+
+        cnrom.cpu_write(0x8000, 2);
+        assert_eq!(cnrom.chr_read(0x0000), 2);
+        assert_eq!(cnrom.chr_read(0x1fff), 2);
+
+        cnrom.cpu_write(0xffff, 5);
+        assert_eq!(cnrom.chr_read(0x1000), 1);
+    }
+
+    #[test]
+    fn test_prg_rom_is_fixed() {
+        let mut cnrom = CnRom::new(banked_rom(3, 2, 0x4000, 4, 0x2000));
+        cnrom.cpu_write(0x8000, 1);
+
+        assert_eq!(cnrom.cpu_read(0x8000), 0);
+        assert_eq!(cnrom.cpu_read(0xc000), 1);
+    }
+}
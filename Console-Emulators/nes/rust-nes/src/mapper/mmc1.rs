@@ -0,0 +1,295 @@
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::{self, Chr, Mapper, PRG_RAM, PRG_RAM_END, PRG_ROM, PRG_ROM_END};
+
+/// MMC1 (Mapper 1)
+/// ===============
+/// Registers are loaded one bit at a time through a 5 bit shift register.
+/// Each write to $8000-$FFFF shifts bit 0 in, and the fifth write copies the
+/// value into the register picked by bits 13 and 14 of that last address.
+/// Writing a value with bit 7 set clears the shift register instead.
+///
+/// Address     | Register
+/// $8000-$9FFF | Control
+/// $A000-$BFFF | CHR bank 0
+/// $C000-$DFFF | CHR bank 1
+/// $E000-$FFFF | PRG bank
+///
+/// Control
+/// 4bit0
+/// -----
+/// CPPMM
+/// |||||
+/// |||++- Mirroring (0: one screen lower, 1: one screen upper,
+/// |||               2: vertical, 3: horizontal)
+/// |++--- PRG-ROM bank mode (0, 1: switch 32KB at $8000, ignoring the
+/// |                         low bit of the bank number;
+/// |                         2: fix first bank at $8000, switch $C000;
+/// |                         3: fix last bank at $C000, switch $8000)
+/// +----- CHR bank mode (0: switch 8KB at a time, 1: two 4KB banks)
+///
+/// PRG bank
+/// 4bit0
+/// -----
+/// RPPPP
+/// |||||
+/// |++++- Select 16KB PRG-ROM bank
+/// +----- PRG-RAM chip enable (0: enabled)
+///
+/// On 512KB SUROM boards bit 4 of the CHR bank registers selects which
+/// 256KB half of PRG-ROM the PRG bank register works within.
+pub struct Mmc1 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Chr,
+    shift_register: u8,
+    shift_count: u8,
+    control: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8,
+}
+
+const CONTROL_RESET: u8 = 0b0_1100;
+
+impl Mmc1 {
+    pub fn new(mut rom: Rom) -> Self {
+        Mmc1 {
+            prg_ram: mapper::prg_ram(&rom),
+            chr: Chr::new(&mut rom),
+            prg_rom: rom.prg_rom,
+            shift_register: 0,
+            shift_count: 0,
+            control: CONTROL_RESET,
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+        }
+    }
+
+    fn write_register(&mut self, address: u16, data: u8) {
+        if data & 0b1000_0000 != 0 {
+            self.shift_register = 0;
+            self.shift_count = 0;
+            self.control |= CONTROL_RESET;
+            return;
+        }
+
+        self.shift_register |= (data & 1) << self.shift_count;
+        self.shift_count += 1;
+
+        if self.shift_count == 5 {
+            let value = self.shift_register;
+            match address {
+                0x8000..=0x9FFF => self.control = value,
+                0xA000..=0xBFFF => self.chr_bank_0 = value,
+                0xC000..=0xDFFF => self.chr_bank_1 = value,
+                _ => self.prg_bank = value,
+            }
+            self.shift_register = 0;
+            self.shift_count = 0;
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank & 0b1_0000 == 0
+    }
+
+    /// The 256KB outer bank used by SUROM, zero on smaller boards
+    fn prg_outer_bank(&self) -> usize {
+        if self.prg_rom.len() > 0x40000 && self.chr_bank_0 & 0b1_0000 != 0 {
+            0x40000
+        } else {
+            0
+        }
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> usize {
+        let bank = (self.prg_bank & 0b1111) as usize;
+        let last_bank = (self.prg_rom.len().min(0x40000) / 0x4000).saturating_sub(1);
+        let upper = address >= 0xC000;
+
+        let bank = match (self.control >> 2) & 0b11 {
+            0 | 1 => (bank & !1) + upper as usize,
+            2 if upper => bank,
+            2 => 0,
+            _ if upper => last_bank,
+            _ => bank,
+        };
+
+        self.prg_outer_bank()
+            + mapper::bank_offset(self.prg_rom.len().min(0x40000), bank, 0x4000, address)
+    }
+
+    fn chr_bank(&self, address: u16) -> (usize, usize) {
+        if self.control & 0b1_0000 == 0 {
+            ((self.chr_bank_0 >> 1) as usize, 0x2000)
+        } else if address < 0x1000 {
+            (self.chr_bank_0 as usize, 0x1000)
+        } else {
+            (self.chr_bank_1 as usize, 0x1000)
+        }
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            PRG_RAM..=PRG_RAM_END if self.prg_ram_enabled() => {
+                mapper::read_prg_ram(&self.prg_ram, address)
+            }
+            PRG_ROM..=PRG_ROM_END => self.prg_rom[self.prg_rom_offset(address)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        match address {
+            PRG_RAM..=PRG_RAM_END if self.prg_ram_enabled() => {
+                mapper::write_prg_ram(&mut self.prg_ram, address, data)
+            }
+            PRG_ROM..=PRG_ROM_END => self.write_register(address, data),
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        let (bank, bank_size) = self.chr_bank(address);
+        self.chr.read(bank, bank_size, address)
+    }
+
+    fn chr_write(&mut self, address: u16, data: u8) {
+        let (bank, bank_size) = self.chr_bank(address);
+        self.chr.write(bank, bank_size, address, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0b11 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::test::banked_rom;
+
+    fn load_register(mmc1: &mut Mmc1, address: u16, value: u8) {
+        for bit in 0..5 {
+            mmc1.cpu_write(address, (value >> bit) & 1);
+        }
+    }
+
+    #[test]
+    fn test_power_on_fixes_last_bank() {
+        let mut mmc1 = Mmc1::new(banked_rom(1, 8, 0x4000, 2, 0x2000));
+
+        assert_eq!(mmc1.cpu_read(0x8000), 0);
+        assert_eq!(mmc1.cpu_read(0xc000), 7);
+    }
+
+    #[test]
+    fn test_shift_register_loads_after_five_writes() {
+        let mut mmc1 = Mmc1::new(banked_rom(1, 8, 0x4000, 2, 0x2000));
+
+        for bit in 0..4 {
+            mmc1.cpu_write(0xe000, (5 >> bit) & 1);
+        }
+        assert_eq!(mmc1.cpu_read(0x8000), 0);
+
+        mmc1.cpu_write(0xe000, 0);
+        assert_eq!(mmc1.cpu_read(0x8000), 5);
+        assert_eq!(mmc1.cpu_read(0xc000), 7);
+    }
+
+    #[test]
+    fn test_reset_bit_clears_shift_register() {
+        let mut mmc1 = Mmc1::new(banked_rom(1, 8, 0x4000, 2, 0x2000));
+        load_register(&mut mmc1, 0x8000, 0b0_0010);
+        assert_eq!(mmc1.mirroring(), Mirroring::Vertical);
+
+        mmc1.cpu_write(0xe000, 1);
+        mmc1.cpu_write(0xe000, 1);
+        mmc1.cpu_write(0x8000, 0x80);
+        load_register(&mut mmc1, 0xe000, 0b0_0010);
+
+        assert_eq!(mmc1.cpu_read(0x8000), 2);
+        assert_eq!(mmc1.cpu_read(0xc000), 7);
+    }
+
+    #[test]
+    fn test_prg_bank_modes() {
+        let mut mmc1 = Mmc1::new(banked_rom(1, 8, 0x4000, 2, 0x2000));
+        load_register(&mut mmc1, 0xe000, 3);
+
+        // 32KB mode ignores the low bit
+        load_register(&mut mmc1, 0x8000, 0b0_0000);
+        assert_eq!(mmc1.cpu_read(0x8000), 2);
+        assert_eq!(mmc1.cpu_read(0xc000), 3);
+
+        // First bank fixed at $8000
+        load_register(&mut mmc1, 0x8000, 0b0_1000);
+        assert_eq!(mmc1.cpu_read(0x8000), 0);
+        assert_eq!(mmc1.cpu_read(0xc000), 3);
+
+        // Last bank fixed at $C000
+        load_register(&mut mmc1, 0x8000, 0b0_1100);
+        assert_eq!(mmc1.cpu_read(0x8000), 3);
+        assert_eq!(mmc1.cpu_read(0xc000), 7);
+    }
+
+    #[test]
+    fn test_chr_bank_modes() {
+        let mut mmc1 = Mmc1::new(banked_rom(1, 2, 0x4000, 8, 0x1000));
+        load_register(&mut mmc1, 0xa000, 5);
+        load_register(&mut mmc1, 0xc000, 2);
+
+        // 8KB mode ignores the low bit and CHR bank 1
+        assert_eq!(mmc1.chr_read(0x0000), 4);
+        assert_eq!(mmc1.chr_read(0x1000), 5);
+
+        load_register(&mut mmc1, 0x8000, 0b1_1100);
+        assert_eq!(mmc1.chr_read(0x0000), 5);
+        assert_eq!(mmc1.chr_read(0x1000), 2);
+    }
+
+    #[test]
+    fn test_mirroring_control() {
+        let mut mmc1 = Mmc1::new(banked_rom(1, 2, 0x4000, 2, 0x2000));
+
+        load_register(&mut mmc1, 0x8000, 0b0_1100);
+        assert_eq!(mmc1.mirroring(), Mirroring::SingleScreenLower);
+        load_register(&mut mmc1, 0x8000, 0b0_1101);
+        assert_eq!(mmc1.mirroring(), Mirroring::SingleScreenUpper);
+        load_register(&mut mmc1, 0x8000, 0b0_1111);
+        assert_eq!(mmc1.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn test_prg_ram_enable() {
+        let mut mmc1 = Mmc1::new(banked_rom(1, 2, 0x4000, 2, 0x2000));
+        mmc1.cpu_write(0x6000, 0x42);
+        assert_eq!(mmc1.cpu_read(0x6000), 0x42);
+
+        load_register(&mut mmc1, 0xe000, 0b1_0000);
+        assert_eq!(mmc1.cpu_read(0x6000), 0);
+        mmc1.cpu_write(0x6000, 0x24);
+
+        load_register(&mut mmc1, 0xe000, 0b0_0000);
+        assert_eq!(mmc1.cpu_read(0x6000), 0x42);
+    }
+
+    #[test]
+    fn test_surom_outer_bank() {
+        let mut mmc1 = Mmc1::new(banked_rom(1, 32, 0x4000, 0, 0x2000));
+
+        assert_eq!(mmc1.cpu_read(0xc000), 15);
+
+        load_register(&mut mmc1, 0xa000, 0b1_0000);
+        assert_eq!(mmc1.cpu_read(0x8000), 16);
+        assert_eq!(mmc1.cpu_read(0xc000), 31);
+    }
+}
@@ -0,0 +1,375 @@
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::{self, Chr, Mapper, PRG_RAM, PRG_RAM_END, PRG_ROM, PRG_ROM_END};
+
+/// MMC3 (Mapper 4)
+/// ===============
+/// Eight bank registers are written indirectly: the even address picks
+/// which one the next write to the odd address lands in. The other
+/// register pairs control mirroring, PRG-RAM and the scanline counter.
+///
+/// Address     | Even                  | Odd
+/// $8000-$9FFF | Bank select           | Bank data
+/// $A000-$BFFF | Mirroring             | PRG-RAM protect
+/// $C000-$DFFF | IRQ latch             | IRQ reload
+/// $E000-$FFFF | IRQ disable and ack   | IRQ enable
+///
+/// Bank select
+/// 7  bit  0
+/// ---- ----
+/// CPxx xRRR
+/// ||    |||
+/// ||    +++- Bank register written by the next bank data write
+/// |+-------- PRG-ROM bank mode (0: R6 at $8000, 1: R6 at $C000)
+/// +--------- CHR A12 inversion (0: 2KB banks at $0000, 1: at $1000)
+///
+/// PRG-ROM Banks (8KB)     | CHR Banks         Inversion 0 | 1
+/// Mode        | 0  | 1    | $0000-$07FF  R0 (2KB)       | R2-R5
+/// $8000-$9FFF | R6 | -2   | $0800-$0FFF  R1 (2KB)       |
+/// $A000-$BFFF | R7 | R7   | $1000-$1FFF  R2-R5 (1KB)    | R0, R1
+/// $C000-$DFFF | -2 | R6   |
+/// $E000-$FFFF | -1 | -1   | -1 and -2 are the last and second last bank
+///
+/// Scanline Counter
+/// ================
+/// The counter is clocked by rising edges of PPU address line A12. With
+/// backgrounds fetched from $0000 and sprites from $1000 that happens once
+/// per scanline, at the first sprite pattern fetch. The sprite fetches
+/// toggle A12 every 8 dots so rises are only counted after the line has been
+/// low for a few CPU cycles, which folds them into a single clock.
+pub struct Mmc3 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Chr,
+    four_screen: bool,
+    bank_select: u8,
+    registers: [u8; 8],
+    horizontal_mirroring: bool,
+    prg_ram_enabled: bool,
+    prg_ram_write_protect: bool,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    a12_high: bool,
+    a12_low_since: u64,
+}
+
+/// Three CPU cycles worth of PPU dots
+const A12_FILTER_DOTS: u64 = 9;
+
+impl Mmc3 {
+    pub fn new(mut rom: Rom) -> Self {
+        Mmc3 {
+            prg_ram: mapper::prg_ram(&rom),
+            chr: Chr::new(&mut rom),
+            four_screen: rom.screen_mirroring == Mirroring::FourScreen,
+            horizontal_mirroring: rom.screen_mirroring == Mirroring::Horizontal,
+            prg_rom: rom.prg_rom,
+            bank_select: 0,
+            registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            prg_ram_enabled: true,
+            prg_ram_write_protect: false,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            a12_high: false,
+            a12_low_since: 0,
+        }
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> usize {
+        let banks = (self.prg_rom.len() / 0x2000).max(2);
+        let second_last = banks - 2;
+        let last = banks - 1;
+        let r6 = (self.registers[6] & 0b0011_1111) as usize;
+        let r7 = (self.registers[7] & 0b0011_1111) as usize;
+        let prg_mode = self.bank_select & 0b0100_0000 != 0;
+
+        let bank = match (address, prg_mode) {
+            (0x8000..=0x9FFF, false) => r6,
+            (0x8000..=0x9FFF, true) => second_last,
+            (0xA000..=0xBFFF, _) => r7,
+            (0xC000..=0xDFFF, false) => second_last,
+            (0xC000..=0xDFFF, true) => r6,
+            _ => last,
+        };
+
+        mapper::bank_offset(self.prg_rom.len(), bank, 0x2000, address)
+    }
+
+    /// Returns the bank and its size for a pattern table address
+    fn chr_bank(&self, address: u16) -> (usize, usize) {
+        let address = if self.bank_select & 0b1000_0000 != 0 {
+            address ^ 0x1000
+        } else {
+            address
+        };
+
+        match address & 0x1FFF {
+            0x0000..=0x07FF => ((self.registers[0] >> 1) as usize, 0x800),
+            0x0800..=0x0FFF => ((self.registers[1] >> 1) as usize, 0x800),
+            0x1000..=0x13FF => (self.registers[2] as usize, 0x400),
+            0x1400..=0x17FF => (self.registers[3] as usize, 0x400),
+            0x1800..=0x1BFF => (self.registers[4] as usize, 0x400),
+            _ => (self.registers[5] as usize, 0x400),
+        }
+    }
+
+    fn write_register(&mut self, address: u16, data: u8) {
+        let even = address & 1 == 0;
+        match (address, even) {
+            (0x8000..=0x9FFF, true) => self.bank_select = data,
+            (0x8000..=0x9FFF, false) => {
+                self.registers[(self.bank_select & 0b111) as usize] = data;
+            }
+            (0xA000..=0xBFFF, true) => self.horizontal_mirroring = data & 1 != 0,
+            (0xA000..=0xBFFF, false) => {
+                self.prg_ram_enabled = data & 0b1000_0000 != 0;
+                self.prg_ram_write_protect = data & 0b0100_0000 != 0;
+            }
+            (0xC000..=0xDFFF, true) => self.irq_latch = data,
+            (0xC000..=0xDFFF, false) => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            (_, true) => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            (_, false) => self.irq_enabled = true,
+        }
+    }
+
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            PRG_RAM..=PRG_RAM_END if self.prg_ram_enabled => {
+                mapper::read_prg_ram(&self.prg_ram, address)
+            }
+            PRG_ROM..=PRG_ROM_END => self.prg_rom[self.prg_rom_offset(address)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        match address {
+            PRG_RAM..=PRG_RAM_END if self.prg_ram_enabled && !self.prg_ram_write_protect => {
+                mapper::write_prg_ram(&mut self.prg_ram, address, data)
+            }
+            PRG_ROM..=PRG_ROM_END => self.write_register(address, data),
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        let (bank, bank_size) = self.chr_bank(address);
+        self.chr.read(bank, bank_size, address)
+    }
+
+    fn chr_write(&mut self, address: u16, data: u8) {
+        let (bank, bank_size) = self.chr_bank(address);
+        self.chr.write(bank, bank_size, address, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.four_screen {
+            Mirroring::FourScreen
+        } else if self.horizontal_mirroring {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        }
+    }
+
+    fn notify_ppu_address(&mut self, address: u16, dot: u64) {
+        let a12_high = address & 0x1000 != 0;
+        if a12_high && !self.a12_high && dot.saturating_sub(self.a12_low_since) >= A12_FILTER_DOTS {
+            self.clock_irq_counter();
+        }
+        if !a12_high && self.a12_high {
+            self.a12_low_since = dot;
+        }
+        self.a12_high = a12_high;
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::test::banked_rom;
+
+    /// Puts the addresses a scanline with backgrounds at $0000 and 8x8
+    /// sprites at $1000 would on the PPU bus, from the given dot
+    fn scanline(mmc3: &mut Mmc3, start: u64) {
+        for dot in (0..256).step_by(2) {
+            mmc3.notify_ppu_address(dot as u16 & 0x0ff0, start + dot);
+        }
+        for sprite in 0..8 {
+            let dot = start + 256 + sprite * 8;
+            mmc3.notify_ppu_address(0x2000, dot);
+            mmc3.notify_ppu_address(0x2000, dot + 2);
+            mmc3.notify_ppu_address(0x1000, dot + 4);
+            mmc3.notify_ppu_address(0x1008, dot + 6);
+        }
+        for dot in (320..340).step_by(2) {
+            mmc3.notify_ppu_address(0x0000, start + dot);
+        }
+    }
+
+    #[test]
+    fn test_prg_bank_modes() {
+        let mut mmc3 = Mmc3::new(banked_rom(4, 16, 0x2000, 8, 0x400));
+        mmc3.cpu_write(0x8000, 6);
+        mmc3.cpu_write(0x8001, 3);
+        mmc3.cpu_write(0x8000, 7);
+        mmc3.cpu_write(0x8001, 4);
+
+        assert_eq!(mmc3.cpu_read(0x8000), 3);
+        assert_eq!(mmc3.cpu_read(0xa000), 4);
+        assert_eq!(mmc3.cpu_read(0xc000), 14);
+        assert_eq!(mmc3.cpu_read(0xe000), 15);
+
+        mmc3.cpu_write(0x8000, 0b0100_0000);
+        assert_eq!(mmc3.cpu_read(0x8000), 14);
+        assert_eq!(mmc3.cpu_read(0xa000), 4);
+        assert_eq!(mmc3.cpu_read(0xc000), 3);
+        assert_eq!(mmc3.cpu_read(0xe000), 15);
+    }
+
+    #[test]
+    fn test_chr_banks_and_inversion() {
+        let mut mmc3 = Mmc3::new(banked_rom(4, 4, 0x2000, 16, 0x400));
+        for (register, bank) in [(0, 4), (1, 7), (2, 9), (3, 10), (4, 11), (5, 12)] {
+            mmc3.cpu_write(0x8000, register);
+            mmc3.cpu_write(0x8001, bank);
+        }
+
+        assert_eq!(mmc3.chr_read(0x0000), 4);
+        assert_eq!(mmc3.chr_read(0x0400), 5);
+        assert_eq!(mmc3.chr_read(0x0800), 6);
+        assert_eq!(mmc3.chr_read(0x0c00), 7);
+        assert_eq!(mmc3.chr_read(0x1000), 9);
+        assert_eq!(mmc3.chr_read(0x1c00), 12);
+
+        mmc3.cpu_write(0x8000, 0b1000_0000);
+        assert_eq!(mmc3.chr_read(0x0000), 9);
+        assert_eq!(mmc3.chr_read(0x0c00), 12);
+        assert_eq!(mmc3.chr_read(0x1000), 4);
+        assert_eq!(mmc3.chr_read(0x1c00), 7);
+    }
+
+    #[test]
+    fn test_mirroring() {
+        let mut mmc3 = Mmc3::new(banked_rom(4, 4, 0x2000, 8, 0x400));
+        mmc3.cpu_write(0xa000, 0);
+        assert_eq!(mmc3.mirroring(), Mirroring::Vertical);
+        mmc3.cpu_write(0xa000, 1);
+        assert_eq!(mmc3.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn test_prg_ram_protect() {
+        let mut mmc3 = Mmc3::new(banked_rom(4, 4, 0x2000, 8, 0x400));
+        mmc3.cpu_write(0xa001, 0b1000_0000);
+        mmc3.cpu_write(0x6000, 0x11);
+        assert_eq!(mmc3.cpu_read(0x6000), 0x11);
+
+        mmc3.cpu_write(0xa001, 0b1100_0000);
+        mmc3.cpu_write(0x6000, 0x22);
+        assert_eq!(mmc3.cpu_read(0x6000), 0x11);
+
+        mmc3.cpu_write(0xa001, 0b0000_0000);
+        assert_eq!(mmc3.cpu_read(0x6000), 0);
+        mmc3.cpu_write(0x6000, 0x33);
+
+        mmc3.cpu_write(0xa001, 0b1000_0000);
+        assert_eq!(mmc3.cpu_read(0x6000), 0x11);
+    }
+
+    #[test]
+    fn test_irq_after_latch_scanlines() {
+        let mut mmc3 = Mmc3::new(banked_rom(4, 4, 0x2000, 8, 0x400));
+        mmc3.cpu_write(0xc000, 3);
+        mmc3.cpu_write(0xc001, 0);
+        mmc3.cpu_write(0xe001, 0);
+
+        // The first clock reloads the counter from the latch
+        for line in 0..3 {
+            scanline(&mut mmc3, line * 341);
+            assert!(!mmc3.irq());
+        }
+        scanline(&mut mmc3, 3 * 341);
+        assert!(mmc3.irq());
+
+        // Stays asserted until acknowledged
+        scanline(&mut mmc3, 4 * 341);
+        assert!(mmc3.irq());
+        mmc3.cpu_write(0xe000, 0);
+        assert!(!mmc3.irq());
+
+        // Counter reloads and counts down again
+        mmc3.cpu_write(0xe001, 0);
+        for line in 5..7 {
+            scanline(&mut mmc3, line * 341);
+            assert!(!mmc3.irq());
+        }
+        scanline(&mut mmc3, 7 * 341);
+        assert!(mmc3.irq());
+    }
+
+    #[test]
+    fn test_irq_disabled() {
+        let mut mmc3 = Mmc3::new(banked_rom(4, 4, 0x2000, 8, 0x400));
+        mmc3.cpu_write(0xc000, 1);
+        mmc3.cpu_write(0xc001, 0);
+        mmc3.cpu_write(0xe000, 0);
+
+        for line in 0..4 {
+            scanline(&mut mmc3, line * 341);
+        }
+        assert!(!mmc3.irq());
+    }
+
+    #[test]
+    fn test_a12_filter_ignores_quick_toggles() {
+        let mut mmc3 = Mmc3::new(banked_rom(4, 4, 0x2000, 8, 0x400));
+        mmc3.cpu_write(0xc000, 0);
+        mmc3.cpu_write(0xc001, 0);
+        mmc3.cpu_write(0xe001, 0);
+
+        mmc3.notify_ppu_address(0x0000, 100);
+        mmc3.notify_ppu_address(0x1000, 200);
+        assert!(mmc3.irq());
+        mmc3.cpu_write(0xe000, 0);
+        mmc3.cpu_write(0xe001, 0);
+
+        mmc3.notify_ppu_address(0x0000, 202);
+        mmc3.notify_ppu_address(0x1000, 206);
+        assert!(!mmc3.irq());
+
+        mmc3.notify_ppu_address(0x0000, 208);
+        mmc3.notify_ppu_address(0x1000, 220);
+        assert!(mmc3.irq());
+    }
+}
@@ -0,0 +1,218 @@
+pub mod axrom;
+pub mod cnrom;
+pub mod mmc1;
+pub mod mmc3;
+pub mod nrom;
+pub mod uxrom;
+
+use crate::cartridge::{Mirroring, Rom, RomError};
+
+/// Cartridge Memory Map
+/// ====================
+/// The cartridge sits on both the CPU and the PPU bus. Which bytes of the
+/// ROM appear where is decided by the board's mapper, usually by writing
+/// to registers that overlay the PRG-ROM window.
+///
+///  CPU Bus                 PPU Bus
+///  _______________ $10000  _______________ $2000
+/// | PRG-ROM       |       | CHR           |
+/// |_______________| $8000 | Pattern       |
+/// | PRG-RAM       |       | Tables        |
+/// |_______________| $6000 |_______________| $0000
+/// | Expansion     |
+/// |_______________| $4020
+pub trait Mapper {
+    /// CPU read from $4020-$FFFF
+    fn cpu_read(&mut self, address: u16) -> u8;
+
+    /// CPU write to $4020-$FFFF, which is where bank registers live
+    fn cpu_write(&mut self, address: u16, data: u8);
+
+    /// PPU read from the pattern tables at $0000-$1FFF
+    fn chr_read(&mut self, address: u16) -> u8;
+
+    /// PPU write to the pattern tables, only boards with CHR-RAM keep it
+    fn chr_write(&mut self, address: u16, data: u8);
+
+    /// Nametable layout, which some boards can switch at runtime
+    fn mirroring(&self) -> Mirroring;
+
+    /// Called with every address the PPU puts on its bus and the PPU dot it
+    /// happened on, for boards that watch the PPU to count scanlines
+    fn notify_ppu_address(&mut self, _address: u16, _dot: u64) {}
+
+    /// Whether the board is holding the CPU's IRQ line asserted
+    fn irq(&self) -> bool {
+        false
+    }
+}
+
+/// Builds the board described by the ROM header
+pub fn from_rom(rom: Rom) -> Result<Box<dyn Mapper>, RomError> {
+    match rom.mapper {
+        0 => Ok(Box::new(nrom::Nrom::new(rom))),
+        1 => Ok(Box::new(mmc1::Mmc1::new(rom))),
+        2 => Ok(Box::new(uxrom::UxRom::new(rom))),
+        3 => Ok(Box::new(cnrom::CnRom::new(rom))),
+        4 => Ok(Box::new(mmc3::Mmc3::new(rom))),
+        7 => Ok(Box::new(axrom::AxRom::new(rom))),
+        mapper => Err(RomError::UnsupportedMapper(mapper)),
+    }
+}
+
+pub(crate) const PRG_RAM: u16 = 0x6000;
+pub(crate) const PRG_RAM_END: u16 = 0x7FFF;
+pub(crate) const PRG_ROM: u16 = 0x8000;
+pub(crate) const PRG_ROM_END: u16 = 0xFFFF;
+const TRAINER_ADDRESS: usize = 0x1000;
+
+/// Pattern table memory. Boards without CHR-ROM have CHR-RAM instead,
+/// which the PPU is free to write to.
+pub(crate) struct Chr {
+    data: Vec<u8>,
+    writable: bool,
+}
+
+impl Chr {
+    pub(crate) fn new(rom: &mut Rom) -> Self {
+        if rom.chr_rom.is_empty() {
+            let size = (rom.chr_ram_size + rom.chr_nvram_size).max(0x2000);
+            Chr {
+                data: vec![0; size],
+                writable: true,
+            }
+        } else {
+            Chr {
+                data: std::mem::take(&mut rom.chr_rom),
+                writable: false,
+            }
+        }
+    }
+
+    /// `bank` is counted in units of `bank_size` and wraps around the
+    /// memory actually present
+    pub(crate) fn read(&self, bank: usize, bank_size: usize, address: u16) -> u8 {
+        self.data[bank_offset(self.data.len(), bank, bank_size, address)]
+    }
+
+    pub(crate) fn write(&mut self, bank: usize, bank_size: usize, address: u16, data: u8) {
+        if self.writable {
+            let offset = bank_offset(self.data.len(), bank, bank_size, address);
+            self.data[offset] = data;
+        }
+    }
+}
+
+/// Work RAM at $6000-$7FFF, if the header asks for any. A trainer is
+/// copied to $7000 before the game starts.
+pub(crate) fn prg_ram(rom: &Rom) -> Vec<u8> {
+    let size = rom.prg_ram_size + rom.prg_nvram_size;
+    let mut prg_ram = vec![0; size];
+    if let Some(trainer) = &rom.trainer {
+        if prg_ram.len() < TRAINER_ADDRESS + trainer.len() {
+            prg_ram.resize(0x2000, 0);
+        }
+        prg_ram[TRAINER_ADDRESS..TRAINER_ADDRESS + trainer.len()].copy_from_slice(trainer);
+    }
+    prg_ram
+}
+
+/// Boards without PRG-RAM leave the data bus floating, read here as 0
+pub(crate) fn read_prg_ram(prg_ram: &[u8], address: u16) -> u8 {
+    if prg_ram.is_empty() {
+        return 0;
+    }
+    prg_ram[(address - PRG_RAM) as usize % prg_ram.len()]
+}
+
+pub(crate) fn write_prg_ram(prg_ram: &mut [u8], address: u16, data: u8) {
+    if !prg_ram.is_empty() {
+        let len = prg_ram.len();
+        prg_ram[(address - PRG_RAM) as usize % len] = data;
+    }
+}
+
+/// Offset of `address` into the bank numbered `bank`, taking the low bits
+/// of `address` as the position within the bank. Bank numbers past the end
+/// of the chip wrap, the same as unconnected high address lines.
+pub(crate) fn bank_offset(len: usize, bank: usize, bank_size: usize, address: u16) -> usize {
+    let banks = (len / bank_size).max(1);
+    (bank % banks) * bank_size + (address as usize & (bank_size - 1)) % len
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::cartridge::{RomFormat, TvSystem};
+
+    /// A ROM whose every byte holds the number of the bank it sits in, so
+    /// reads show which bank is switched in
+    pub fn banked_rom(
+        mapper: u16,
+        prg_banks: usize,
+        prg_bank_size: usize,
+        chr_banks: usize,
+        chr_bank_size: usize,
+    ) -> Rom {
+        let mut prg_rom = Vec::new();
+        for bank in 0..prg_banks {
+            prg_rom.extend(vec![bank as u8; prg_bank_size]);
+        }
+        let mut chr_rom = Vec::new();
+        for bank in 0..chr_banks {
+            chr_rom.extend(vec![bank as u8; chr_bank_size]);
+        }
+
+        Rom {
+            format: RomFormat::INes,
+            prg_rom,
+            chr_rom,
+            trainer: None,
+            mapper,
+            submapper: 0,
+            screen_mirroring: Mirroring::Horizontal,
+            battery: false,
+            prg_ram_size: 0x2000,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            tv_system: TvSystem::Ntsc,
+        }
+    }
+
+    #[test]
+    fn test_unsupported_mapper() {
+        let rom = banked_rom(255, 2, 0x4000, 1, 0x2000);
+
+        assert_eq!(from_rom(rom).err(), Some(RomError::UnsupportedMapper(255)));
+    }
+
+    #[test]
+    fn test_chr_ram_is_writable() {
+        let mut rom = banked_rom(0, 2, 0x4000, 0, 0x2000);
+        let mut chr = Chr::new(&mut rom);
+        chr.write(0, 0x2000, 0x1234, 0x56);
+
+        assert_eq!(chr.read(0, 0x2000, 0x1234), 0x56);
+    }
+
+    #[test]
+    fn test_chr_rom_is_read_only() {
+        let mut rom = banked_rom(0, 2, 0x4000, 1, 0x2000);
+        let mut chr = Chr::new(&mut rom);
+        chr.write(0, 0x2000, 0x1234, 0x56);
+
+        assert_eq!(chr.read(0, 0x2000, 0x1234), 0x00);
+    }
+
+    #[test]
+    fn test_trainer_is_copied_to_7000() {
+        let mut rom = banked_rom(0, 2, 0x4000, 1, 0x2000);
+        rom.trainer = Some(vec![0xaa; 512]);
+        let prg_ram = prg_ram(&rom);
+
+        assert_eq!(prg_ram[0x0fff], 0x00);
+        assert_eq!(prg_ram[0x1000], 0xaa);
+        assert_eq!(prg_ram[0x11ff], 0xaa);
+    }
+}
@@ -0,0 +1,89 @@
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::{self, Chr, Mapper, PRG_RAM, PRG_RAM_END, PRG_ROM, PRG_ROM_END};
+
+/// NROM (Mapper 0)
+/// ===============
+/// No bank switching at all. 16KB of PRG-ROM is mirrored into both
+/// $8000 and $C000, 32KB fills the whole window. Family Basic boards also
+/// carry PRG-RAM at $6000.
+pub struct Nrom {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Chr,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(mut rom: Rom) -> Self {
+        Nrom {
+            prg_ram: mapper::prg_ram(&rom),
+            chr: Chr::new(&mut rom),
+            mirroring: rom.screen_mirroring,
+            prg_rom: rom.prg_rom,
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            PRG_RAM..=PRG_RAM_END => mapper::read_prg_ram(&self.prg_ram, address),
+            PRG_ROM..=PRG_ROM_END => {
+                let offset = mapper::bank_offset(self.prg_rom.len(), 0, 0x8000, address);
+                self.prg_rom[offset]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        if let PRG_RAM..=PRG_RAM_END = address {
+            mapper::write_prg_ram(&mut self.prg_ram, address, data);
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        self.chr.read(0, 0x2000, address)
+    }
+
+    fn chr_write(&mut self, address: u16, data: u8) {
+        self.chr.write(0, 0x2000, address, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::test::banked_rom;
+
+    #[test]
+    fn test_16kb_is_mirrored() {
+        let mut nrom = Nrom::new(banked_rom(0, 1, 0x4000, 1, 0x2000));
+        nrom.prg_rom[0x0123] = 0x45;
+
+        assert_eq!(nrom.cpu_read(0x8123), 0x45);
+        assert_eq!(nrom.cpu_read(0xc123), 0x45);
+    }
+
+    #[test]
+    fn test_32kb_fills_window() {
+        let mut nrom = Nrom::new(banked_rom(0, 2, 0x4000, 1, 0x2000));
+
+        assert_eq!(nrom.cpu_read(0x8000), 0);
+        assert_eq!(nrom.cpu_read(0xffff), 1);
+    }
+
+    #[test]
+    fn test_rom_is_read_only_and_ram_is_not() {
+        let mut nrom = Nrom::new(banked_rom(0, 2, 0x4000, 1, 0x2000));
+        nrom.cpu_write(0x8000, 0x55);
+        nrom.cpu_write(0x6000, 0x66);
+
+        assert_eq!(nrom.cpu_read(0x8000), 0);
+        assert_eq!(nrom.cpu_read(0x6000), 0x66);
+    }
+}
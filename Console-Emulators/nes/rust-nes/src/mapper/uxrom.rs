@@ -0,0 +1,111 @@
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::{self, Chr, Mapper, PRG_RAM, PRG_RAM_END, PRG_ROM, PRG_ROM_END};
+
+/// UxROM (Mapper 2)
+/// ================
+/// Any write to $8000-$FFFF selects the 16KB bank at $8000. The last bank
+/// is fixed at $C000 so the reset vector and bank switching code are always
+/// reachable. CHR is a single 8KB bank, nearly always RAM.
+///
+/// Register ($8000-$FFFF)
+/// 7  bit  0
+/// ---- ----
+/// xxxx PPPP
+///      ||||
+///      ++++- Select 16KB PRG-ROM bank for $8000-$BFFF
+pub struct UxRom {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Chr,
+    mirroring: Mirroring,
+    prg_bank: usize,
+}
+
+impl UxRom {
+    pub fn new(mut rom: Rom) -> Self {
+        UxRom {
+            prg_ram: mapper::prg_ram(&rom),
+            chr: Chr::new(&mut rom),
+            mirroring: rom.screen_mirroring,
+            prg_rom: rom.prg_rom,
+            prg_bank: 0,
+        }
+    }
+
+    fn last_bank(&self) -> usize {
+        (self.prg_rom.len() / 0x4000).saturating_sub(1)
+    }
+}
+
+impl Mapper for UxRom {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            PRG_RAM..=PRG_RAM_END => mapper::read_prg_ram(&self.prg_ram, address),
+            PRG_ROM..=0xBFFF => {
+                let offset =
+                    mapper::bank_offset(self.prg_rom.len(), self.prg_bank, 0x4000, address);
+                self.prg_rom[offset]
+            }
+            0xC000..=PRG_ROM_END => {
+                let offset =
+                    mapper::bank_offset(self.prg_rom.len(), self.last_bank(), 0x4000, address);
+                self.prg_rom[offset]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        match address {
+            PRG_RAM..=PRG_RAM_END => mapper::write_prg_ram(&mut self.prg_ram, address, data),
+            PRG_ROM..=PRG_ROM_END => self.prg_bank = data as usize & 0b1111,
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        self.chr.read(0, 0x2000, address)
+    }
+
+    fn chr_write(&mut self, address: u16, data: u8) {
+        self.chr.write(0, 0x2000, address, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::test::banked_rom;
+
+    #[test]
+    fn test_last_bank_is_fixed() {
+        let mut uxrom = UxRom::new(banked_rom(2, 8, 0x4000, 0, 0x2000));
+
+        assert_eq!(uxrom.cpu_read(0x8000), 0);
+        assert_eq!(uxrom.cpu_read(0xc000), 7);
+
+        uxrom.cpu_write(0x8000, 3);
+        assert_eq!(uxrom.cpu_read(0xbfff), 3);
+        assert_eq!(uxrom.cpu_read(0xffff), 7);
+    }
+
+    #[test]
+    fn test_bank_number_wraps() {
+        let mut uxrom = UxRom::new(banked_rom(2, 4, 0x4000, 0, 0x2000));
+        uxrom.cpu_write(0xffff, 6);
+
+        assert_eq!(uxrom.cpu_read(0x8000), 2);
+    }
+
+    #[test]
+    fn test_chr_ram() {
+        let mut uxrom = UxRom::new(banked_rom(2, 4, 0x4000, 0, 0x2000));
+        uxrom.chr_write(0x1fff, 0x12);
+
+        assert_eq!(uxrom.chr_read(0x1fff), 0x12);
+    }
+}
@@ -0,0 +1,690 @@
+pub mod frame;
+pub mod palette;
+pub mod registers;
+mod render;
+mod scroll;
+mod sprites;
+
+use crate::cartridge::Mirroring;
+use crate::mapper::Mapper;
+use frame::Frame;
+use registers::{ControlRegister, MaskRegister, StatusRegister};
+
+/// PPU Memory Map
+/// ==============
+///  _______________ $4000  _______________
+/// | Mirrors       |       |               |
+/// | $3F00-$3F1F   |       |               |
+/// |_ _ _ _ _ _ _ _| $3F20 | Palettes      |
+/// | Sprite Palette|       |               |
+/// |_ _ _ _ _ _ _ _| $3F10 |               |
+/// | Image Palette |       |               |
+/// |_______________| $3F00 |_______________|
+/// | Mirrors       |       |               |
+/// | $2000-$2EFF   |       |               |
+/// |_ _ _ _ _ _ _ _| $3000 |               |
+/// | Nametable 3   |       |               |
+/// |_ _ _ _ _ _ _ _| $2C00 |               |
+/// | Nametable 2   |       | Nametables    |
+/// |_ _ _ _ _ _ _ _| $2800 |               |
+/// | Nametable 1   |       |               |
+/// |_ _ _ _ _ _ _ _| $2400 |               |
+/// | Nametable 0   |       |               |
+/// |_______________| $2000 |_______________|
+/// | Pattern       |       |               |
+/// | Table 1       |       |               |
+/// |_ _ _ _ _ _ _ _| $1000 | Pattern Tables|
+/// | Pattern       |       | (Cartridge)   |
+/// | Table 0       |       |               |
+/// |_______________| $0000 |_______________|
+///
+/// The console only has 2KB of VRAM, enough for two nametables. The
+/// cartridge wires up the address lines to decide which two of the four
+/// nametables share memory.
+const PATTERN_TABLES_END: u16 = 0x1FFF;
+const NAMETABLES: u16 = 0x2000;
+const NAMETABLES_MIRRORS_END: u16 = 0x3EFF;
+const PALETTES: u16 = 0x3F00;
+const PALETTES_MIRRORS_END: u16 = 0x3FFF;
+
+/// Frame Timing (NTSC)
+/// ===================
+/// Scanline | Dots
+/// 0-239    | Visible picture, 256 pixels plus fetches for the next line
+/// 240      | Post-render, idle
+/// 241-260  | Vertical blank, the flag and NMI are raised on dot 1 of 241
+/// 261      | Pre-render, flags cleared on dot 1, fetches for line 0
+///
+/// Every scanline is 341 dots long, except the pre-render line of odd
+/// frames which skips its last dot while rendering is enabled.
+const DOTS_PER_SCANLINE: u16 = 341;
+const VISIBLE_SCANLINES: u16 = 240;
+const VBLANK_SCANLINE: u16 = 241;
+const PRE_RENDER_SCANLINE: u16 = 261;
+
+pub struct Ppu {
+    pub ctrl: ControlRegister,
+    pub mask: MaskRegister,
+    pub status: StatusRegister,
+    pub oam_addr: u8,
+    pub oam_data: [u8; 256],
+    pub palette_table: [u8; 32],
+    /// 2KB on the console, the upper half is the extra RAM that four
+    /// screen cartridges carry
+    pub vram: [u8; 4096],
+    pub frame: Frame,
+    /// Scanline and dot the PPU will process next
+    pub scanline: u16,
+    pub dot: u16,
+    /// Frames completed, counted at the start of each vertical blank
+    pub frame_count: u64,
+    /// Total dots since power on
+    pub cycles: u64,
+    v: u16,
+    t: u16,
+    fine_x: u8,
+    w: bool,
+    background: render::BackgroundPipeline,
+    sprites: sprites::SpritePipeline,
+    internal_data_buf: u8,
+    io_latch: u8,
+    odd_frame: bool,
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Ppu {
+            ctrl: ControlRegister::empty(),
+            mask: MaskRegister::empty(),
+            status: StatusRegister::empty(),
+            oam_addr: 0,
+            oam_data: [0; 256],
+            palette_table: [0; 32],
+            vram: [0; 4096],
+            frame: Frame::new(),
+            scanline: 0,
+            dot: 0,
+            frame_count: 0,
+            cycles: 0,
+            v: 0,
+            t: 0,
+            fine_x: 0,
+            w: false,
+            background: render::BackgroundPipeline::default(),
+            sprites: sprites::SpritePipeline::default(),
+            internal_data_buf: 0,
+            io_latch: 0,
+            odd_frame: false,
+        }
+    }
+
+    /// The reset button clears PPUCTRL, PPUMASK, the scroll, the write
+    /// toggle and the read buffer. Memory, OAM and the status register are
+    /// left alone.
+    pub fn reset(&mut self) {
+        self.ctrl = ControlRegister::empty();
+        self.mask = MaskRegister::empty();
+        self.t = 0;
+        self.fine_x = 0;
+        self.w = false;
+        self.internal_data_buf = 0;
+        self.odd_frame = false;
+    }
+
+    /// Level of the PPU's NMI output. The CPU only reacts to it going high,
+    /// so enabling NMIs part way through vblank raises another one.
+    pub fn nmi_line(&self) -> bool {
+        self.ctrl.generate_vblank_nmi() && self.status.contains(StatusRegister::VBLANK_STARTED)
+    }
+
+    // Registers
+
+    /// CPU read from $2000-$3FFF, mirrored every 8 bytes
+    pub fn read_register(&mut self, mapper: &mut dyn Mapper, address: u16) -> u8 {
+        let data = match address & 0b111 {
+            2 => self.read_status(),
+            4 => self.read_oam_data(),
+            7 => self.read_data(mapper),
+            // Write only registers return whatever is left on the data bus
+            _ => return self.io_latch,
+        };
+        self.io_latch = data;
+        data
+    }
+
+    /// CPU write to $2000-$3FFF, mirrored every 8 bytes
+    pub fn write_register(&mut self, mapper: &mut dyn Mapper, address: u16, data: u8) {
+        self.io_latch = data;
+        match address & 0b111 {
+            0 => self.write_to_ctrl(data),
+            1 => self.mask = MaskRegister::from_bits_retain(data),
+            2 => {}
+            3 => self.oam_addr = data,
+            4 => self.write_to_oam_data(data),
+            5 => self.write_to_scroll(data),
+            6 => self.write_to_ppu_addr(mapper, data),
+            _ => self.write_to_data(mapper, data),
+        }
+    }
+
+    /// The nametable select bits also land in t, so they are part of the
+    /// scroll position
+    fn write_to_ctrl(&mut self, data: u8) {
+        self.ctrl = ControlRegister::from_bits_retain(data);
+        self.t = (self.t & !0x0C00) | ((data as u16 & 0b11) << 10);
+    }
+
+    /// Reading PPUSTATUS acknowledges vblank and resets the write toggle
+    /// shared by PPUSCROLL and PPUADDR
+    fn read_status(&mut self) -> u8 {
+        let data = self.status.bits() | (self.io_latch & 0b0001_1111);
+        self.status.remove(StatusRegister::VBLANK_STARTED);
+        self.w = false;
+        data
+    }
+
+    fn read_oam_data(&self) -> u8 {
+        self.oam_data[self.oam_addr as usize]
+    }
+
+    fn write_to_oam_data(&mut self, data: u8) {
+        // Bits 2-4 of the attribute byte are not implemented in OAM
+        let data = if self.oam_addr & 0b11 == 2 {
+            data & 0b1110_0011
+        } else {
+            data
+        };
+        self.oam_data[self.oam_addr as usize] = data;
+        self.oam_addr = self.oam_addr.wrapping_add(1);
+    }
+
+    /// First write is the X scroll, second write the Y scroll
+    ///
+    /// First:  t: ....... ...ABCDE <- d: ABCDE...
+    ///         x:              FGH <- d: .....FGH
+    /// Second: t: FGH..AB CDE..... <- d: ABCDEFGH
+    fn write_to_scroll(&mut self, data: u8) {
+        let data = data as u16;
+        if !self.w {
+            self.t = (self.t & !0b0000_0000_0001_1111) | (data >> 3);
+            self.fine_x = (data & 0b111) as u8;
+        } else {
+            self.t =
+                (self.t & !0b0111_0011_1110_0000) | ((data & 0b111) << 12) | ((data >> 3) << 5);
+        }
+        self.w = !self.w;
+    }
+
+    /// First write is the high byte, second write the low byte
+    ///
+    /// First:  t: .CDEFGH ........ <- d: ..CDEFGH
+    ///         t: Z...... ........ <- 0
+    /// Second: t: ....... ABCDEFGH <- d: ABCDEFGH
+    ///         v: <...all bits...> <- t: <...all bits...>
+    fn write_to_ppu_addr(&mut self, mapper: &mut dyn Mapper, data: u8) {
+        if !self.w {
+            self.t = (self.t & 0x00FF) | ((data as u16 & 0x3F) << 8);
+        } else {
+            self.t = (self.t & 0xFF00) | data as u16;
+            self.v = self.t;
+            mapper.notify_ppu_address(self.v & 0x3FFF, self.cycles);
+        }
+        self.w = !self.w;
+    }
+
+    /// Outside of rendering PPUDATA accesses step v by 1 or 32. While the
+    /// PPU is rendering they instead bump coarse X and Y together, the same
+    /// as the fetch logic does.
+    fn increment_vram_addr(&mut self) {
+        if self.is_rendering() {
+            self.increment_coarse_x();
+            self.increment_y();
+        } else {
+            self.v = self.v.wrapping_add(self.ctrl.vram_addr_increment()) & 0x7FFF;
+        }
+    }
+
+    /// Reads below the palettes are delayed by one read through an internal
+    /// buffer. Palette reads return immediately, but still refill the buffer
+    /// with the nametable byte hidden underneath.
+    fn read_data(&mut self, mapper: &mut dyn Mapper) -> u8 {
+        let addr = self.v & 0x3FFF;
+        self.increment_vram_addr();
+
+        if addr >= PALETTES {
+            self.internal_data_buf = self.read_vram(mapper, addr - 0x1000);
+            self.read_vram(mapper, addr)
+        } else {
+            let result = self.internal_data_buf;
+            self.internal_data_buf = self.read_vram(mapper, addr);
+            result
+        }
+    }
+
+    fn write_to_data(&mut self, mapper: &mut dyn Mapper, data: u8) {
+        let addr = self.v & 0x3FFF;
+        self.increment_vram_addr();
+        self.write_vram(mapper, addr, data);
+    }
+
+    // Memory
+
+    /// Index into `vram` for a nametable address, following the mirroring
+    /// the cartridge sets up
+    ///
+    /// Horizontal:        Vertical:
+    ///   [ A ] [ a ]        [ A ] [ B ]
+    ///   [ B ] [ b ]        [ a ] [ b ]
+    fn mirror_vram_addr(mirroring: Mirroring, addr: u16) -> usize {
+        let vram_index = (addr - NAMETABLES) as usize & 0x0FFF;
+        let name_table = vram_index / 0x400;
+        let offset = vram_index & 0x3FF;
+        let page = match mirroring {
+            Mirroring::Vertical => name_table & 1,
+            Mirroring::Horizontal => name_table >> 1,
+            Mirroring::SingleScreenLower => 0,
+            Mirroring::SingleScreenUpper => 1,
+            Mirroring::FourScreen => name_table,
+        };
+        page * 0x400 + offset
+    }
+
+    /// $3F10, $3F14, $3F18 and $3F1C are the same bytes as $3F00, $3F04,
+    /// $3F08 and $3F0C, which is how sprites share the backdrop colour
+    fn mirror_palette_addr(addr: u16) -> usize {
+        let index = (addr & 0x1F) as usize;
+        match index {
+            0x10 | 0x14 | 0x18 | 0x1C => index - 0x10,
+            _ => index,
+        }
+    }
+
+    fn read_palette(&self, index: usize) -> u8 {
+        let color = self.palette_table[Ppu::mirror_palette_addr(index as u16)];
+        if self.mask.contains(MaskRegister::GREYSCALE) {
+            color & 0x30
+        } else {
+            color
+        }
+    }
+
+    fn read_vram(&mut self, mapper: &mut dyn Mapper, addr: u16) -> u8 {
+        match addr {
+            0..=PATTERN_TABLES_END => mapper.chr_read(addr),
+            NAMETABLES..=NAMETABLES_MIRRORS_END => {
+                self.vram[Ppu::mirror_vram_addr(mapper.mirroring(), addr)]
+            }
+            PALETTES..=PALETTES_MIRRORS_END => self.read_palette(addr as usize),
+            _ => unreachable!("unexpected access to mirrored space {:#06x}", addr),
+        }
+    }
+
+    fn write_vram(&mut self, mapper: &mut dyn Mapper, addr: u16, data: u8) {
+        match addr {
+            0..=PATTERN_TABLES_END => mapper.chr_write(addr, data),
+            NAMETABLES..=NAMETABLES_MIRRORS_END => {
+                self.vram[Ppu::mirror_vram_addr(mapper.mirroring(), addr)] = data;
+            }
+            PALETTES..=PALETTES_MIRRORS_END => {
+                self.palette_table[Ppu::mirror_palette_addr(addr)] = data & 0x3F;
+            }
+            _ => unreachable!("unexpected access to mirrored space {:#06x}", addr),
+        }
+    }
+
+    // Timing
+
+    /// True on the lines where the PPU is fetching with rendering enabled
+    fn is_rendering(&self) -> bool {
+        self.mask.rendering_enabled()
+            && (self.scanline < VISIBLE_SCANLINES || self.scanline == PRE_RENDER_SCANLINE)
+    }
+
+    /// Advances the PPU by one dot
+    pub fn tick(&mut self, mapper: &mut dyn Mapper) {
+        let rendering = self.mask.rendering_enabled();
+
+        if self.is_rendering() {
+            self.step_background(mapper);
+            self.step_sprites(mapper);
+        }
+
+        if self.scanline < VISIBLE_SCANLINES && (1..=256).contains(&self.dot) {
+            self.render_pixel();
+        }
+
+        match (self.scanline, self.dot) {
+            (VBLANK_SCANLINE, 1) => {
+                self.status.insert(StatusRegister::VBLANK_STARTED);
+                self.frame_count += 1;
+            }
+            (PRE_RENDER_SCANLINE, 1) => {
+                self.status.remove(
+                    StatusRegister::VBLANK_STARTED
+                        | StatusRegister::SPRITE_ZERO_HIT
+                        | StatusRegister::SPRITE_OVERFLOW,
+                );
+            }
+            _ => {}
+        }
+
+        self.cycles += 1;
+        self.dot += 1;
+
+        if self.scanline == PRE_RENDER_SCANLINE
+            && self.dot == DOTS_PER_SCANLINE - 1
+            && self.odd_frame
+            && rendering
+        {
+            self.dot = DOTS_PER_SCANLINE;
+        }
+
+        if self.dot == DOTS_PER_SCANLINE {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline > PRE_RENDER_SCANLINE {
+                self.scanline = 0;
+                self.odd_frame = !self.odd_frame;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::mapper::nrom::Nrom;
+    use crate::mapper::test::banked_rom;
+
+    /// An NROM board with CHR-RAM so tests can write their own tiles
+    pub fn test_mapper(mirroring: Mirroring) -> Nrom {
+        let mut rom = banked_rom(0, 2, 0x4000, 0, 0x2000);
+        rom.screen_mirroring = mirroring;
+        Nrom::new(rom)
+    }
+
+    pub fn set_ppu_addr(ppu: &mut Ppu, mapper: &mut dyn Mapper, addr: u16) {
+        ppu.write_register(mapper, 0x2006, (addr >> 8) as u8);
+        ppu.write_register(mapper, 0x2006, (addr & 0xff) as u8);
+    }
+
+    pub fn write_bytes(ppu: &mut Ppu, mapper: &mut dyn Mapper, addr: u16, data: &[u8]) {
+        set_ppu_addr(ppu, mapper, addr);
+        for byte in data {
+            ppu.write_register(mapper, 0x2007, *byte);
+        }
+    }
+
+    /// Writes `tiles` to the pattern table from tile 1 onwards, every row
+    /// of a tile repeats the same pair of bit plane bytes. Also sets up the
+    /// first background and sprite palettes and moves every sprite off
+    /// screen.
+    pub fn load_tiles(ppu: &mut Ppu, mapper: &mut dyn Mapper, tiles: &[(u8, u8)]) {
+        for (index, &(low, high)) in tiles.iter().enumerate() {
+            let addr = 0x0010 * (index as u16 + 1);
+            write_bytes(ppu, mapper, addr, &[low; 8]);
+            write_bytes(ppu, mapper, addr + 8, &[high; 8]);
+        }
+        write_bytes(ppu, mapper, 0x3f00, &[0x0f, 0x01, 0x02, 0x03]);
+        write_bytes(ppu, mapper, 0x3f11, &[0x11, 0x12, 0x13]);
+        ppu.oam_data = [0xff; 256];
+    }
+
+    pub fn tick_until(ppu: &mut Ppu, mapper: &mut dyn Mapper, scanline: u16, dot: u16) {
+        loop {
+            ppu.tick(mapper);
+            if (ppu.scanline, ppu.dot) == (scanline, dot) {
+                break;
+            }
+        }
+    }
+
+    /// Runs through a pre-render line, so scroll is loaded from t, and
+    /// then every visible line
+    pub fn render_frame(ppu: &mut Ppu, mapper: &mut dyn Mapper) {
+        tick_until(ppu, mapper, 261, 0);
+        tick_until(ppu, mapper, 240, 0);
+    }
+
+    pub fn rgb(color: u8) -> (u8, u8, u8) {
+        palette::SYSTEM_PALETTE[color as usize]
+    }
+
+    #[test]
+    fn test_ppu_vram_writes() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        write_bytes(&mut ppu, &mut mapper, 0x2305, &[0x66]);
+
+        assert_eq!(ppu.vram[0x0305], 0x66);
+    }
+
+    #[test]
+    fn test_ppu_vram_reads_are_buffered() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        ppu.vram[0x0305] = 0x66;
+        ppu.vram[0x0306] = 0x77;
+        set_ppu_addr(&mut ppu, &mut mapper, 0x2305);
+
+        ppu.read_register(&mut mapper, 0x2007);
+        assert_eq!(ppu.read_register(&mut mapper, 0x2007), 0x66);
+        assert_eq!(ppu.read_register(&mut mapper, 0x2007), 0x77);
+    }
+
+    #[test]
+    fn test_ppu_vram_reads_step_32() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        ppu.write_register(&mut mapper, 0x2000, 0b100);
+        ppu.vram[0x01ff] = 0x66;
+        ppu.vram[0x01ff + 32] = 0x77;
+        ppu.vram[0x01ff + 64] = 0x88;
+        set_ppu_addr(&mut ppu, &mut mapper, 0x21ff);
+
+        ppu.read_register(&mut mapper, 0x2007);
+        assert_eq!(ppu.read_register(&mut mapper, 0x2007), 0x66);
+        assert_eq!(ppu.read_register(&mut mapper, 0x2007), 0x77);
+        assert_eq!(ppu.read_register(&mut mapper, 0x2007), 0x88);
+    }
+
+    // Horizontal: https://wiki.nesdev.com/w/index.php/Mirroring
+    //   [0x2000 A ] [0x2400 a ]
+    //   [0x2800 B ] [0x2C00 b ]
+    #[test]
+    fn test_vram_horizontal_mirror() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        write_bytes(&mut ppu, &mut mapper, 0x2405, &[0x66]);
+        write_bytes(&mut ppu, &mut mapper, 0x2805, &[0x77]);
+
+        set_ppu_addr(&mut ppu, &mut mapper, 0x2005);
+        ppu.read_register(&mut mapper, 0x2007);
+        assert_eq!(ppu.read_register(&mut mapper, 0x2007), 0x66);
+
+        set_ppu_addr(&mut ppu, &mut mapper, 0x2c05);
+        ppu.read_register(&mut mapper, 0x2007);
+        assert_eq!(ppu.read_register(&mut mapper, 0x2007), 0x77);
+    }
+
+    // Vertical: https://wiki.nesdev.com/w/index.php/Mirroring
+    //   [0x2000 A ] [0x2400 B ]
+    //   [0x2800 a ] [0x2C00 b ]
+    #[test]
+    fn test_vram_vertical_mirror() {
+        let mut mapper = test_mapper(Mirroring::Vertical);
+        let mut ppu = Ppu::new();
+        write_bytes(&mut ppu, &mut mapper, 0x2005, &[0x66]);
+        write_bytes(&mut ppu, &mut mapper, 0x2c05, &[0x77]);
+
+        set_ppu_addr(&mut ppu, &mut mapper, 0x2805);
+        ppu.read_register(&mut mapper, 0x2007);
+        assert_eq!(ppu.read_register(&mut mapper, 0x2007), 0x66);
+
+        set_ppu_addr(&mut ppu, &mut mapper, 0x2405);
+        ppu.read_register(&mut mapper, 0x2007);
+        assert_eq!(ppu.read_register(&mut mapper, 0x2007), 0x77);
+    }
+
+    #[test]
+    fn test_vram_mirrors_above_3000() {
+        let mut mapper = test_mapper(Mirroring::Vertical);
+        let mut ppu = Ppu::new();
+        write_bytes(&mut ppu, &mut mapper, 0x3405, &[0x66]);
+
+        assert_eq!(ppu.vram[0x0405], 0x66);
+    }
+
+    #[test]
+    fn test_palette_reads_are_not_buffered() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        write_bytes(&mut ppu, &mut mapper, 0x2f00, &[0x12]);
+        write_bytes(&mut ppu, &mut mapper, 0x3f00, &[0x21]);
+
+        set_ppu_addr(&mut ppu, &mut mapper, 0x3f00);
+        assert_eq!(ppu.read_register(&mut mapper, 0x2007), 0x21);
+
+        // The buffer now holds the nametable byte under the palette
+        set_ppu_addr(&mut ppu, &mut mapper, 0x2000);
+        assert_eq!(ppu.read_register(&mut mapper, 0x2007), 0x12);
+    }
+
+    #[test]
+    fn test_palette_mirrors() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        write_bytes(&mut ppu, &mut mapper, 0x3f10, &[0x0f]);
+        write_bytes(&mut ppu, &mut mapper, 0x3f25, &[0x2a]);
+
+        assert_eq!(ppu.palette_table[0x00], 0x0f);
+        assert_eq!(ppu.palette_table[0x05], 0x2a);
+
+        set_ppu_addr(&mut ppu, &mut mapper, 0x3ff0);
+        assert_eq!(ppu.read_register(&mut mapper, 0x2007), 0x0f);
+    }
+
+    #[test]
+    fn test_read_status_resets_latch() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        ppu.vram[0x0305] = 0x66;
+
+        ppu.write_register(&mut mapper, 0x2006, 0x21);
+        ppu.write_register(&mut mapper, 0x2006, 0x23);
+        ppu.write_register(&mut mapper, 0x2006, 0x05);
+
+        ppu.read_register(&mut mapper, 0x2007);
+        assert_ne!(ppu.read_register(&mut mapper, 0x2007), 0x66);
+
+        ppu.read_register(&mut mapper, 0x2002);
+
+        ppu.write_register(&mut mapper, 0x2006, 0x23);
+        ppu.write_register(&mut mapper, 0x2006, 0x05);
+
+        ppu.read_register(&mut mapper, 0x2007);
+        assert_eq!(ppu.read_register(&mut mapper, 0x2007), 0x66);
+    }
+
+    #[test]
+    fn test_read_status_resets_vblank() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        ppu.status.insert(StatusRegister::VBLANK_STARTED);
+
+        let status = ppu.read_register(&mut mapper, 0x2002);
+
+        assert_eq!(status >> 7, 1);
+        assert_eq!(ppu.status.bits() >> 7, 0);
+    }
+
+    #[test]
+    fn test_status_low_bits_are_open_bus() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        ppu.write_register(&mut mapper, 0x2000, 0x1f);
+
+        assert_eq!(ppu.read_register(&mut mapper, 0x2002), 0x1f);
+        assert_eq!(ppu.read_register(&mut mapper, 0x2005), 0x1f);
+    }
+
+    #[test]
+    fn test_oam_read_write() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        ppu.write_register(&mut mapper, 0x2003, 0x10);
+        ppu.write_register(&mut mapper, 0x2004, 0x66);
+        ppu.write_register(&mut mapper, 0x2004, 0x77);
+        ppu.write_register(&mut mapper, 0x2004, 0xff);
+
+        ppu.write_register(&mut mapper, 0x2003, 0x10);
+        assert_eq!(ppu.read_register(&mut mapper, 0x2004), 0x66);
+
+        ppu.write_register(&mut mapper, 0x2003, 0x11);
+        assert_eq!(ppu.read_register(&mut mapper, 0x2004), 0x77);
+
+        // Attribute bytes have no bits 2-4
+        ppu.write_register(&mut mapper, 0x2003, 0x12);
+        assert_eq!(ppu.read_register(&mut mapper, 0x2004), 0xe3);
+    }
+
+    #[test]
+    fn test_vblank_and_nmi_timing() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        ppu.write_register(&mut mapper, 0x2000, 0x80);
+
+        let dots_to_vblank = VBLANK_SCANLINE as u64 * DOTS_PER_SCANLINE as u64 + 1;
+        for _ in 0..dots_to_vblank {
+            ppu.tick(&mut mapper);
+        }
+        assert!(!ppu.nmi_line());
+
+        ppu.tick(&mut mapper);
+        assert!(ppu.status.contains(StatusRegister::VBLANK_STARTED));
+        assert!(ppu.nmi_line());
+        assert_eq!(ppu.frame_count, 1);
+
+        // Disabling NMIs drops the line without touching the flag
+        ppu.write_register(&mut mapper, 0x2000, 0x00);
+        assert!(!ppu.nmi_line());
+        ppu.write_register(&mut mapper, 0x2000, 0x80);
+        assert!(ppu.nmi_line());
+
+        let dots_to_pre_render = 20 * DOTS_PER_SCANLINE as u64;
+        for _ in 0..dots_to_pre_render {
+            ppu.tick(&mut mapper);
+        }
+        assert!(!ppu.status.contains(StatusRegister::VBLANK_STARTED));
+        assert!(!ppu.nmi_line());
+    }
+
+    #[test]
+    fn test_odd_frames_skip_a_dot_while_rendering() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        let dots_per_frame = 262 * DOTS_PER_SCANLINE as u64;
+
+        for _ in 0..dots_per_frame {
+            ppu.tick(&mut mapper);
+        }
+        assert_eq!((ppu.scanline, ppu.dot), (0, 0));
+
+        ppu.write_register(&mut mapper, 0x2001, 0b0000_1000);
+        for _ in 0..dots_per_frame - 1 {
+            ppu.tick(&mut mapper);
+        }
+        assert_eq!((ppu.scanline, ppu.dot), (0, 0));
+
+        for _ in 0..dots_per_frame {
+            ppu.tick(&mut mapper);
+        }
+        assert_eq!((ppu.scanline, ppu.dot), (0, 0));
+    }
+}
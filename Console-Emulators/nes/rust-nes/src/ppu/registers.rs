@@ -0,0 +1,127 @@
+use bitflags::bitflags;
+
+bitflags! {
+    /// PPUCTRL ($2000)
+    /// 7  bit  0
+    /// ---- ----
+    /// VPHB SINN
+    /// |||| ||||
+    /// |||| ||++- Base nametable address
+    /// |||| ||    (0 = $2000; 1 = $2400; 2 = $2800; 3 = $2C00)
+    /// |||| |+--- VRAM address increment per CPU read/write of PPUDATA
+    /// |||| |     (0: add 1, going across; 1: add 32, going down)
+    /// |||| +---- Sprite pattern table address for 8x8 sprites
+    /// ||||       (0: $0000; 1: $1000; ignored in 8x16 mode)
+    /// |||+------ Background pattern table address (0: $0000; 1: $1000)
+    /// ||+------- Sprite size (0: 8x8 pixels; 1: 8x16 pixels)
+    /// |+-------- PPU master/slave select
+    /// |          (0: read backdrop from EXT pins; 1: output color on EXT pins)
+    /// +--------- Generate an NMI at the start of the
+    ///            vertical blanking interval (0: off; 1: on)
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ControlRegister: u8 {
+        const NAMETABLE1              = 0b0000_0001;
+        const NAMETABLE2              = 0b0000_0010;
+        const VRAM_ADD_INCREMENT      = 0b0000_0100;
+        const SPRITE_PATTERN_ADDR     = 0b0000_1000;
+        const BACKGROUND_PATTERN_ADDR = 0b0001_0000;
+        const SPRITE_SIZE             = 0b0010_0000;
+        const MASTER_SLAVE_SELECT     = 0b0100_0000;
+        const GENERATE_NMI            = 0b1000_0000;
+    }
+}
+
+impl ControlRegister {
+    pub fn nametable_index(&self) -> u16 {
+        (self.bits() & 0b11) as u16
+    }
+
+    pub fn vram_addr_increment(&self) -> u16 {
+        if self.contains(ControlRegister::VRAM_ADD_INCREMENT) {
+            32
+        } else {
+            1
+        }
+    }
+
+    pub fn sprite_pattern_addr(&self) -> u16 {
+        if self.contains(ControlRegister::SPRITE_PATTERN_ADDR) {
+            0x1000
+        } else {
+            0
+        }
+    }
+
+    pub fn background_pattern_addr(&self) -> u16 {
+        if self.contains(ControlRegister::BACKGROUND_PATTERN_ADDR) {
+            0x1000
+        } else {
+            0
+        }
+    }
+
+    pub fn sprite_size(&self) -> u8 {
+        if self.contains(ControlRegister::SPRITE_SIZE) {
+            16
+        } else {
+            8
+        }
+    }
+
+    pub fn generate_vblank_nmi(&self) -> bool {
+        self.contains(ControlRegister::GENERATE_NMI)
+    }
+}
+
+bitflags! {
+    /// PPUMASK ($2001)
+    /// 7  bit  0
+    /// ---- ----
+    /// BGRs bMmG
+    /// |||| ||||
+    /// |||| |||+- Greyscale (0: normal color, 1: produce a greyscale display)
+    /// |||| ||+-- 1: Show background in leftmost 8 pixels of screen, 0: Hide
+    /// |||| |+--- 1: Show sprites in leftmost 8 pixels of screen, 0: Hide
+    /// |||| +---- 1: Show background
+    /// |||+------ 1: Show sprites
+    /// ||+------- Emphasize red
+    /// |+-------- Emphasize green
+    /// +--------- Emphasize blue
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct MaskRegister: u8 {
+        const GREYSCALE                = 0b0000_0001;
+        const LEFTMOST_8PXL_BACKGROUND = 0b0000_0010;
+        const LEFTMOST_8PXL_SPRITE     = 0b0000_0100;
+        const SHOW_BACKGROUND          = 0b0000_1000;
+        const SHOW_SPRITES             = 0b0001_0000;
+        const EMPHASISE_RED            = 0b0010_0000;
+        const EMPHASISE_GREEN          = 0b0100_0000;
+        const EMPHASISE_BLUE           = 0b1000_0000;
+    }
+}
+
+impl MaskRegister {
+    /// The PPU only fetches and advances its scroll while at least one
+    /// layer is switched on
+    pub fn rendering_enabled(&self) -> bool {
+        self.intersects(MaskRegister::SHOW_BACKGROUND | MaskRegister::SHOW_SPRITES)
+    }
+}
+
+bitflags! {
+    /// PPUSTATUS ($2002)
+    /// 7  bit  0
+    /// ---- ----
+    /// VSO. ....
+    /// |||| ||||
+    /// |||+-++++- Open bus, the low bits of the last value on the PPU data bus
+    /// ||+------- Sprite overflow
+    /// |+-------- Sprite 0 hit
+    /// +--------- Vertical blank has started (0: not in vblank; 1: in vblank)
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct StatusRegister: u8 {
+        const SPRITE_OVERFLOW = 0b0010_0000;
+        const SPRITE_ZERO_HIT = 0b0100_0000;
+        const VBLANK_STARTED  = 0b1000_0000;
+    }
+}
@@ -0,0 +1,434 @@
+use super::palette::SYSTEM_PALETTE;
+use super::registers::{MaskRegister, StatusRegister};
+use super::{Ppu, PRE_RENDER_SCANLINE};
+use crate::mapper::Mapper;
+
+/// Pattern Tables
+/// ==============
+/// Each tile is 16 bytes, two 8x8 bit planes one after the other. Bit 7 of
+/// each byte is the leftmost pixel and the two planes combine into a 2 bit
+/// colour within the tile's palette, where 0 is transparent.
+///
+/// Attribute Tables
+/// ================
+/// The last 64 bytes of each nametable pick a background palette for every
+/// 2x2 tile area, four areas packed into each byte.
+///
+/// 7654 3210
+/// |||| ||++- Top left
+/// |||| ++--- Top right
+/// ||++------ Bottom left
+/// ++-------- Bottom right
+const ATTRIBUTE_TABLE: u16 = 0x23C0;
+
+/// Background Pipeline
+/// ===================
+/// Every 8 dots the PPU fetches a nametable byte, an attribute byte and the
+/// two pattern bytes of the next tile, then loads them into the low half of
+/// 16 bit shift registers. The registers shift once per dot and fine X picks
+/// which bit of the high half is drawn, so a tile can start on any pixel.
+///
+/// Dot     | Fetch
+/// 1-256   | Tiles 3-34 of this line, 2 tiles ahead of the pixel drawn
+/// 256     | Increment fine/coarse Y
+/// 257     | Copy horizontal scroll from t
+/// 280-304 | Copy vertical scroll from t (pre-render line only)
+/// 321-336 | First two tiles of the next line
+#[derive(Default)]
+pub(super) struct BackgroundPipeline {
+    next_tile: u8,
+    next_attribute: u8,
+    next_pattern_low: u8,
+    next_pattern_high: u8,
+    pattern_low: u16,
+    pattern_high: u16,
+    attribute_low: u16,
+    attribute_high: u16,
+}
+
+impl BackgroundPipeline {
+    fn load(&mut self) {
+        self.pattern_low = (self.pattern_low & 0xFF00) | self.next_pattern_low as u16;
+        self.pattern_high = (self.pattern_high & 0xFF00) | self.next_pattern_high as u16;
+        let attribute_low = if self.next_attribute & 0b01 != 0 {
+            0xFF
+        } else {
+            0x00
+        };
+        let attribute_high = if self.next_attribute & 0b10 != 0 {
+            0xFF
+        } else {
+            0x00
+        };
+        self.attribute_low = (self.attribute_low & 0xFF00) | attribute_low;
+        self.attribute_high = (self.attribute_high & 0xFF00) | attribute_high;
+    }
+
+    fn shift(&mut self) {
+        self.pattern_low <<= 1;
+        self.pattern_high <<= 1;
+        self.attribute_low <<= 1;
+        self.attribute_high <<= 1;
+    }
+
+    /// Pattern value (0-3) and palette (0-3) of the pixel being drawn
+    fn pixel(&self, fine_x: u8) -> (u8, u8) {
+        let bit = 0x8000 >> fine_x;
+        let pixel =
+            (((self.pattern_high & bit) != 0) as u8) << 1 | ((self.pattern_low & bit) != 0) as u8;
+        let palette = (((self.attribute_high & bit) != 0) as u8) << 1
+            | ((self.attribute_low & bit) != 0) as u8;
+        (pixel, palette)
+    }
+}
+
+impl Ppu {
+    /// Reads from the PPU bus as part of rendering, which the mapper can see
+    pub(super) fn fetch(&mut self, mapper: &mut dyn Mapper, addr: u16) -> u8 {
+        mapper.notify_ppu_address(addr, self.cycles);
+        self.read_vram(mapper, addr)
+    }
+
+    /// One dot of background fetching and scrolling, on the visible and
+    /// pre-render lines while rendering is enabled
+    pub(super) fn step_background(&mut self, mapper: &mut dyn Mapper) {
+        let dot = self.dot;
+
+        if (2..=257).contains(&dot) || (322..=337).contains(&dot) {
+            self.background.shift();
+        }
+
+        if (1..=256).contains(&dot) || (321..=336).contains(&dot) {
+            match dot % 8 {
+                1 => {
+                    self.background.load();
+                    self.background.next_tile = self.fetch(mapper, 0x2000 | (self.v & 0x0FFF));
+                }
+                3 => {
+                    let v = self.v;
+                    let addr =
+                        ATTRIBUTE_TABLE | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07);
+                    let shift = ((v >> 4) & 0b100) | (v & 0b10);
+                    self.background.next_attribute = (self.fetch(mapper, addr) >> shift) & 0b11;
+                }
+                5 => {
+                    let addr = self.background_pattern_row();
+                    self.background.next_pattern_low = self.fetch(mapper, addr);
+                }
+                7 => {
+                    let addr = self.background_pattern_row() + 8;
+                    self.background.next_pattern_high = self.fetch(mapper, addr);
+                }
+                0 => self.increment_coarse_x(),
+                _ => {}
+            }
+        }
+
+        match dot {
+            256 => self.increment_y(),
+            257 => {
+                self.background.load();
+                self.copy_horizontal();
+            }
+            280..=304 if self.scanline == PRE_RENDER_SCANLINE => self.copy_vertical(),
+            // Unused nametable fetches at the end of the line
+            337 => {
+                self.background.load();
+                self.fetch(mapper, 0x2000 | (self.v & 0x0FFF));
+            }
+            339 => {
+                self.fetch(mapper, 0x2000 | (self.v & 0x0FFF));
+            }
+            _ => {}
+        }
+    }
+
+    fn background_pattern_row(&self) -> u16 {
+        self.ctrl.background_pattern_addr() + self.background.next_tile as u16 * 16 + self.fine_y()
+    }
+
+    /// Draws the pixel for the current dot into the frame
+    pub(super) fn render_pixel(&mut self) {
+        let x = self.dot as usize - 1;
+        let y = self.scanline as usize;
+
+        let mut color = if !self.mask.rendering_enabled() && self.v & 0x3F00 == 0x3F00 {
+            // With rendering off the backdrop comes from wherever v points
+            // if that is inside the palette
+            self.read_palette((self.v & 0x1F) as usize)
+        } else {
+            self.read_palette(0)
+        };
+
+        let mut background_opaque = false;
+        if self.mask.contains(MaskRegister::SHOW_BACKGROUND)
+            && (x >= 8 || self.mask.contains(MaskRegister::LEFTMOST_8PXL_BACKGROUND))
+        {
+            let (pixel, palette) = self.background.pixel(self.fine_x);
+            if pixel != 0 {
+                color = self.read_palette(palette as usize * 4 + pixel as usize);
+                background_opaque = true;
+            }
+        }
+
+        if self.mask.contains(MaskRegister::SHOW_SPRITES)
+            && (x >= 8 || self.mask.contains(MaskRegister::LEFTMOST_8PXL_SPRITE))
+        {
+            if let Some(sprite) = self.sprite_pixel(x as u8) {
+                // Sprite 0 hit never happens on the last pixel of a line
+                if sprite.sprite_zero && background_opaque && x != 255 {
+                    self.status.insert(StatusRegister::SPRITE_ZERO_HIT);
+                }
+                if !sprite.behind_background || !background_opaque {
+                    color = self.read_palette(sprite.palette as usize * 4 + sprite.pixel as usize);
+                }
+            }
+        }
+
+        self.frame
+            .set_pixel(x, y, SYSTEM_PALETTE[(color & 0x3F) as usize]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::{
+        load_tiles, render_frame, rgb, set_ppu_addr, test_mapper, tick_until, write_bytes,
+    };
+    use super::*;
+    use crate::cartridge::Mirroring;
+
+    /// Tile 1 is solid colour 1, tile 2 has colour 3 in its left column
+    const TILES: [(u8, u8); 2] = [(0xff, 0x00), (0x80, 0x80)];
+
+    /// Points v and t at the top left of the first nametable, as a game
+    /// would before turning rendering on
+    fn reset_scroll(ppu: &mut Ppu, mapper: &mut dyn Mapper) {
+        set_ppu_addr(ppu, mapper, 0x0000);
+    }
+
+    #[test]
+    fn test_backdrop_when_rendering_disabled() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        load_tiles(&mut ppu, &mut mapper, &TILES);
+        reset_scroll(&mut ppu, &mut mapper);
+        render_frame(&mut ppu, &mut mapper);
+
+        assert_eq!(ppu.frame.pixel(0, 0), rgb(0x0f));
+        assert_eq!(ppu.frame.pixel(255, 239), rgb(0x0f));
+    }
+
+    #[test]
+    fn test_background_tiles_and_attributes() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        load_tiles(&mut ppu, &mut mapper, &TILES);
+        write_bytes(&mut ppu, &mut mapper, 0x3f07, &[0x21]);
+        // Tile (1, 0) solid, tile (2, 1) in the top right quadrant of
+        // attribute byte 0 using palette 1
+        write_bytes(&mut ppu, &mut mapper, 0x2001, &[0x01]);
+        write_bytes(&mut ppu, &mut mapper, 0x2022, &[0x02]);
+        write_bytes(&mut ppu, &mut mapper, 0x23c0, &[0b0000_0100]);
+        reset_scroll(&mut ppu, &mut mapper);
+        ppu.write_register(&mut mapper, 0x2001, 0b0000_1010);
+        render_frame(&mut ppu, &mut mapper);
+
+        assert_eq!(ppu.frame.pixel(7, 0), rgb(0x0f));
+        assert_eq!(ppu.frame.pixel(8, 0), rgb(0x01));
+        assert_eq!(ppu.frame.pixel(15, 7), rgb(0x01));
+        assert_eq!(ppu.frame.pixel(16, 8), rgb(0x21));
+        assert_eq!(ppu.frame.pixel(16, 16), rgb(0x0f));
+    }
+
+    #[test]
+    fn test_background_left_column_clipping() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        load_tiles(&mut ppu, &mut mapper, &TILES);
+        write_bytes(&mut ppu, &mut mapper, 0x2000, &[0x01, 0x01]);
+        reset_scroll(&mut ppu, &mut mapper);
+        ppu.write_register(&mut mapper, 0x2001, 0b0000_1000);
+        render_frame(&mut ppu, &mut mapper);
+
+        assert_eq!(ppu.frame.pixel(0, 0), rgb(0x0f));
+        assert_eq!(ppu.frame.pixel(8, 0), rgb(0x01));
+    }
+
+    #[test]
+    fn test_background_scroll() {
+        let mut mapper = test_mapper(Mirroring::Vertical);
+        let mut ppu = Ppu::new();
+        load_tiles(&mut ppu, &mut mapper, &TILES);
+        // First tile of the second nametable
+        write_bytes(&mut ppu, &mut mapper, 0x2400, &[0x01]);
+        reset_scroll(&mut ppu, &mut mapper);
+        ppu.write_register(&mut mapper, 0x2005, 0xf8);
+        ppu.write_register(&mut mapper, 0x2005, 0x00);
+        ppu.write_register(&mut mapper, 0x2001, 0b0000_1010);
+        render_frame(&mut ppu, &mut mapper);
+
+        assert_eq!(ppu.frame.pixel(7, 0), rgb(0x0f));
+        assert_eq!(ppu.frame.pixel(8, 0), rgb(0x01));
+        assert_eq!(ppu.frame.pixel(15, 0), rgb(0x01));
+        assert_eq!(ppu.frame.pixel(16, 0), rgb(0x0f));
+    }
+
+    #[test]
+    fn test_sprite_position_and_flip() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        load_tiles(&mut ppu, &mut mapper, &TILES);
+        ppu.oam_data = [0xff; 256];
+        ppu.oam_data[0..8].copy_from_slice(&[9, 2, 0b0000_0000, 20, 29, 2, 0b0100_0000, 20]);
+        reset_scroll(&mut ppu, &mut mapper);
+        ppu.write_register(&mut mapper, 0x2001, 0b0001_0100);
+        render_frame(&mut ppu, &mut mapper);
+
+        // Drawn one line below its Y coordinate
+        assert_eq!(ppu.frame.pixel(20, 9), rgb(0x0f));
+        assert_eq!(ppu.frame.pixel(20, 10), rgb(0x13));
+        assert_eq!(ppu.frame.pixel(20, 17), rgb(0x13));
+        assert_eq!(ppu.frame.pixel(20, 18), rgb(0x0f));
+        assert_eq!(ppu.frame.pixel(21, 10), rgb(0x0f));
+
+        // Flipped horizontally the column moves to the right edge
+        assert_eq!(ppu.frame.pixel(20, 30), rgb(0x0f));
+        assert_eq!(ppu.frame.pixel(27, 30), rgb(0x13));
+    }
+
+    #[test]
+    fn test_sprite_priority() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        load_tiles(&mut ppu, &mut mapper, &TILES);
+        write_bytes(&mut ppu, &mut mapper, 0x2000, &[0x00, 0x02]);
+        ppu.oam_data = [0xff; 256];
+        // Sprite 0 is behind the background and wins over sprite 1 in
+        // front of it, so the background shows through both
+        ppu.oam_data[0..8].copy_from_slice(&[0, 2, 0b0010_0000, 8, 0, 2, 0b0000_0001, 8]);
+        // Over transparent background both priorities are visible
+        ppu.oam_data[8..16].copy_from_slice(&[0, 2, 0b0000_0000, 40, 0, 2, 0b0010_0000, 48]);
+        reset_scroll(&mut ppu, &mut mapper);
+        ppu.write_register(&mut mapper, 0x2001, 0b0001_1110);
+        render_frame(&mut ppu, &mut mapper);
+
+        assert_eq!(ppu.frame.pixel(8, 1), rgb(0x03));
+        assert_eq!(ppu.frame.pixel(40, 1), rgb(0x13));
+        assert_eq!(ppu.frame.pixel(48, 1), rgb(0x13));
+    }
+
+    #[test]
+    fn test_fine_x_scroll() {
+        let mut mapper = test_mapper(Mirroring::Vertical);
+        let mut ppu = Ppu::new();
+        load_tiles(&mut ppu, &mut mapper, &TILES);
+        write_bytes(&mut ppu, &mut mapper, 0x2001, &[0x01]);
+        reset_scroll(&mut ppu, &mut mapper);
+        ppu.write_register(&mut mapper, 0x2005, 0x03);
+        ppu.write_register(&mut mapper, 0x2005, 0x00);
+        ppu.write_register(&mut mapper, 0x2001, 0b0000_1010);
+        render_frame(&mut ppu, &mut mapper);
+
+        assert_eq!(ppu.frame.pixel(4, 0), rgb(0x0f));
+        assert_eq!(ppu.frame.pixel(5, 0), rgb(0x01));
+        assert_eq!(ppu.frame.pixel(12, 7), rgb(0x01));
+        assert_eq!(ppu.frame.pixel(13, 7), rgb(0x0f));
+    }
+
+    #[test]
+    fn test_fine_y_scroll() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        load_tiles(&mut ppu, &mut mapper, &TILES);
+        write_bytes(&mut ppu, &mut mapper, 0x2020, &[0x01]);
+        reset_scroll(&mut ppu, &mut mapper);
+        ppu.write_register(&mut mapper, 0x2005, 0x00);
+        ppu.write_register(&mut mapper, 0x2005, 0x05);
+        ppu.write_register(&mut mapper, 0x2001, 0b0000_1010);
+        render_frame(&mut ppu, &mut mapper);
+
+        assert_eq!(ppu.frame.pixel(0, 2), rgb(0x0f));
+        assert_eq!(ppu.frame.pixel(0, 3), rgb(0x01));
+        assert_eq!(ppu.frame.pixel(0, 10), rgb(0x01));
+        assert_eq!(ppu.frame.pixel(0, 11), rgb(0x0f));
+    }
+
+    #[test]
+    fn test_mid_frame_horizontal_scroll_split() {
+        let mut mapper = test_mapper(Mirroring::Vertical);
+        let mut ppu = Ppu::new();
+        load_tiles(&mut ppu, &mut mapper, &TILES);
+        for row in 0..30 {
+            write_bytes(&mut ppu, &mut mapper, 0x2001 + row * 32, &[0x01]);
+        }
+        reset_scroll(&mut ppu, &mut mapper);
+        ppu.write_register(&mut mapper, 0x2005, 0x00);
+        ppu.write_register(&mut mapper, 0x2005, 0x00);
+        ppu.write_register(&mut mapper, 0x2001, 0b0000_1010);
+
+        // Takes effect from the horizontal copy at dot 257 of line 100
+        tick_until(&mut ppu, &mut mapper, 261, 0);
+        tick_until(&mut ppu, &mut mapper, 100, 100);
+        ppu.write_register(&mut mapper, 0x2005, 0x08);
+        ppu.write_register(&mut mapper, 0x2005, 0x00);
+        tick_until(&mut ppu, &mut mapper, 240, 0);
+
+        assert_eq!(ppu.frame.pixel(0, 99), rgb(0x0f));
+        assert_eq!(ppu.frame.pixel(8, 99), rgb(0x01));
+        assert_eq!(ppu.frame.pixel(8, 100), rgb(0x01));
+        assert_eq!(ppu.frame.pixel(0, 101), rgb(0x01));
+        assert_eq!(ppu.frame.pixel(8, 101), rgb(0x0f));
+        assert_eq!(ppu.frame.pixel(0, 239), rgb(0x01));
+    }
+
+    #[test]
+    fn test_mid_frame_vertical_split_through_ppuaddr() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        load_tiles(&mut ppu, &mut mapper, &TILES);
+        write_bytes(&mut ppu, &mut mapper, 0x2000, &[0x01; 32]);
+        reset_scroll(&mut ppu, &mut mapper);
+        ppu.write_register(&mut mapper, 0x2005, 0x00);
+        ppu.write_register(&mut mapper, 0x2005, 0x00);
+        ppu.write_register(&mut mapper, 0x2001, 0b0000_1010);
+
+        // Point v back at the top row during hblank of line 119. In loopy
+        // terms $0000 is nametable 0, coarse and fine Y 0.
+        tick_until(&mut ppu, &mut mapper, 261, 0);
+        tick_until(&mut ppu, &mut mapper, 119, 300);
+        set_ppu_addr(&mut ppu, &mut mapper, 0x0000);
+        tick_until(&mut ppu, &mut mapper, 240, 0);
+
+        assert_eq!(ppu.frame.pixel(0, 0), rgb(0x01));
+        assert_eq!(ppu.frame.pixel(0, 8), rgb(0x0f));
+        assert_eq!(ppu.frame.pixel(0, 119), rgb(0x0f));
+        assert_eq!(ppu.frame.pixel(0, 120), rgb(0x01));
+        assert_eq!(ppu.frame.pixel(255, 127), rgb(0x01));
+        assert_eq!(ppu.frame.pixel(0, 128), rgb(0x0f));
+    }
+
+    #[test]
+    #[allow(clippy::unusual_byte_groupings)]
+    fn test_ppudata_during_rendering_bumps_coarse_x_and_y() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        ppu.write_register(&mut mapper, 0x2001, 0b0000_1000);
+        tick_until(&mut ppu, &mut mapper, 10, 300);
+        ppu.v = 0b000_00_00010_00011;
+
+        ppu.read_register(&mut mapper, 0x2007);
+        assert_eq!(ppu.v, 0b001_00_00010_00100);
+    }
+
+    #[test]
+    fn test_backdrop_follows_v_into_palette_when_rendering_disabled() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        load_tiles(&mut ppu, &mut mapper, &TILES);
+        set_ppu_addr(&mut ppu, &mut mapper, 0x3f02);
+        render_frame(&mut ppu, &mut mapper);
+
+        assert_eq!(ppu.frame.pixel(0, 0), rgb(0x02));
+    }
+}
@@ -0,0 +1,176 @@
+#![allow(clippy::unusual_byte_groupings)]
+
+use super::Ppu;
+
+/// Loopy Registers
+/// ===============
+/// The PPU keeps its scroll position in the same registers it uses to
+/// address VRAM, named after the person who first documented them.
+///
+/// v | Current VRAM address, 15 bits
+/// t | Temporary VRAM address, the top left of the screen
+/// x | Fine X scroll, 3 bits
+/// w | First or second write toggle for PPUSCROLL and PPUADDR
+///
+/// While rendering, v and t are laid out as:
+///
+/// yyy NN YYYYY XXXXX
+/// ||| || ||||| +++++-- Coarse X scroll
+/// ||| || +++++-------- Coarse Y scroll
+/// ||| ++-------------- Nametable select
+/// +++----------------- Fine Y scroll
+///
+/// Register writes only change t, which is copied into v at fixed points in
+/// the frame: the horizontal bits at dot 257 of every line and the vertical
+/// bits through dots 280-304 of the pre-render line. The second PPUADDR
+/// write copies all of t straight away, which is how games change the
+/// vertical scroll part way down the screen.
+const COARSE_X: u16 = 0b000_00_00000_11111;
+const COARSE_Y: u16 = 0b000_00_11111_00000;
+const NAMETABLE_X: u16 = 0b000_01_00000_00000;
+const NAMETABLE_Y: u16 = 0b000_10_00000_00000;
+const FINE_Y: u16 = 0b111_00_00000_00000;
+
+impl Ppu {
+    /// Moves v one tile right, into the next horizontal nametable at the
+    /// end of a row
+    pub(super) fn increment_coarse_x(&mut self) {
+        if self.v & COARSE_X == 31 {
+            self.v &= !COARSE_X;
+            self.v ^= NAMETABLE_X;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    /// Moves v down one pixel. Row 29 is the last row of tiles, after it
+    /// coarse Y wraps into the next vertical nametable. Rows 30 and 31 are
+    /// the attribute table and wrap without switching nametables.
+    pub(super) fn increment_y(&mut self) {
+        if self.v & FINE_Y != FINE_Y {
+            self.v += 0x1000;
+            return;
+        }
+
+        self.v &= !FINE_Y;
+        let mut coarse_y = (self.v & COARSE_Y) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.v ^= NAMETABLE_Y;
+        } else if coarse_y == 31 {
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !COARSE_Y) | (coarse_y << 5);
+    }
+
+    pub(super) fn copy_horizontal(&mut self) {
+        let mask = COARSE_X | NAMETABLE_X;
+        self.v = (self.v & !mask) | (self.t & mask);
+    }
+
+    pub(super) fn copy_vertical(&mut self) {
+        let mask = FINE_Y | NAMETABLE_Y | COARSE_Y;
+        self.v = (self.v & !mask) | (self.t & mask);
+    }
+
+    pub(super) fn fine_y(&self) -> u16 {
+        (self.v & FINE_Y) >> 12
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::test_mapper;
+    use super::*;
+    use crate::cartridge::Mirroring;
+
+    // Example from https://www.nesdev.org/wiki/PPU_scrolling
+    #[test]
+    fn test_register_writes_update_t_v_x_and_w() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+
+        ppu.write_register(&mut mapper, 0x2000, 0b0000_0011);
+        assert_eq!(ppu.t, 0b000_11_00000_00000);
+
+        ppu.read_register(&mut mapper, 0x2002);
+        assert!(!ppu.w);
+
+        ppu.write_register(&mut mapper, 0x2005, 0b0111_1101);
+        assert_eq!(ppu.t, 0b000_11_00000_01111);
+        assert_eq!(ppu.fine_x, 0b101);
+        assert!(ppu.w);
+
+        ppu.write_register(&mut mapper, 0x2005, 0b0101_1110);
+        assert_eq!(ppu.t, 0b110_11_01011_01111);
+        assert!(!ppu.w);
+
+        ppu.write_register(&mut mapper, 0x2006, 0b0011_1101);
+        assert_eq!(ppu.t, 0b011_11_01011_01111);
+        assert!(ppu.w);
+
+        ppu.write_register(&mut mapper, 0x2006, 0b1111_0000);
+        assert_eq!(ppu.t, 0b011_11_01111_10000);
+        assert_eq!(ppu.v, ppu.t);
+        assert!(!ppu.w);
+    }
+
+    #[test]
+    fn test_first_ppuaddr_write_clears_bit_14() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        ppu.write_register(&mut mapper, 0x2005, 0x00);
+        ppu.write_register(&mut mapper, 0x2005, 0xff);
+        assert_eq!(ppu.t & 0x4000, 0x4000);
+
+        ppu.write_register(&mut mapper, 0x2006, 0xff);
+        assert_eq!(ppu.t & 0x4000, 0);
+    }
+
+    #[test]
+    fn test_coarse_x_wraps_into_next_nametable() {
+        let mut ppu = Ppu::new();
+        ppu.v = 0b000_00_00000_11110;
+        ppu.increment_coarse_x();
+        assert_eq!(ppu.v, 0b000_00_00000_11111);
+        ppu.increment_coarse_x();
+        assert_eq!(ppu.v, 0b000_01_00000_00000);
+        ppu.v = 0b000_01_00000_11111;
+        ppu.increment_coarse_x();
+        assert_eq!(ppu.v, 0b000_00_00000_00000);
+    }
+
+    #[test]
+    fn test_increment_y() {
+        let mut ppu = Ppu::new();
+        ppu.v = 0b110_00_00011_00000;
+        ppu.increment_y();
+        assert_eq!(ppu.v, 0b111_00_00011_00000);
+        ppu.increment_y();
+        assert_eq!(ppu.v, 0b000_00_00100_00000);
+
+        // Row 29 moves to the next nametable
+        ppu.v = 0b111_00_11101_00000;
+        ppu.increment_y();
+        assert_eq!(ppu.v, 0b000_10_00000_00000);
+
+        // Row 31 wraps inside the same nametable
+        ppu.v = 0b111_00_11111_00000;
+        ppu.increment_y();
+        assert_eq!(ppu.v, 0b000_00_00000_00000);
+    }
+
+    #[test]
+    fn test_copy_horizontal_and_vertical() {
+        let mut ppu = Ppu::new();
+        ppu.t = 0b101_11_10101_01010;
+        ppu.v = 0;
+
+        ppu.copy_horizontal();
+        assert_eq!(ppu.v, 0b000_01_00000_01010);
+        ppu.copy_vertical();
+        assert_eq!(ppu.v, ppu.t);
+    }
+}
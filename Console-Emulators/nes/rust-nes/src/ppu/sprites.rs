@@ -0,0 +1,484 @@
+use super::registers::StatusRegister;
+use super::{Ppu, PRE_RENDER_SCANLINE};
+use crate::mapper::Mapper;
+
+/// OAM Sprite Entries
+/// ==================
+/// Byte | Contents
+/// 0    | Y position of the top of the sprite, minus 1
+/// 1    | Tile index
+/// 2    | Attributes
+/// 3    | X position of the left of the sprite
+///
+/// 76543210
+/// |||   ||
+/// |||   ++- Palette (4 to 7) of sprite
+/// ||+------ Priority (0: in front of background; 1: behind background)
+/// |+------- Flip sprite horizontally
+/// +-------- Flip sprite vertically
+///
+/// In 8x16 mode bit 0 of the tile index picks the pattern table and the
+/// rest picks an even tile for the top half, the odd tile after it is the
+/// bottom half.
+const FLIP_VERTICAL: u8 = 0b1000_0000;
+const FLIP_HORIZONTAL: u8 = 0b0100_0000;
+const BEHIND_BACKGROUND: u8 = 0b0010_0000;
+const PALETTE: u8 = 0b0000_0011;
+
+/// Sprite Evaluation
+/// =================
+/// While drawing a line the PPU searches OAM for the sprites on the next
+/// one, copying up to 8 of them into a 32 byte secondary OAM. It then
+/// fetches their patterns into 8 sprite units, which output pixels once
+/// the dot reaches their X position.
+///
+/// Dot     | Work
+/// 1-64    | Clear secondary OAM to $FF
+/// 65-256  | Evaluate, 2 dots per sprite checked and 6 more to copy one
+/// 257-320 | Fetch patterns for the 8 slots, OAMADDR held at 0
+///
+/// Evaluation does not run on the pre-render line, so nothing is drawn on
+/// line 0.
+const MAX_SPRITES_PER_LINE: usize = 8;
+const EVALUATION_START: u16 = 65;
+const FETCH_START: u16 = 257;
+const FETCH_END: u16 = 320;
+
+#[derive(Clone, Copy, Default)]
+struct SpriteUnit {
+    /// Pattern bytes, already reversed for horizontally flipped sprites
+    pattern_low: u8,
+    pattern_high: u8,
+    attributes: u8,
+    x: u8,
+}
+
+pub(super) struct SpritePipeline {
+    secondary_oam: [u8; 32],
+    /// Sprites found for the next line and whether sprite 0 is among them
+    found: usize,
+    sprite_zero_found: bool,
+    /// Dot on this line where evaluation trips the overflow flag
+    overflow_dot: Option<u16>,
+    units: [SpriteUnit; MAX_SPRITES_PER_LINE],
+    sprite_zero_in_units: bool,
+}
+
+impl Default for SpritePipeline {
+    fn default() -> Self {
+        SpritePipeline {
+            secondary_oam: [0xFF; 32],
+            found: 0,
+            sprite_zero_found: false,
+            overflow_dot: None,
+            units: [SpriteUnit::default(); MAX_SPRITES_PER_LINE],
+            sprite_zero_in_units: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(super) struct SpritePixel {
+    pub(super) pixel: u8,
+    pub(super) palette: u8,
+    pub(super) behind_background: bool,
+    pub(super) sprite_zero: bool,
+}
+
+impl Ppu {
+    /// One dot of sprite evaluation and fetching, on the visible and
+    /// pre-render lines while rendering is enabled
+    pub(super) fn step_sprites(&mut self, mapper: &mut dyn Mapper) {
+        let dot = self.dot;
+
+        if dot == EVALUATION_START {
+            if self.scanline == PRE_RENDER_SCANLINE {
+                self.sprites.secondary_oam = [0xFF; 32];
+                self.sprites.found = 0;
+                self.sprites.sprite_zero_found = false;
+                self.sprites.overflow_dot = None;
+            } else {
+                self.evaluate_sprites();
+            }
+        }
+
+        if self.sprites.overflow_dot == Some(dot) {
+            self.status.insert(StatusRegister::SPRITE_OVERFLOW);
+        }
+
+        if (FETCH_START..=FETCH_END).contains(&dot) {
+            self.oam_addr = 0;
+            let slot = (dot - FETCH_START) as usize / 8;
+            match (dot - FETCH_START) % 8 {
+                4 => {
+                    let addr = self.sprite_pattern_row(slot);
+                    self.sprites.units[slot].pattern_low = self.fetch(mapper, addr);
+                }
+                6 => {
+                    let addr = self.sprite_pattern_row(slot) + 8;
+                    self.sprites.units[slot].pattern_high = self.fetch(mapper, addr);
+                }
+                7 => self.load_sprite_unit(slot),
+                _ => {}
+            }
+            if dot == FETCH_END {
+                self.sprites.sprite_zero_in_units = self.sprites.sprite_zero_found;
+            }
+        }
+    }
+
+    /// Copies the sprites on the next line into secondary OAM the way the
+    /// hardware does, including its broken overflow search. Once 8 sprites
+    /// are found it keeps looking for a 9th but steps through the bytes of
+    /// each entry as well as the entries themselves, so it compares tile
+    /// indexes, attributes and X positions against the line as if they were
+    /// Y coordinates. That gives both false positives and missed overflows.
+    fn evaluate_sprites(&mut self) {
+        let height = self.ctrl.sprite_size() as i16;
+        let scanline = self.scanline as i16;
+        let in_range = |y: u8| (0..height).contains(&(scanline - y as i16));
+
+        let sprites = &mut self.sprites;
+        sprites.secondary_oam = [0xFF; 32];
+        sprites.found = 0;
+        sprites.sprite_zero_found = false;
+        sprites.overflow_dot = None;
+
+        let mut dot = EVALUATION_START;
+        let mut n = 0;
+        while n < 64 && sprites.found < MAX_SPRITES_PER_LINE {
+            let entry = &self.oam_data[n * 4..n * 4 + 4];
+            if in_range(entry[0]) {
+                let slot = sprites.found * 4;
+                sprites.secondary_oam[slot..slot + 4].copy_from_slice(entry);
+                sprites.sprite_zero_found |= n == 0;
+                sprites.found += 1;
+                dot += 8;
+            } else {
+                dot += 2;
+            }
+            n += 1;
+        }
+
+        let mut m = 0;
+        while n < 64 {
+            if in_range(self.oam_data[n * 4 + m]) {
+                sprites.overflow_dot = Some(dot);
+                break;
+            }
+            n += 1;
+            m = (m + 1) % 4;
+            dot += 2;
+        }
+    }
+
+    /// Address of the pattern row a sprite slot fetches. Empty slots still
+    /// fetch, from tile $FF.
+    fn sprite_pattern_row(&self, slot: usize) -> u16 {
+        let height = self.ctrl.sprite_size() as u16;
+        let entry = &self.sprites.secondary_oam[slot * 4..slot * 4 + 4];
+        let mut tile = entry[1] as u16;
+
+        let mut row = if slot < self.sprites.found {
+            // The height may have changed since evaluation, the hardware
+            // only uses as many bits of the row as the current height has
+            let row = self.scanline.wrapping_sub(entry[0] as u16) & (height - 1);
+            if entry[2] & FLIP_VERTICAL != 0 {
+                height - 1 - row
+            } else {
+                row
+            }
+        } else {
+            0
+        };
+
+        let table = if height == 16 {
+            let table = (tile & 1) * 0x1000;
+            tile &= 0xFE;
+            if row >= 8 {
+                tile += 1;
+                row -= 8;
+            }
+            table
+        } else {
+            self.ctrl.sprite_pattern_addr()
+        };
+        table + tile * 16 + row
+    }
+
+    fn load_sprite_unit(&mut self, slot: usize) {
+        let sprites = &mut self.sprites;
+        let unit = &mut sprites.units[slot];
+        if slot >= sprites.found {
+            *unit = SpriteUnit::default();
+            return;
+        }
+        unit.attributes = sprites.secondary_oam[slot * 4 + 2];
+        unit.x = sprites.secondary_oam[slot * 4 + 3];
+        if unit.attributes & FLIP_HORIZONTAL != 0 {
+            unit.pattern_low = unit.pattern_low.reverse_bits();
+            unit.pattern_high = unit.pattern_high.reverse_bits();
+        }
+    }
+
+    /// The first opaque sprite pixel at x. Lower slots win even when they
+    /// sit behind the background, so a hidden sprite can still mask the
+    /// ones after it.
+    pub(super) fn sprite_pixel(&self, x: u8) -> Option<SpritePixel> {
+        self.sprites
+            .units
+            .iter()
+            .enumerate()
+            .find_map(|(slot, unit)| {
+                let column = x.checked_sub(unit.x).filter(|column| *column < 8)?;
+                let bit = 7 - column;
+                let pixel =
+                    (((unit.pattern_high >> bit) & 1) << 1) | ((unit.pattern_low >> bit) & 1);
+                (pixel != 0).then_some(SpritePixel {
+                    pixel,
+                    palette: 4 + (unit.attributes & PALETTE),
+                    behind_background: unit.attributes & BEHIND_BACKGROUND != 0,
+                    sprite_zero: slot == 0 && self.sprites.sprite_zero_in_units,
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::{
+        load_tiles, render_frame, rgb, set_ppu_addr, test_mapper, tick_until, write_bytes,
+    };
+    use super::*;
+    use crate::cartridge::Mirroring;
+
+    /// Tile 1 is solid colour 1, tile 2 is solid colour 2 and tile 3 is
+    /// solid colour 3
+    const TILES: [(u8, u8); 3] = [(0xff, 0x00), (0x00, 0xff), (0xff, 0xff)];
+
+    fn start_rendering(ppu: &mut Ppu, mapper: &mut dyn Mapper, mask: u8) {
+        set_ppu_addr(ppu, mapper, 0x0000);
+        ppu.write_register(mapper, 0x2001, mask);
+    }
+
+    fn overflow(ppu: &Ppu) -> bool {
+        ppu.status.contains(StatusRegister::SPRITE_OVERFLOW)
+    }
+
+    fn sprite_zero_hit(ppu: &Ppu) -> bool {
+        ppu.status.contains(StatusRegister::SPRITE_ZERO_HIT)
+    }
+
+    #[test]
+    fn test_eight_sprites_per_line() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        load_tiles(&mut ppu, &mut mapper, &TILES);
+        for n in 0..9 {
+            ppu.oam_data[n * 4..n * 4 + 4].copy_from_slice(&[19, 1, 0, n as u8 * 16]);
+        }
+        start_rendering(&mut ppu, &mut mapper, 0b0001_0100);
+        render_frame(&mut ppu, &mut mapper);
+
+        assert_eq!(ppu.frame.pixel(112, 20), rgb(0x11));
+        assert_eq!(ppu.frame.pixel(128, 20), rgb(0x0f));
+        assert!(overflow(&ppu));
+    }
+
+    #[test]
+    fn test_overflow_set_during_evaluation() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        load_tiles(&mut ppu, &mut mapper, &TILES);
+        for n in 0..9 {
+            ppu.oam_data[n * 4] = 19;
+        }
+        start_rendering(&mut ppu, &mut mapper, 0b0001_0100);
+        tick_until(&mut ppu, &mut mapper, 19, 0);
+        assert!(!overflow(&ppu));
+
+        // 8 copies of 8 dots each, then the 9th sprite is read
+        tick_until(&mut ppu, &mut mapper, 19, 65 + 64);
+        assert!(!overflow(&ppu));
+        ppu.tick(&mut mapper);
+        assert!(overflow(&ppu));
+
+        tick_until(&mut ppu, &mut mapper, 261, 2);
+        assert!(!overflow(&ppu));
+    }
+
+    #[test]
+    fn test_overflow_false_positive() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        load_tiles(&mut ppu, &mut mapper, &TILES);
+        for n in 0..8 {
+            ppu.oam_data[n * 4] = 19;
+        }
+        // Sprite 8 misses, so the search reads the tile index of sprite 9
+        // as its Y coordinate
+        ppu.oam_data[36..40].copy_from_slice(&[0xf0, 19, 0, 0]);
+        start_rendering(&mut ppu, &mut mapper, 0b0001_0100);
+        tick_until(&mut ppu, &mut mapper, 20, 0);
+
+        assert!(overflow(&ppu));
+    }
+
+    #[test]
+    fn test_overflow_false_negative() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        load_tiles(&mut ppu, &mut mapper, &TILES);
+        for n in 0..8 {
+            ppu.oam_data[n * 4] = 19;
+        }
+        // Sprite 9 is on the line but only its tile index gets checked
+        ppu.oam_data[36..40].copy_from_slice(&[19, 0xf0, 0xf0, 0xf0]);
+        start_rendering(&mut ppu, &mut mapper, 0b0001_0100);
+        render_frame(&mut ppu, &mut mapper);
+
+        assert!(!overflow(&ppu));
+    }
+
+    #[test]
+    fn test_sprites_not_drawn_on_first_line() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        load_tiles(&mut ppu, &mut mapper, &TILES);
+        // Y of $FF would put the sprite's bottom rows on lines 0 to 6
+        ppu.oam_data[0..4].copy_from_slice(&[0xff, 1, 0, 0]);
+        start_rendering(&mut ppu, &mut mapper, 0b0001_0100);
+        render_frame(&mut ppu, &mut mapper);
+
+        assert_eq!(ppu.frame.pixel(0, 0), rgb(0x0f));
+        assert_eq!(ppu.frame.pixel(0, 1), rgb(0x0f));
+    }
+
+    #[test]
+    fn test_8x16_sprites() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        load_tiles(&mut ppu, &mut mapper, &TILES);
+        // Tiles $102 and $103 in the right pattern table
+        write_bytes(&mut ppu, &mut mapper, 0x1020, &[0xff; 8]);
+        write_bytes(&mut ppu, &mut mapper, 0x1038, &[0xff; 8]);
+        ppu.oam_data[0..8].copy_from_slice(&[9, 0x03, 0, 0, 9, 0x03, FLIP_VERTICAL, 8]);
+        ppu.write_register(&mut mapper, 0x2000, 0b0010_0000);
+        start_rendering(&mut ppu, &mut mapper, 0b0001_0100);
+        render_frame(&mut ppu, &mut mapper);
+
+        assert_eq!(ppu.frame.pixel(0, 10), rgb(0x11));
+        assert_eq!(ppu.frame.pixel(0, 17), rgb(0x11));
+        assert_eq!(ppu.frame.pixel(0, 18), rgb(0x12));
+        assert_eq!(ppu.frame.pixel(0, 25), rgb(0x12));
+        assert_eq!(ppu.frame.pixel(0, 26), rgb(0x0f));
+
+        // Flipped vertically the halves swap
+        assert_eq!(ppu.frame.pixel(8, 10), rgb(0x12));
+        assert_eq!(ppu.frame.pixel(8, 18), rgb(0x11));
+    }
+
+    #[test]
+    fn test_sprite_size_change_after_evaluation() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        ppu.oam_data = [0xff; 256];
+        ppu.oam_data[0..4].copy_from_slice(&[9, 0x03, FLIP_VERTICAL, 0]);
+        ppu.write_register(&mut mapper, 0x2000, 0b0010_0000);
+        start_rendering(&mut ppu, &mut mapper, 0b0001_0000);
+
+        // Row 11 of the 8x16 sprite is found, then the game switches to 8x8
+        // before the fetches
+        tick_until(&mut ppu, &mut mapper, 20, 200);
+        ppu.write_register(&mut mapper, 0x2000, 0b0000_0000);
+        tick_until(&mut ppu, &mut mapper, 20, 321);
+
+        // Row 11 masked to 3, flipped to 4, of tile 3
+        assert_eq!(ppu.sprite_pattern_row(0), 0x0034);
+    }
+
+    #[test]
+    fn test_8x16_fetches_empty_slots_from_right_table() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        ppu.oam_data = [0xff; 256];
+        ppu.write_register(&mut mapper, 0x2000, 0b0010_0000);
+        ppu.write_register(&mut mapper, 0x2001, 0b0001_0000);
+        tick_until(&mut ppu, &mut mapper, 0, 257);
+
+        assert_eq!(ppu.sprite_pattern_row(0), 0x1FE0);
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_timing() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        load_tiles(&mut ppu, &mut mapper, &TILES);
+        for row in 0..30 {
+            write_bytes(&mut ppu, &mut mapper, 0x2000 + row * 32, &[0x01; 32]);
+        }
+        ppu.oam_data[0..4].copy_from_slice(&[29, 1, 0, 20]);
+        start_rendering(&mut ppu, &mut mapper, 0b0001_1110);
+        tick_until(&mut ppu, &mut mapper, 261, 0);
+
+        // Pixel 20 of line 30 is drawn on dot 21
+        tick_until(&mut ppu, &mut mapper, 30, 21);
+        assert!(!sprite_zero_hit(&ppu));
+        ppu.tick(&mut mapper);
+        assert!(sprite_zero_hit(&ppu));
+
+        tick_until(&mut ppu, &mut mapper, 261, 2);
+        assert!(!sprite_zero_hit(&ppu));
+    }
+
+    #[test]
+    fn test_no_sprite_zero_hit() {
+        let cases: [(&[u8], u8, u8); 4] = [
+            // Over transparent background
+            (&[0x00], 20, 0b0001_1110),
+            // On the last pixel of the line
+            (&[0x01; 32], 255, 0b0001_1110),
+            // In the clipped left column
+            (&[0x01; 32], 0, 0b0001_1000),
+            // With only sprites shown
+            (&[0x01; 32], 20, 0b0001_0110),
+        ];
+
+        for (tiles, x, mask) in cases {
+            let mut mapper = test_mapper(Mirroring::Horizontal);
+            let mut ppu = Ppu::new();
+            load_tiles(&mut ppu, &mut mapper, &TILES);
+            write_bytes(&mut ppu, &mut mapper, 0x2060, tiles);
+            ppu.oam_data[0..4].copy_from_slice(&[29, 1, 0, x]);
+            start_rendering(&mut ppu, &mut mapper, mask);
+            render_frame(&mut ppu, &mut mapper);
+
+            assert!(!sprite_zero_hit(&ppu), "x {} mask {:#010b}", x, mask);
+        }
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_needs_oam_sprite_zero() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        load_tiles(&mut ppu, &mut mapper, &TILES);
+        write_bytes(&mut ppu, &mut mapper, 0x2060, &[0x01; 32]);
+        // Sprite 1 lands in the first slot, but it is not sprite 0
+        ppu.oam_data[4..8].copy_from_slice(&[29, 1, 0, 20]);
+        start_rendering(&mut ppu, &mut mapper, 0b0001_1110);
+        render_frame(&mut ppu, &mut mapper);
+
+        assert!(!sprite_zero_hit(&ppu));
+    }
+
+    #[test]
+    fn test_oamaddr_cleared_during_sprite_fetches() {
+        let mut mapper = test_mapper(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        ppu.write_register(&mut mapper, 0x2003, 0x40);
+        start_rendering(&mut ppu, &mut mapper, 0b0001_0000);
+        tick_until(&mut ppu, &mut mapper, 0, 258);
+
+        assert_eq!(ppu.oam_addr, 0);
+    }
+}
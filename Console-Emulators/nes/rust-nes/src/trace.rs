@@ -0,0 +1,296 @@
+use crate::bus::Bus;
+use crate::cpu::{AddressingMode, CPU};
+use crate::opcodes;
+
+/// Execution Trace
+/// ===============
+/// Formats the instruction at the program counter as one line of a
+/// nestest.log style trace, so runs can be diffed line by line against the
+/// reference logs. Call it from `CPU::run_with_callback`, which runs the
+/// callback just before each instruction executes.
+///
+/// C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+/// |     |         |                               |                         |           |
+/// |     |         |                               |                         |           +- CPU cycles
+/// |     |         |                               |                         +- Scanline, dot
+/// |     |         |                               +- Registers, P with bit 5 set and B clear
+/// |     |         +- Disassembly, with the operand's effective address and value
+/// |     +- Instruction bytes
+/// +- Program counter
+///
+/// Operands are read with `Bus::memory_peek`, so tracing never changes
+/// what the program sees. The PPU column is left out on buses without one.
+pub fn trace<B: Bus>(cpu: &mut CPU<B>) -> String {
+    let pc = cpu.program_counter;
+    let code = cpu.bus.memory_peek(pc);
+    let opcode = opcodes::OPCODES_MAP.get(&code);
+
+    let len = opcode.map_or(1, |opcode| opcode.len);
+    let bytes: Vec<u8> = (0..len as u16)
+        .map(|offset| cpu.bus.memory_peek(pc.wrapping_add(offset)))
+        .collect();
+    let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+
+    // Unofficial opcodes are starred, as nestest.log does
+    let (mnemonic, operand) = match opcode {
+        Some(opcode) if !opcode.official => (
+            format!("*{}", opcode.mnemonic),
+            disassemble(cpu, code, &opcode.mode, &bytes),
+        ),
+        Some(opcode) => (
+            opcode.mnemonic.to_string(),
+            disassemble(cpu, code, &opcode.mode, &bytes),
+        ),
+        None => ("???".to_string(), String::new()),
+    };
+
+    let asm = format!(
+        "{:04X}  {:8} {:>4} {}",
+        pc,
+        hex.join(" "),
+        mnemonic,
+        operand
+    );
+    let mut line = format!(
+        "{:47} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+        asm.trim_end(),
+        cpu.register_a,
+        cpu.register_x,
+        cpu.register_y,
+        cpu.status.pushed(false),
+        cpu.stack_pointer,
+    );
+    if let Some((scanline, dot)) = cpu.bus.ppu_position() {
+        line.push_str(&format!(" PPU:{:>3},{:>3}", scanline, dot));
+    }
+    line.push_str(&format!(" CYC:{}", cpu.cycles));
+    line
+}
+
+/// Zero page pointers wrap round within the zero page
+fn peek_zero_page_u16<B: Bus>(cpu: &mut CPU<B>, address: u8) -> u16 {
+    let lsb = cpu.bus.memory_peek(address as u16) as u16;
+    let msb = cpu.bus.memory_peek(address.wrapping_add(1) as u16) as u16;
+    (msb << 8) | lsb
+}
+
+/// The operand column, following the addressing mode through to the byte
+/// the instruction will use
+fn disassemble<B: Bus>(cpu: &mut CPU<B>, code: u8, mode: &AddressingMode, bytes: &[u8]) -> String {
+    let x = cpu.register_x;
+    let y = cpu.register_y;
+    let zero_page = bytes.get(1).copied().unwrap_or(0);
+    let absolute = match bytes {
+        [_, lsb, msb] => (*msb as u16) << 8 | *lsb as u16,
+        _ => 0,
+    };
+
+    match mode {
+        AddressingMode::Immediate => format!("#${:02X}", zero_page),
+        AddressingMode::ZeroPage => {
+            let value = cpu.bus.memory_peek(zero_page as u16);
+            format!("${:02X} = {:02X}", zero_page, value)
+        }
+        AddressingMode::ZeroPageX | AddressingMode::ZeroPageY => {
+            let (index, name) = if let AddressingMode::ZeroPageX = mode {
+                (x, "X")
+            } else {
+                (y, "Y")
+            };
+            let address = zero_page.wrapping_add(index);
+            let value = cpu.bus.memory_peek(address as u16);
+            format!(
+                "${:02X},{} @ {:02X} = {:02X}",
+                zero_page, name, address, value
+            )
+        }
+
+        // JMP and JSR go to the address rather than reading from it
+        AddressingMode::Absolute if code == 0x4C || code == 0x20 => format!("${:04X}", absolute),
+        AddressingMode::Absolute => {
+            let value = cpu.bus.memory_peek(absolute);
+            format!("${:04X} = {:02X}", absolute, value)
+        }
+        AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => {
+            let (index, name) = if let AddressingMode::AbsoluteX = mode {
+                (x, "X")
+            } else {
+                (y, "Y")
+            };
+            let address = absolute.wrapping_add(index as u16);
+            let value = cpu.bus.memory_peek(address);
+            format!(
+                "${:04X},{} @ {:04X} = {:02X}",
+                absolute, name, address, value
+            )
+        }
+
+        // Same page wrapping bug as `CPU::get_operand_address`
+        AddressingMode::Indirect => {
+            let lsb = cpu.bus.memory_peek(absolute) as u16;
+            let msb = cpu
+                .bus
+                .memory_peek((absolute & 0xFF00) | (absolute.wrapping_add(1) & 0x00FF))
+                as u16;
+            format!("(${:04X}) = {:04X}", absolute, msb << 8 | lsb)
+        }
+        AddressingMode::IndirectX => {
+            let pointer = zero_page.wrapping_add(x);
+            let address = peek_zero_page_u16(cpu, pointer);
+            let value = cpu.bus.memory_peek(address);
+            format!(
+                "(${:02X},X) @ {:02X} = {:04X} = {:02X}",
+                zero_page, pointer, address, value
+            )
+        }
+        AddressingMode::IndirectY => {
+            let base = peek_zero_page_u16(cpu, zero_page);
+            let address = base.wrapping_add(y as u16);
+            let value = cpu.bus.memory_peek(address);
+            format!(
+                "(${:02X}),Y = {:04X} @ {:04X} = {:02X}",
+                zero_page, base, address, value
+            )
+        }
+
+        // Branches show where they go if taken
+        AddressingMode::Relative => {
+            let next = cpu.program_counter.wrapping_add(2);
+            format!("${:04X}", next.wrapping_add(zero_page as i8 as u16))
+        }
+        AddressingMode::Accumulator => "A".to_string(),
+        AddressingMode::NoneAddressing => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::{FlatRam, NesBus};
+    use crate::cartridge::test::test_rom;
+    use crate::mapper;
+    use crate::ppu::registers::StatusRegister;
+
+    fn cpu_at(program: &[u8]) -> CPU<FlatRam> {
+        let mut cpu = CPU::new(FlatRam::new());
+        for (offset, byte) in program.iter().enumerate() {
+            cpu.bus.memory_write(0x0064 + offset as u16, *byte);
+        }
+        cpu.program_counter = 0x64;
+        cpu.stack_pointer = 0xFD;
+        cpu
+    }
+
+    #[test]
+    fn test_format_trace() {
+        let mut cpu = cpu_at(&[0xa2, 0x01, 0xca, 0x88, 0x00]);
+        cpu.register_a = 1;
+        cpu.register_x = 2;
+        cpu.register_y = 3;
+        let mut result = vec![];
+        cpu.run_with_callback(|cpu| {
+            result.push(trace(cpu));
+            if cpu.program_counter == 0x68 {
+                std::ops::ControlFlow::Break(())
+            } else {
+                std::ops::ControlFlow::Continue(())
+            }
+        })
+        .unwrap();
+
+        assert_eq!(
+            "0064  A2 01     LDX #$01                        A:01 X:02 Y:03 P:24 SP:FD CYC:0",
+            result[0]
+        );
+        assert_eq!(
+            "0066  CA        DEX                             A:01 X:01 Y:03 P:24 SP:FD CYC:2",
+            result[1]
+        );
+        assert_eq!(
+            "0067  88        DEY                             A:01 X:00 Y:03 P:26 SP:FD CYC:4",
+            result[2]
+        );
+    }
+
+    #[test]
+    fn test_format_memory_access() {
+        let mut cpu = cpu_at(&[0x11, 0x33]);
+        cpu.bus.memory_write(0x33, 0x00);
+        cpu.bus.memory_write(0x34, 0x04);
+        cpu.bus.memory_write(0x400, 0xAA);
+
+        assert_eq!(
+            "0064  11 33     ORA ($33),Y = 0400 @ 0400 = AA  A:00 X:00 Y:00 P:24 SP:FD CYC:0",
+            trace(&mut cpu)
+        );
+    }
+
+    #[test]
+    fn test_format_operands() {
+        let cases: [(&[u8], &str); 9] = [
+            (&[0x4c, 0xf5, 0xc5], "JMP $C5F5"),
+            (&[0x8d, 0x00, 0x02], "STA $0200 = 5A"),
+            (&[0xb5, 0xff], "LDA $FF,X @ 01 = 00"),
+            (&[0xbd, 0xff, 0x01], "LDA $01FF,X @ 0201 = 00"),
+            (&[0x6c, 0xff, 0x02], "JMP ($02FF) = 5A00"),
+            (&[0xa1, 0x80], "LDA ($80,X) @ 82 = 0200 = 5A"),
+            (&[0xb0, 0xfb], "BCS $0061"),
+            (&[0x4a], "LSR A"),
+            (&[0xa5, 0x10], "LDA $10 = 07"),
+        ];
+
+        for (program, expected) in cases {
+            let mut cpu = cpu_at(program);
+            cpu.register_x = 2;
+            cpu.bus.memory_write(0x10, 0x07);
+            cpu.bus.memory_write(0x82, 0x00);
+            cpu.bus.memory_write(0x83, 0x02);
+            cpu.bus.memory_write(0x200, 0x5A);
+            cpu.bus.memory_write(0x2FF, 0x00);
+
+            let line = trace(&mut cpu);
+            assert_eq!(line[16..47].trim_end(), expected);
+        }
+    }
+
+    #[test]
+    fn test_format_unofficial_opcodes() {
+        let cases: [(&[u8], &str); 4] = [
+            (&[0x04, 0x10], "*NOP $10 = 07"),
+            (&[0xa7, 0x10], "*LAX $10 = 07"),
+            (&[0xeb, 0x01], "*SBC #$01"),
+            (&[0x02], "*JAM"),
+        ];
+
+        for (program, expected) in cases {
+            let mut cpu = cpu_at(program);
+            cpu.bus.memory_write(0x10, 0x07);
+
+            let line = trace(&mut cpu);
+            assert_eq!(line[15..47].trim_end(), expected);
+        }
+    }
+
+    #[test]
+    fn test_format_ppu_position() {
+        let bus = NesBus::new(mapper::from_rom(test_rom(vec![0xea])).unwrap());
+        let mut cpu = CPU::new(bus);
+        cpu.reset();
+
+        assert_eq!(
+            "8000  EA        NOP                             A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7",
+            trace(&mut cpu)
+        );
+    }
+
+    #[test]
+    fn test_trace_has_no_side_effects() {
+        let bus = NesBus::new(mapper::from_rom(test_rom(vec![0xad, 0x02, 0x20])).unwrap());
+        let mut cpu = CPU::new(bus);
+        cpu.reset();
+        cpu.bus.ppu.status.insert(StatusRegister::VBLANK_STARTED);
+
+        assert!(trace(&mut cpu).contains("LDA $2002 = FF"));
+        assert!(cpu.bus.ppu.status.contains(StatusRegister::VBLANK_STARTED));
+    }
+}
@@ -0,0 +1,82 @@
+//! Blargg's Test ROMs
+//! ==================
+//! Runs every accuracy ROM in the table below from tests/roms, using the
+//! $6000 status protocol, and prints a table of the results. Run it with
+//! `cargo test --test blargg -- --ignored`, every one must be present and
+//! pass.
+
+use rust_nes::blargg::{self, Outcome};
+use rust_nes::cartridge::Rom;
+use std::path::PathBuf;
+
+/// Two minutes of emulated time, the slowest ROMs take about one
+const MAX_FRAMES: u64 = 60 * 120;
+
+/// instr_timing, cpu_interrupts_v2 and ppu_vbl_nmi are left out until the
+/// CPU ticks each bus access as it happens, they time individual accesses
+/// and the whole instruction's cycles are spent before any of them
+const ROMS: &[&str] = &[
+    "instr_test-v5/official_only.nes",
+    "instr_test-v5/all_instrs.nes",
+    "instr_misc/instr_misc.nes",
+    "cpu_reset/registers.nes",
+    "cpu_reset/ram_after_reset.nes",
+    "ppu_open_bus/ppu_open_bus.nes",
+    "oam_read/oam_read.nes",
+    "oam_stress/oam_stress.nes",
+    "apu_test/apu_test.nes",
+];
+
+fn rom_path(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "roms", name]
+        .iter()
+        .collect()
+}
+
+#[test]
+#[ignore = "needs blargg's test ROMs in tests/roms"]
+fn test_blargg_roms() {
+    let missing: Vec<&str> = ROMS
+        .iter()
+        .copied()
+        .filter(|name| !rom_path(name).exists())
+        .collect();
+    assert!(
+        missing.is_empty(),
+        "not found in tests/roms: {}",
+        missing.join(", ")
+    );
+
+    let mut results = vec![];
+    for name in ROMS {
+        let rom =
+            Rom::from_file(rom_path(name)).unwrap_or_else(|error| panic!("{}: {}", name, error));
+        let report = blargg::run(rom, MAX_FRAMES).unwrap();
+        results.push((name, report));
+    }
+
+    let mut table = String::new();
+    for (name, report) in &results {
+        let outcome = match report.outcome {
+            Outcome::Passed => "passed".to_string(),
+            Outcome::Failed(code) => format!("failed #{}", code),
+            Outcome::TimedOut => "timed out".to_string(),
+            Outcome::Halted(error) => error.to_string(),
+        };
+        let summary = report.message.lines().last().unwrap_or("");
+        table.push_str(&format!("{:40} {:10} {}\n", name, outcome, summary));
+    }
+    eprint!("{}", table);
+
+    let failed = results
+        .iter()
+        .filter(|(_, report)| !report.passed())
+        .count();
+    assert_eq!(
+        failed,
+        0,
+        "{} of {} test ROMs failed",
+        failed,
+        results.len()
+    );
+}
@@ -0,0 +1,159 @@
+//! Klaus Dormann's 6502 Tests
+//! ==========================
+//! Runs 6502_functional_test.bin and 6502_interrupt_test.bin from
+//! https://github.com/Klaus2m5/6502_65C02_functional_tests on a flat 64KB
+//! bus. Both are full memory images started at $0400. Every check in them
+//! ends in a trap, a jump or branch to itself, and only the trap at the
+//! documented success address means everything passed.
+//!
+//! The 2A03 has no decimal mode and neither does this emulator, so the
+//! prebuilt functional test fails in its decimal checks. Assemble it with
+//! `disable_decimal = 1` and set DORMANN_FUNCTIONAL_SUCCESS to the address
+//! of the success trap in hex, from the listing. The interrupt test uses
+//! the prebuilt binary's success address unless DORMANN_INTERRUPT_SUCCESS
+//! is set.
+//!
+//! Put the binaries in tests/roms and run
+//! `cargo test --test dormann -- --ignored`.
+
+use rust_nes::bus::{Bus, FlatRam};
+use rust_nes::cpu::{CpuError, CPU};
+use rust_nes::interrupt::IrqSource;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const START: u16 = 0x0400;
+const INTERRUPT_SUCCESS: u16 = 0x06F5;
+
+/// Far more than either test needs, in case it loops without trapping
+const MAX_INSTRUCTIONS: u64 = 200_000_000;
+
+fn rom_path(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "roms", name]
+        .iter()
+        .collect()
+}
+
+fn success_address(variable: &str, default: Option<u16>) -> u16 {
+    match env::var(variable) {
+        Ok(value) => u16::from_str_radix(value.trim_start_matches('$'), 16)
+            .unwrap_or_else(|_| panic!("{} must be a hex address", variable)),
+        Err(_) => default.unwrap_or_else(|| panic!("{} is not set", variable)),
+    }
+}
+
+fn load_image(name: &str) -> Vec<u8> {
+    let path = rom_path(name);
+    let image = fs::read(&path).unwrap_or_else(|_| panic!("{} not found", path.display()));
+    assert_eq!(image.len(), 0x10000, "{} is not a 64KB image", name);
+    image
+}
+
+/// Runs until an instruction jumps or branches to itself, or a JAM halts
+/// the CPU, and returns where it stopped
+fn run_to_trap<B: Bus>(cpu: &mut CPU<B>) -> u16 {
+    for _ in 0..MAX_INSTRUCTIONS {
+        match cpu.step() {
+            Ok(Some(step)) if step.pc == cpu.program_counter => return step.pc,
+            Ok(Some(_)) => {}
+            Ok(None) => unreachable!("BRK only halts in test mode"),
+            Err(CpuError::Jam { pc }) => return pc,
+            Err(error) => panic!("{}", error),
+        }
+    }
+    panic!(
+        "no trap after {} instructions, PC at ${:04X}",
+        MAX_INSTRUCTIONS, cpu.program_counter
+    );
+}
+
+#[test]
+#[ignore = "needs 6502_functional_test.bin in tests/roms and DORMANN_FUNCTIONAL_SUCCESS"]
+fn test_functional() {
+    let image = load_image("6502_functional_test.bin");
+    let success = success_address("DORMANN_FUNCTIONAL_SUCCESS", None);
+
+    let mut cpu = CPU::new(FlatRam::new());
+    for (address, &data) in image.iter().enumerate() {
+        cpu.bus.memory_write(address as u16, data);
+    }
+    cpu.program_counter = START;
+
+    let trap = run_to_trap(&mut cpu);
+    assert_eq!(
+        trap,
+        success,
+        "trapped at ${:04X}, test number {:02X}",
+        trap,
+        cpu.bus.memory_read(0x0200)
+    );
+}
+
+/// Feedback Port ($BFFC)
+/// =====================
+/// The interrupt test raises its own interrupts by writing to a port wired
+/// back to the CPU's inputs. Bit 0 holds IRQ asserted and a rising edge on
+/// bit 1 triggers an NMI.
+const FEEDBACK_PORT: u16 = 0xBFFC;
+const FEEDBACK_IRQ: u8 = 0b01;
+const FEEDBACK_NMI: u8 = 0b10;
+
+struct FeedbackBus {
+    ram: FlatRam,
+    port: u8,
+}
+
+impl Bus for FeedbackBus {
+    fn memory_read(&mut self, address: u16) -> u8 {
+        match address {
+            FEEDBACK_PORT => self.port,
+            _ => self.ram.memory_read(address),
+        }
+    }
+
+    fn memory_write(&mut self, address: u16, data: u8) {
+        match address {
+            FEEDBACK_PORT => self.port = data,
+            _ => self.ram.memory_write(address, data),
+        }
+    }
+
+    fn nmi_line(&self) -> bool {
+        self.port & FEEDBACK_NMI != 0
+    }
+
+    fn irq_line(&self) -> IrqSource {
+        if self.port & FEEDBACK_IRQ != 0 {
+            IrqSource::EXTERNAL
+        } else {
+            IrqSource::empty()
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs 6502_interrupt_test.bin in tests/roms"]
+fn test_interrupts() {
+    let image = load_image("6502_interrupt_test.bin");
+    let success = success_address("DORMANN_INTERRUPT_SUCCESS", Some(INTERRUPT_SUCCESS));
+
+    let mut cpu = CPU::new(FeedbackBus {
+        ram: FlatRam::new(),
+        port: 0,
+    });
+    for (address, &data) in image.iter().enumerate() {
+        cpu.bus.memory_write(address as u16, data);
+    }
+    cpu.bus.port = 0;
+    cpu.program_counter = START;
+
+    let trap = run_to_trap(&mut cpu);
+    assert_eq!(
+        trap,
+        success,
+        "trapped at ${:04X}, test number {:02X}",
+        trap,
+        cpu.bus.memory_read(0x0200)
+    );
+}
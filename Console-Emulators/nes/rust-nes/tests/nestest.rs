@@ -0,0 +1,82 @@
+//! nestest
+//! =======
+//! Runs kevtris' nestest.nes in automation mode, starting at $C000 rather
+//! than the reset vector, and compares a trace of every instruction against
+//! the golden nestest.log. Both go in tests/roms, run it with
+//! `cargo test --test nestest -- --ignored`.
+
+use rust_nes::bus::{Bus, NesBus};
+use rust_nes::cartridge::Rom;
+use rust_nes::cpu::CPU;
+use rust_nes::mapper;
+use rust_nes::trace::trace;
+use std::fs;
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+
+/// Where automation mode starts, skipping the menu
+const AUTOMATION_START: u16 = 0xC000;
+
+/// Lines of the trace shown before the first divergent line
+const CONTEXT_LINES: usize = 5;
+
+fn rom_path(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "roms", name]
+        .iter()
+        .collect()
+}
+
+#[test]
+#[ignore = "needs nestest.nes and nestest.log in tests/roms"]
+fn test_nestest() {
+    let (rom_file, log_file) = (rom_path("nestest.nes"), rom_path("nestest.log"));
+    assert!(
+        rom_file.exists() && log_file.exists(),
+        "{} and {} not found",
+        rom_file.display(),
+        log_file.display()
+    );
+
+    let golden = fs::read_to_string(&log_file).unwrap();
+    let golden: Vec<&str> = golden
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    let rom = Rom::from_file(&rom_file).unwrap();
+    let mut cpu = CPU::new(NesBus::new(mapper::from_rom(rom).unwrap()));
+    cpu.reset();
+    cpu.program_counter = AUTOMATION_START;
+
+    let mut actual: Vec<String> = vec![];
+    let result = cpu.run_with_callback(|cpu| {
+        let line = trace(cpu);
+        let matches = line == golden[actual.len()];
+        actual.push(line);
+
+        if matches && actual.len() < golden.len() {
+            ControlFlow::Continue(())
+        } else {
+            ControlFlow::Break(())
+        }
+    });
+
+    let last = actual.len() - 1;
+    if actual[last] != golden[last] {
+        let mut report = format!("trace diverges at line {}\n", last + 1);
+        for line in &actual[last.saturating_sub(CONTEXT_LINES)..last] {
+            report.push_str(&format!("  {}\n", line));
+        }
+        report.push_str(&format!("- {}\n+ {}", golden[last], actual[last]));
+        panic!("{}", report);
+    }
+    if let Err(error) = result {
+        panic!("{} after line {}", error, actual.len());
+    }
+
+    // The official and unofficial opcode tests leave their error codes in
+    // $02 and $03, zero when everything passed
+    assert_eq!(cpu.bus.memory_read(0x02), 0x00, "official opcode error");
+    assert_eq!(cpu.bus.memory_read(0x03), 0x00, "unofficial opcode error");
+}
@@ -0,0 +1,228 @@
+//! SingleStepTests
+//! ===============
+//! Runs the 65x02 SingleStepTests, which describe each opcode with 10,000
+//! cases of initial and final CPU and RAM state along with the bus traffic
+//! of every cycle. Use the nes6502 set, which leaves out decimal mode like
+//! the 2A03, and put its JSON files (00.json to ff.json) in
+//! tests/single_step. Run it with
+//! `cargo test --test single_step -- --ignored`.
+//!
+//! Registers, RAM and the cycle count are always checked. Set
+//! SINGLE_STEP_BUS=1 to compare the address, data and direction of every
+//! bus access as well. The CPU doesn't make the dummy reads and writes of
+//! the real chip yet, such as implied instructions reading the next byte,
+//! read-modify-write instructions writing twice and indexed modes reading
+//! the wrong page, so expect most opcodes to fail in this mode.
+
+use rust_nes::bus::Bus;
+use rust_nes::cpu::{CpuFlags, CPU};
+use rust_nes::opcodes::OPCODES_MAP;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+/// Bits 4 and 5 of P only exist when it is pushed, so they are not compared
+const STATUS_MASK: u8 = 0b1100_1111;
+
+#[derive(Deserialize)]
+struct Case {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    cycles: Vec<(u16, u8, String)>,
+}
+
+#[derive(Deserialize)]
+struct State {
+    pc: u16,
+    s: u8,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+/// 64KB of RAM which records every access made through it
+struct TestBus {
+    memory: Vec<u8>,
+    accesses: Vec<(u16, u8, String)>,
+}
+
+impl TestBus {
+    fn new() -> Self {
+        TestBus {
+            memory: vec![0; 0x10000],
+            accesses: vec![],
+        }
+    }
+}
+
+impl Bus for TestBus {
+    fn memory_read(&mut self, address: u16) -> u8 {
+        let data = self.memory[address as usize];
+        self.accesses.push((address, data, "read".to_string()));
+        data
+    }
+
+    fn memory_write(&mut self, address: u16, data: u8) {
+        self.memory[address as usize] = data;
+        self.accesses.push((address, data, "write".to_string()));
+    }
+
+    fn memory_peek(&mut self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+}
+
+fn test_dir() -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "single_step"]
+        .iter()
+        .collect()
+}
+
+fn load(cpu: &mut CPU<TestBus>, state: &State) {
+    cpu.program_counter = state.pc;
+    cpu.stack_pointer = state.s;
+    cpu.register_a = state.a;
+    cpu.register_x = state.x;
+    cpu.register_y = state.y;
+    cpu.status = CpuFlags::from_bits_truncate(state.p & STATUS_MASK);
+    for &(address, data) in &state.ram {
+        cpu.bus.memory[address as usize] = data;
+    }
+    cpu.bus.accesses.clear();
+}
+
+/// Runs a single instruction and describes the first difference from the
+/// expected state
+fn run_case(cpu: &mut CPU<TestBus>, case: &Case, check_bus: bool) -> Result<(), String> {
+    load(cpu, &case.initial);
+
+    let step = cpu
+        .step()
+        .map_err(|error| format!("{}: {}", case.name, error))?
+        .ok_or_else(|| format!("{}: halted", case.name))?;
+
+    let expected = &case.expected;
+    let registers = [
+        ("PC", cpu.program_counter, expected.pc),
+        ("S", cpu.stack_pointer as u16, expected.s as u16),
+        ("A", cpu.register_a as u16, expected.a as u16),
+        ("X", cpu.register_x as u16, expected.x as u16),
+        ("Y", cpu.register_y as u16, expected.y as u16),
+        (
+            "P",
+            (cpu.status.bits() & STATUS_MASK) as u16,
+            (expected.p & STATUS_MASK) as u16,
+        ),
+    ];
+    for (name, actual, expected) in registers {
+        if actual != expected {
+            return Err(format!(
+                "{}: {} is {:02X}, expected {:02X}",
+                case.name, name, actual, expected
+            ));
+        }
+    }
+
+    for &(address, data) in &expected.ram {
+        let actual = cpu.bus.memory[address as usize];
+        if actual != data {
+            return Err(format!(
+                "{}: ${:04X} is {:02X}, expected {:02X}",
+                case.name, address, actual, data
+            ));
+        }
+    }
+
+    if step.cycles != case.cycles.len() as u64 {
+        return Err(format!(
+            "{}: took {} cycles, expected {}",
+            case.name,
+            step.cycles,
+            case.cycles.len()
+        ));
+    }
+
+    if check_bus && cpu.bus.accesses != case.cycles {
+        return Err(format!(
+            "{}: bus traffic was {:?}, expected {:?}",
+            case.name, cpu.bus.accesses, case.cycles
+        ));
+    }
+    Ok(())
+}
+
+/// Runs every case for one opcode, returning how many failed and the first
+/// failure
+fn run_file(path: &Path, check_bus: bool) -> Option<(usize, usize, String)> {
+    let cases: Vec<Case> = serde_json::from_str(&fs::read_to_string(path).unwrap())
+        .unwrap_or_else(|error| panic!("{}: {}", path.display(), error));
+
+    let mut cpu = CPU::new(TestBus::new());
+    let mut failed = 0;
+    let mut first_failure = None;
+    for case in &cases {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| run_case(&mut cpu, case, check_bus)))
+            .unwrap_or_else(|_| Err(format!("{}: panicked", case.name)));
+
+        if let Err(message) = result {
+            failed += 1;
+            first_failure.get_or_insert(message);
+            cpu = CPU::new(TestBus::new());
+        }
+
+        // Clear what the case touched rather than allocating a new bus
+        for &(address, _) in case.initial.ram.iter().chain(&case.expected.ram) {
+            cpu.bus.memory[address as usize] = 0;
+        }
+    }
+    first_failure.map(|message| (failed, cases.len(), message))
+}
+
+#[test]
+#[ignore = "needs the SingleStepTests JSON files in tests/single_step"]
+fn test_single_step() {
+    let dir = test_dir();
+    assert!(dir.is_dir(), "{} not found", dir.display());
+    let check_bus = std::env::var("SINGLE_STEP_BUS").is_ok_and(|value| value == "1");
+
+    let mut failures = BTreeMap::new();
+    let mut skipped = vec![];
+    let mut ran = 0;
+    for code in 0..=0xFFu8 {
+        let path = dir.join(format!("{:02x}.json", code));
+        if !path.exists() {
+            continue;
+        }
+        // JAM never finishes, so there is no final state to compare
+        if OPCODES_MAP[&code].mnemonic == "JAM" {
+            skipped.push(format!("{:02X}", code));
+            continue;
+        }
+        if let Some(failure) = run_file(&path, check_bus) {
+            failures.insert(code, failure);
+        }
+        ran += 1;
+    }
+    assert!(ran > 0, "no opcode files found in {}", dir.display());
+
+    if !skipped.is_empty() {
+        eprintln!("skipped JAM opcodes: {}", skipped.join(" "));
+    }
+    if !failures.is_empty() {
+        let mut report = format!("{} opcodes failed\n", failures.len());
+        for (code, (failed, total, message)) in failures {
+            let mnemonic = OPCODES_MAP[&code].mnemonic;
+            report.push_str(&format!(
+                "{:02X} {}: {}/{} failed, first {}\n",
+                code, mnemonic, failed, total, message
+            ));
+        }
+        panic!("{}", report);
+    }
+}